@@ -0,0 +1,375 @@
+pub mod server_config {
+    use crate::mackley::application::{DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_MAX_REQUEST_SIZE};
+    use lib::queue::queue::{validate_configuration, QueueConfiguration};
+
+    #[test]
+    fn file_and_overrides() {
+        let config = ServerConfig::from_toml(
+            r#"
+            bind_address = "0.0.0.0"
+            page_dir = "/var/lib/mackley"
+            topic_config = "/etc/mackley/topics.json"
+            max_message_size = 1048576
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.bind_address, "0.0.0.0");
+        assert_eq!(config.port, 8787);
+        assert_eq!(config.page_dir.as_deref(), Some("/var/lib/mackley"));
+        assert_eq!(config.queue_config, "./queue_config.json");
+        assert_eq!(config.topic_config, "/etc/mackley/topics.json");
+        assert_eq!(config.exchange_config, "./exchange_config.json");
+        assert_eq!(config.max_message_size, 1048576);
+
+        let args: Vec<String> = [
+            "--port",
+            "9000",
+            "--log-level",
+            "debug",
+            "--log-format",
+            "json",
+            "--auth-file",
+            "auth",
+            "--max-request-size",
+            "1024",
+            "--exchange-config",
+            "exchanges.json",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let overridden = config.with_args(&args).unwrap();
+        assert_eq!(overridden.port, 9000);
+        assert_eq!(overridden.log_level, LogLevel::Debug);
+        assert_eq!(overridden.log_format, LogFormat::Json);
+        assert_eq!(overridden.auth_file.as_deref(), Some("auth"));
+        assert_eq!(overridden.max_request_size, 1024);
+        assert_eq!(overridden.exchange_config, "exchanges.json");
+        assert_eq!(overridden.bind_address, "0.0.0.0");
+
+        assert!(ServerConfig::from_toml("port = \"eighty\"").is_err());
+        assert!(ServerConfig::from_toml("prot = 80").is_err());
+        assert!(ServerConfig::default()
+            .with_args(&["--port".to_string()])
+            .is_err());
+        assert!(ServerConfig::default()
+            .with_args(&["--colour".to_string()])
+            .is_err());
+    }
+
+    #[test]
+    fn auto_create() {
+        let config = ServerConfig::from_toml(
+            r#"
+            [auto_create]
+            patterns = ["dev-*", "scratch"]
+            users = ["alice"]
+
+            [auto_create.template]
+            pending_mode = "Read"
+            visibility_timeout = 5000
+            "#,
+        )
+        .unwrap();
+        let auto_create = config.auto_create.unwrap();
+        assert!(auto_create.allows("dev-orders", "alice"));
+        assert!(auto_create.allows("scratch", "alice"));
+        assert!(!auto_create.allows("scratch", "bob"));
+        assert!(!auto_create.allows("prod-orders", "alice"));
+        assert!(!auto_create.allows("", "alice"));
+        assert_eq!(auto_create.configuration("dev-a").name, "dev-a");
+        assert_eq!(auto_create.configuration("dev-a").visibility_timeout, 5000);
+
+        assert!(name_matches("*", "anything"));
+        assert!(name_matches("a*b*c", "aXXbYc"));
+        assert!(!name_matches("a*b*c", "aXXbY"));
+        assert!(name_matches("q?", "q1"));
+
+        let invalid = ServerConfig::from_toml(
+            r#"
+            [auto_create]
+            patterns = ["*"]
+            template = { pending_mode = "Read", buffer_count = 0 }
+            "#,
+        );
+        assert_eq!(
+            invalid.unwrap_err(),
+            "auto_create.template.buffer_count: must be at least 1"
+        );
+        assert!(ServerConfig::from_toml("[auto_create]\npatterns = []").is_err());
+    }
+
+    // * matches any run of characters, including none, and ? any one character
+    pub fn name_matches(pattern: &str, name: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+
+        // the last * seen and where in the name it started matching, to go back to when the rest fails
+        let (mut p, mut n) = (0, 0);
+        let mut star: Option<(usize, usize)> = None;
+        while n < name.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    star = Some((p, n));
+                    p += 1;
+                }
+                Some(c) if *c == '?' || *c == name[n] => {
+                    p += 1;
+                    n += 1;
+                }
+                _ => match star {
+                    Some((star_p, star_n)) => {
+                        p = star_p + 1;
+                        n = star_n + 1;
+                        star = Some((star_p, star_n + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|c| *c == '*')
+    }
+
+    // queues that are created the first time they are sent to or polled instead of being QUEUE_NOT_FOUND
+    #[derive(serde::Deserialize, Debug, Clone)]
+    #[serde(deny_unknown_fields)]
+    pub struct AutoCreate {
+        // names that can be created, eg dev-*. See name_matches
+        pub patterns: Vec<String>,
+
+        // user names auto create is meant for, everyone when empty. Advisory only, see allows
+        #[serde(default)]
+        pub users: Vec<String>,
+
+        // settings of created queues, the name is filled in. Read mode with the default settings when left out
+        #[serde(default)]
+        pub template: Option<QueueConfiguration>,
+    }
+
+    impl AutoCreate {
+        // advisory, not a security boundary. username is whatever the client put in the request and the server
+        // does not authenticate it, so users keeps well behaved clients from creating queues by mistake and
+        // nothing more
+        pub fn allows(&self, queue: &str, username: &str) -> bool {
+            (self.users.is_empty() || self.users.iter().any(|user| user == username))
+                && !queue.is_empty()
+                && self
+                    .patterns
+                    .iter()
+                    .any(|pattern| name_matches(pattern, queue))
+        }
+
+        pub fn configuration(&self, queue: &str) -> QueueConfiguration {
+            let mut config = self
+                .template
+                .clone()
+                .unwrap_or_else(|| QueueConfiguration::new("".to_string()));
+            config.name = queue.to_string();
+            config
+        }
+    }
+
+    // read when no --config is given, missing is the same as empty
+    pub const DEFAULT_CONFIG_FILE: &str = "./mackley.toml";
+
+    #[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum LogLevel {
+        Error,
+
+        // also requests that were refused and problems the server works around
+        Warn,
+
+        // also startup, admin commands and queue changes
+        Info,
+
+        // also every request with its command, queue, peer and latency
+        Debug,
+
+        // also the raw bytes of every request, with the password redacted
+        Trace,
+    }
+
+    impl LogLevel {
+        pub fn from_name(name: &str) -> Option<LogLevel> {
+            match name {
+                "error" => Some(LogLevel::Error),
+                "warn" => Some(LogLevel::Warn),
+                "info" => Some(LogLevel::Info),
+                "debug" => Some(LogLevel::Debug),
+                "trace" => Some(LogLevel::Trace),
+                _ => None,
+            }
+        }
+
+        pub fn level(&self) -> tracing::Level {
+            match self {
+                LogLevel::Error => tracing::Level::ERROR,
+                LogLevel::Warn => tracing::Level::WARN,
+                LogLevel::Info => tracing::Level::INFO,
+                LogLevel::Debug => tracing::Level::DEBUG,
+                LogLevel::Trace => tracing::Level::TRACE,
+            }
+        }
+    }
+
+    #[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum LogFormat {
+        // one readable line per event
+        Text,
+
+        // one json object per event, for log collectors
+        Json,
+    }
+
+    // everything the server needs before it can listen. Each setting comes from the command line if it is
+    // given there, then the config file, then the default
+    #[derive(serde::Deserialize, Debug, Clone)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct ServerConfig {
+        pub bind_address: String,
+        pub port: u16,
+
+        // no page dir means nothing is kept on disk between sessions
+        pub page_dir: Option<String>,
+
+        // there is no authentication yet, the server refuses to start with an auth file rather than ignore it
+        pub auth_file: Option<String>,
+
+        pub queue_config: String,
+        pub topic_config: String,
+        pub exchange_config: String,
+
+        // largest message body accepted, queues can set a lower one
+        pub max_message_size: usize,
+
+        // largest request read from a client, IMPORT data included. Larger requests are refused unread
+        pub max_request_size: usize,
+
+        pub log_level: LogLevel,
+        pub log_format: LogFormat,
+
+        // no auto create table means unknown queues are QUEUE_NOT_FOUND
+        pub auto_create: Option<AutoCreate>,
+    }
+
+    impl Default for ServerConfig {
+        fn default() -> ServerConfig {
+            ServerConfig {
+                bind_address: "127.0.0.1".to_string(),
+                port: 8787,
+                page_dir: None,
+                auth_file: None,
+                queue_config: "./queue_config.json".to_string(),
+                topic_config: "./topic_config.json".to_string(),
+                exchange_config: "./exchange_config.json".to_string(),
+                max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+                max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+                log_level: LogLevel::Info,
+                log_format: LogFormat::Text,
+                auto_create: None,
+            }
+        }
+    }
+
+    pub const USAGE: &str = "Usage: mackley [--config <file>] [--bind <address>] [--port <port>]
+               [--page-dir <dir>] [--auth-file <file>] [--queue-config <file>]
+               [--topic-config <file>] [--exchange-config <file>]
+               [--max-message-size <bytes>] [--max-request-size <bytes>]
+               [--log-level error|warn|info|debug|trace] [--log-format text|json]";
+
+    impl ServerConfig {
+        pub fn from_toml(contents: &str) -> Result<ServerConfig, String> {
+            let config: ServerConfig = toml::from_str(contents).map_err(|e| e.to_string())?;
+
+            if let Some(auto_create) = config.auto_create.as_ref() {
+                if auto_create.patterns.is_empty() {
+                    return Err(
+                        "auto_create.patterns: list at least one queue name pattern".to_string()
+                    );
+                }
+                if let Some(e) = validate_configuration(&auto_create.configuration("template")) {
+                    return Err(format!("auto_create.template.{}", e));
+                }
+            }
+            Ok(config)
+        }
+
+        // the file named by --config, or ./mackley.toml if it exists, with the other flags applied on top
+        pub fn load(args: &[String]) -> Result<ServerConfig, String> {
+            let path = args
+                .iter()
+                .position(|arg| arg == "--config")
+                .map(|index| args.get(index + 1).ok_or("--config needs a value"))
+                .transpose()?;
+
+            let config = match path {
+                Some(path) => {
+                    let contents = std::fs::read_to_string(path)
+                        .map_err(|e| format!("could not read {}: {}", path, e))?;
+                    ServerConfig::from_toml(&contents).map_err(|e| format!("{}: {}", path, e))?
+                }
+                None => match std::fs::read_to_string(DEFAULT_CONFIG_FILE) {
+                    Ok(contents) => ServerConfig::from_toml(&contents)
+                        .map_err(|e| format!("{}: {}", DEFAULT_CONFIG_FILE, e))?,
+                    Err(_) => ServerConfig::default(),
+                },
+            };
+
+            config.with_args(args)
+        }
+
+        // args without the program name. --config is skipped, it has been read already
+        pub fn with_args(mut self, args: &[String]) -> Result<ServerConfig, String> {
+            let mut args = args.iter();
+            while let Some(flag) = args.next() {
+                let mut value = || {
+                    args.next()
+                        .cloned()
+                        .ok_or_else(|| format!("{} needs a value", flag))
+                };
+                let invalid = |value: &str| format!("invalid value {} for {}", value, flag);
+
+                match flag.as_str() {
+                    "--config" => {
+                        value()?;
+                    }
+                    "--bind" => self.bind_address = value()?,
+                    "--port" => {
+                        let port = value()?;
+                        self.port = port.parse().map_err(|_| invalid(&port))?;
+                    }
+                    "--page-dir" => self.page_dir = Some(value()?),
+                    "--auth-file" => self.auth_file = Some(value()?),
+                    "--queue-config" => self.queue_config = value()?,
+                    "--topic-config" => self.topic_config = value()?,
+                    "--exchange-config" => self.exchange_config = value()?,
+                    "--max-message-size" => {
+                        let size = value()?;
+                        self.max_message_size = size.parse().map_err(|_| invalid(&size))?;
+                    }
+                    "--max-request-size" => {
+                        let size = value()?;
+                        self.max_request_size = size.parse().map_err(|_| invalid(&size))?;
+                    }
+                    "--log-level" => {
+                        let level = value()?;
+                        self.log_level =
+                            LogLevel::from_name(&level).ok_or_else(|| invalid(&level))?;
+                    }
+                    "--log-format" => {
+                        let format = value()?;
+                        self.log_format = match format.as_str() {
+                            "text" => LogFormat::Text,
+                            "json" => LogFormat::Json,
+                            _ => return Err(invalid(&format)),
+                        };
+                    }
+                    _ => return Err(format!("unknown argument {}", flag)),
+                }
+            }
+            Ok(self)
+        }
+    }
+}
@@ -0,0 +1,418 @@
+pub mod admin {
+    use crate::encoding::encoding::MmqpEncoding;
+    use crate::exchange::exchange::ExchangeType;
+    use crate::export::export::ExportFormat;
+    use crate::queue::queue::MessageSelection;
+
+    #[test]
+    fn admin_round_trip() {
+        let commands = [
+            MmqpAdminCommand::Purge("queue1".to_string()),
+            MmqpAdminCommand::CreateTopic("orders".to_string()),
+            MmqpAdminCommand::DeleteTopic("orders".to_string()),
+            MmqpAdminCommand::Subscribe("orders".to_string(), "queue1".to_string(), None),
+            MmqpAdminCommand::Subscribe(
+                "orders".to_string(),
+                "queue1".to_string(),
+                Some(r#"region = "eu""#.to_string()),
+            ),
+            MmqpAdminCommand::Unsubscribe("orders".to_string(), "queue1".to_string()),
+            MmqpAdminCommand::CreateExchange("logs".to_string(), ExchangeType::Topic),
+            MmqpAdminCommand::DeleteExchange("logs".to_string()),
+            MmqpAdminCommand::Bind(
+                "logs".to_string(),
+                "queue1".to_string(),
+                "error.#".to_string(),
+            ),
+            MmqpAdminCommand::Unbind(
+                "logs".to_string(),
+                "queue1".to_string(),
+                "error.#".to_string(),
+            ),
+            MmqpAdminCommand::DeleteConsumerGroup("queue1".to_string(), "billing".to_string()),
+            MmqpAdminCommand::Move(
+                "dlq".to_string(),
+                "queue1".to_string(),
+                MessageSelection::All,
+                true,
+            ),
+            MmqpAdminCommand::Copy(
+                "queue1".to_string(),
+                "queue2".to_string(),
+                MessageSelection::Filter(r#"region = "eu""#.to_string()),
+                false,
+            ),
+            MmqpAdminCommand::Move(
+                "queue1".to_string(),
+                "queue2".to_string(),
+                MessageSelection::Ids(vec!["aWQx".to_string(), "aWQy".to_string()]),
+                false,
+            ),
+            MmqpAdminCommand::Export("queue1".to_string(), ExportFormat::JsonLines),
+            MmqpAdminCommand::Import(
+                "".to_string(),
+                ExportFormat::Native,
+                b"MMQP-EXPORT|\0|0".to_vec(),
+            ),
+            MmqpAdminCommand::Reload,
+        ];
+
+        let mut truncated = MmqpAdminCommand::Import(
+            "queue1".to_string(),
+            ExportFormat::JsonLines,
+            vec![b'{'; 600],
+        )
+        .serialise("user", "pass")
+        .to_vec();
+        truncated.truncate(512);
+        match crate::tcp_parse::tcp_parse::parse_tcp_request(truncated) {
+            crate::tcp_parse::tcp_parse::MmqpTcpFormat::Admin(parsed) => {
+                assert_eq!(parsed, MmqpAdminCommand::Truncated("IMPORT".to_string()))
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+
+        for command in commands {
+            let binary = command.serialise("user", "pass");
+
+            match crate::tcp_parse::tcp_parse::parse_tcp_request(binary.to_vec()) {
+                crate::tcp_parse::tcp_parse::MmqpTcpFormat::Admin(parsed) => {
+                    assert_eq!(parsed, command)
+                }
+                res => panic!("unexpected {:?}", res),
+            }
+
+            // cut short anywhere after the command name, every command is Truncated rather than a panic
+            // MMQP|0.1|A|%user:%pass|%command
+            let name_end = 11 + 6 + 1 + 6 + 1 + 2 + command.name().len();
+            for len in name_end..binary.len() - 1 {
+                match crate::tcp_parse::tcp_parse::parse_tcp_request(binary[..len].to_vec()) {
+                    crate::tcp_parse::tcp_parse::MmqpTcpFormat::Admin(parsed) => {
+                        assert_eq!(parsed, MmqpAdminCommand::Truncated(command.name()))
+                    }
+                    res => panic!("unexpected {:?}", res),
+                }
+            }
+        }
+    }
+
+    // operator commands, sent as
+    // MMQP|0.1|A|%username:%password|%command|...command dependant
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum MmqpAdminCommand {
+        // queue name. removes every message from the queue
+        Purge(String),
+
+        // topic name. creating a topic that exists already does nothing
+        CreateTopic(String),
+
+        // topic name. the subscribed queues and their messages are left alone
+        DeleteTopic(String),
+
+        // topic name, queue name, filter expression. Subscribing again replaces the filter
+        Subscribe(String, String, Option<String>),
+
+        // topic name, queue name
+        Unsubscribe(String, String),
+
+        // exchange name, type. creating an exchange that exists already does nothing
+        CreateExchange(String, ExchangeType),
+
+        // exchange name. the bound queues and their messages are left alone
+        DeleteExchange(String),
+
+        // exchange name, queue name, pattern
+        Bind(String, String, String),
+
+        // exchange name, queue name, pattern
+        Unbind(String, String, String),
+
+        // queue name, consumer group. the messages it was the last to read are released
+        DeleteConsumerGroup(String, String),
+
+        // source queue, destination queue, which messages, keep ids. Waiting messages are moved to the end of
+        // the destination. With keep ids they keep their id, timestamps and receive count, otherwise they are
+        // given new ones as if they were sent again
+        Move(String, String, MessageSelection, bool),
+
+        // as Move, but the messages stay in the source queue as well
+        Copy(String, String, MessageSelection, bool),
+
+        // queue name, format. responds with the whole queue, see export
+        Export(String, ExportFormat),
+
+        // queue name, format, export file contents. The messages are added to the queue, which is created from
+        // the exported configuration if it does not exist. An empty queue name uses the exported one
+        Import(String, ExportFormat, Vec<u8>),
+
+        // read the queue config file again and apply the changes, as SIGHUP does. New queues are created,
+        // existing ones take their new settings and queues that are no longer listed are retired
+        Reload,
+
+        // EXPORT or IMPORT with a format that is not jsonl or native
+        UnknownExportFormat(String),
+
+        // command name, for a request that ends before its last argument does
+        Truncated(String),
+
+        // CREATE_EXCHANGE with a type that is not direct or topic
+        UnknownExchangeType(String),
+
+        // command name that was not recognised
+        Unknown(String),
+    }
+
+    impl MmqpAdminCommand {
+        pub fn name(&self) -> String {
+            match self {
+                MmqpAdminCommand::Purge(_) => "PURGE".to_string(),
+                MmqpAdminCommand::CreateTopic(_) => "CREATE_TOPIC".to_string(),
+                MmqpAdminCommand::DeleteTopic(_) => "DELETE_TOPIC".to_string(),
+                MmqpAdminCommand::Subscribe(_, _, _) => "SUBSCRIBE".to_string(),
+                MmqpAdminCommand::Unsubscribe(_, _) => "UNSUBSCRIBE".to_string(),
+                MmqpAdminCommand::CreateExchange(_, _) => "CREATE_EXCHANGE".to_string(),
+                MmqpAdminCommand::DeleteExchange(_) => "DELETE_EXCHANGE".to_string(),
+                MmqpAdminCommand::Bind(_, _, _) => "BIND".to_string(),
+                MmqpAdminCommand::Unbind(_, _, _) => "UNBIND".to_string(),
+                MmqpAdminCommand::DeleteConsumerGroup(_, _) => "DELETE_GROUP".to_string(),
+                MmqpAdminCommand::Move(_, _, _, _) => "MOVE".to_string(),
+                MmqpAdminCommand::Copy(_, _, _, _) => "COPY".to_string(),
+                MmqpAdminCommand::Export(_, _) => "EXPORT".to_string(),
+                MmqpAdminCommand::Import(_, _, _) => "IMPORT".to_string(),
+                MmqpAdminCommand::Reload => "RELOAD".to_string(),
+                MmqpAdminCommand::UnknownExportFormat(_) => "EXPORT".to_string(),
+                MmqpAdminCommand::UnknownExchangeType(_) => "CREATE_EXCHANGE".to_string(),
+                MmqpAdminCommand::Truncated(name) | MmqpAdminCommand::Unknown(name) => name.clone(),
+            }
+        }
+
+        pub fn serialise(&self, username: &str, password: &str) -> Box<[u8]> {
+            let pipe = b"|";
+            let mut binary: Vec<u8> = Vec::new();
+            binary.extend("MMQP".as_bytes());
+            binary.extend(pipe);
+            binary.extend([0u8, b'.', 1u8]);
+            binary.extend(pipe);
+            binary.extend(b"A");
+            binary.extend(pipe);
+            binary.extend(username.to_string().to_mmqp_binary().unwrap());
+            binary.extend(b":");
+            binary.extend(password.to_string().to_mmqp_binary().unwrap());
+            binary.extend(pipe);
+            binary.extend(self.name().to_mmqp_binary().unwrap());
+
+            match self {
+                MmqpAdminCommand::Purge(name)
+                | MmqpAdminCommand::CreateTopic(name)
+                | MmqpAdminCommand::DeleteTopic(name)
+                | MmqpAdminCommand::DeleteExchange(name) => {
+                    binary.extend(pipe);
+                    binary.extend(name.to_mmqp_binary().unwrap());
+                }
+                MmqpAdminCommand::Subscribe(topic, queue, filter) => {
+                    binary.extend(pipe);
+                    binary.extend(topic.to_mmqp_binary().unwrap());
+                    binary.extend(pipe);
+                    binary.extend(queue.to_mmqp_binary().unwrap());
+                    // an empty filter is no filter
+                    binary.extend(pipe);
+                    binary.extend(filter.clone().unwrap_or_default().to_mmqp_binary().unwrap());
+                }
+                MmqpAdminCommand::Unsubscribe(topic, queue) => {
+                    binary.extend(pipe);
+                    binary.extend(topic.to_mmqp_binary().unwrap());
+                    binary.extend(pipe);
+                    binary.extend(queue.to_mmqp_binary().unwrap());
+                }
+                MmqpAdminCommand::DeleteConsumerGroup(queue, group) => {
+                    binary.extend(pipe);
+                    binary.extend(queue.to_mmqp_binary().unwrap());
+                    binary.extend(pipe);
+                    binary.extend(group.to_mmqp_binary().unwrap());
+                }
+                MmqpAdminCommand::CreateExchange(name, exchange_type) => {
+                    binary.extend(pipe);
+                    binary.extend(name.to_mmqp_binary().unwrap());
+                    binary.extend(pipe);
+                    binary.extend(exchange_type.name().to_string().to_mmqp_binary().unwrap());
+                }
+                MmqpAdminCommand::Bind(exchange, queue, pattern)
+                | MmqpAdminCommand::Unbind(exchange, queue, pattern) => {
+                    binary.extend(pipe);
+                    binary.extend(exchange.to_mmqp_binary().unwrap());
+                    binary.extend(pipe);
+                    binary.extend(queue.to_mmqp_binary().unwrap());
+                    binary.extend(pipe);
+                    binary.extend(pattern.to_mmqp_binary().unwrap());
+                }
+                MmqpAdminCommand::Move(source, destination, selection, keep_ids)
+                | MmqpAdminCommand::Copy(source, destination, selection, keep_ids) => {
+                    let (kind, argument) = match selection {
+                        MessageSelection::All => ("ALL", "".to_string()),
+                        MessageSelection::Filter(filter) => ("FILTER", filter.clone()),
+                        MessageSelection::Ids(ids) => ("IDS", ids.join(",")),
+                    };
+                    let preserve = if *keep_ids { "PRESERVE" } else { "" };
+
+                    for arg in [
+                        source.clone(),
+                        destination.clone(),
+                        kind.to_string(),
+                        argument,
+                        preserve.to_string(),
+                    ] {
+                        binary.extend(pipe);
+                        binary.extend(arg.to_mmqp_binary().unwrap());
+                    }
+                }
+                MmqpAdminCommand::Export(queue, format) => {
+                    binary.extend(pipe);
+                    binary.extend(queue.to_mmqp_binary().unwrap());
+                    binary.extend(pipe);
+                    binary.extend(format.name().to_string().to_mmqp_binary().unwrap());
+                }
+                MmqpAdminCommand::Import(queue, format, data) => {
+                    binary.extend(pipe);
+                    binary.extend(queue.to_mmqp_binary().unwrap());
+                    binary.extend(pipe);
+                    binary.extend(format.name().to_string().to_mmqp_binary().unwrap());
+                    binary.extend(pipe);
+                    binary.extend(data.to_mmqp_binary().unwrap());
+                }
+                MmqpAdminCommand::Reload
+                | MmqpAdminCommand::UnknownExportFormat(_)
+                | MmqpAdminCommand::UnknownExchangeType(_)
+                | MmqpAdminCommand::Truncated(_)
+                | MmqpAdminCommand::Unknown(_) => {}
+            }
+
+            binary.push(0x00);
+            binary.into_boxed_slice()
+        }
+    }
+
+    // request starts at the auth section. A request that ends before all of its arguments is Truncated,
+    // named ADMIN if it ends before the command name
+    pub fn parse_admin(request: &[u8]) -> MmqpAdminCommand {
+        let mut cursor = 0usize;
+        let command = match parse_command_name(request, &mut cursor) {
+            Some(command) => command,
+            None => return MmqpAdminCommand::Truncated("ADMIN".to_string()),
+        };
+
+        parse_arguments(&command, request, &mut cursor)
+            .unwrap_or(MmqpAdminCommand::Truncated(command))
+    }
+
+    // %username:%password|%command
+    fn parse_command_name(request: &[u8], cursor: &mut usize) -> Option<String> {
+        String::try_from_mmqp_binary(request, cursor).ok()?;
+        *cursor += 1;
+        String::try_from_mmqp_binary(request, cursor).ok()?;
+        *cursor += 1;
+        String::try_from_mmqp_binary(request, cursor).ok()
+    }
+
+    // None if the request ends before the last argument
+    fn parse_arguments(
+        command: &str,
+        request: &[u8],
+        cursor: &mut usize,
+    ) -> Option<MmqpAdminCommand> {
+        // every argument is a %string preceded by a pipe
+        let mut next_arg = || {
+            *cursor += 1;
+            String::try_from_mmqp_binary(request, cursor).ok()
+        };
+
+        let parsed = match command {
+            "PURGE" => MmqpAdminCommand::Purge(next_arg()?),
+            "CREATE_TOPIC" => MmqpAdminCommand::CreateTopic(next_arg()?),
+            "DELETE_TOPIC" => MmqpAdminCommand::DeleteTopic(next_arg()?),
+            "SUBSCRIBE" => {
+                let topic = next_arg()?;
+                let queue = next_arg()?;
+                let filter = Some(next_arg()?).filter(|filter| !filter.is_empty());
+                MmqpAdminCommand::Subscribe(topic, queue, filter)
+            }
+            "CREATE_EXCHANGE" => {
+                let name = next_arg()?;
+                let exchange_type = next_arg()?;
+                match ExchangeType::from_name(&exchange_type) {
+                    Some(exchange_type) => MmqpAdminCommand::CreateExchange(name, exchange_type),
+                    None => MmqpAdminCommand::UnknownExchangeType(exchange_type),
+                }
+            }
+            "DELETE_EXCHANGE" => MmqpAdminCommand::DeleteExchange(next_arg()?),
+            "BIND" => {
+                let exchange = next_arg()?;
+                let queue = next_arg()?;
+                MmqpAdminCommand::Bind(exchange, queue, next_arg()?)
+            }
+            "UNBIND" => {
+                let exchange = next_arg()?;
+                let queue = next_arg()?;
+                MmqpAdminCommand::Unbind(exchange, queue, next_arg()?)
+            }
+            "UNSUBSCRIBE" => {
+                let topic = next_arg()?;
+                MmqpAdminCommand::Unsubscribe(topic, next_arg()?)
+            }
+            "MOVE" | "COPY" => {
+                let source = next_arg()?;
+                let destination = next_arg()?;
+                let kind = next_arg()?;
+                let argument = next_arg()?;
+                let keep_ids = next_arg()? == "PRESERVE";
+
+                // ids are base64, which has no commas
+                let selection = match kind.as_str() {
+                    "ALL" => MessageSelection::All,
+                    "FILTER" => MessageSelection::Filter(argument),
+                    "IDS" => MessageSelection::Ids(
+                        argument
+                            .split(',')
+                            .filter(|id| !id.is_empty())
+                            .map(|id| id.to_string())
+                            .collect(),
+                    ),
+                    _ => return Some(MmqpAdminCommand::Unknown(format!("{} {}", command, kind))),
+                };
+
+                if command == "MOVE" {
+                    MmqpAdminCommand::Move(source, destination, selection, keep_ids)
+                } else {
+                    MmqpAdminCommand::Copy(source, destination, selection, keep_ids)
+                }
+            }
+            "EXPORT" => {
+                let queue = next_arg()?;
+                let format = next_arg()?;
+                match ExportFormat::from_name(&format) {
+                    Some(format) => MmqpAdminCommand::Export(queue, format),
+                    None => MmqpAdminCommand::UnknownExportFormat(format),
+                }
+            }
+            "IMPORT" => {
+                let queue = next_arg()?;
+                let format = next_arg()?;
+                let format = match ExportFormat::from_name(&format) {
+                    Some(format) => format,
+                    None => return Some(MmqpAdminCommand::UnknownExportFormat(format)),
+                };
+
+                // the file is binary and can be large, a request that was cut short is not imported
+                *cursor += 1;
+                let data = Vec::<u8>::try_from_mmqp_binary(request, cursor).ok()?;
+                MmqpAdminCommand::Import(queue, format, data)
+            }
+            "RELOAD" => MmqpAdminCommand::Reload,
+            "DELETE_GROUP" => {
+                let queue = next_arg()?;
+                MmqpAdminCommand::DeleteConsumerGroup(queue, next_arg()?)
+            }
+            _ => MmqpAdminCommand::Unknown(command.to_string()),
+        };
+        Some(parsed)
+    }
+}
@@ -0,0 +1,174 @@
+pub mod attributes {
+    use crate::encoding::encoding::{DecodeError, MmqpEncoding};
+    use std::collections::BTreeMap;
+
+    // attribute values are typed so consumers do not have to guess how to interpret them
+    // every value is written as a type byte followed by a %string style length-prefixed payload
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub enum MmqpAttributeValue {
+        String(String),
+        Number(i64),
+        Binary(Vec<u8>),
+    }
+
+    // keyed by attribute name. BTreeMap keeps the encoded order stable between serialisations
+    pub type MmqpAttributes = BTreeMap<String, MmqpAttributeValue>;
+
+    // reserved attribute names, interpreted by the server rather than just carried with the message
+
+    // Number, milliseconds after the message is received that it expires
+    pub const TTL_ATTRIBUTE: &str = "mmqp.ttl";
+
+    // String, the W3C traceparent of the span that sent the message. Dropped when malformed
+    pub const TRACEPARENT_ATTRIBUTE: &str = "mmqp.traceparent";
+
+    // String, the W3C tracestate that goes with mmqp.traceparent, dropped without it
+    pub const TRACESTATE_ATTRIBUTE: &str = "mmqp.tracestate";
+
+    const STRING_TYPE: u8 = b'S';
+    const NUMBER_TYPE: u8 = b'N';
+    const BINARY_TYPE: u8 = b'B';
+
+    impl MmqpAttributeValue {
+        pub fn type_byte(&self) -> u8 {
+            match self {
+                MmqpAttributeValue::String(_) => STRING_TYPE,
+                MmqpAttributeValue::Number(_) => NUMBER_TYPE,
+                MmqpAttributeValue::Binary(_) => BINARY_TYPE,
+            }
+        }
+
+        fn payload_len(&self) -> usize {
+            match self {
+                MmqpAttributeValue::String(s) => s.len(),
+                MmqpAttributeValue::Number(n) => n.to_string().len(),
+                MmqpAttributeValue::Binary(b) => b.len(),
+            }
+        }
+    }
+
+    impl MmqpEncoding for MmqpAttributeValue {
+        fn to_mmqp_binary(&self) -> Result<Vec<u8>, String> {
+            let mut binary: Vec<u8> = vec![self.type_byte()];
+
+            match self {
+                MmqpAttributeValue::String(s) => binary.extend(s.to_mmqp_binary()?),
+                MmqpAttributeValue::Number(n) => binary.extend(n.to_string().to_mmqp_binary()?),
+                MmqpAttributeValue::Binary(b) => binary.extend(b.to_mmqp_binary()?),
+            }
+
+            Ok(binary)
+        }
+
+        // values are only ever written by to_mmqp_binary, so a bad type or number here is corruption
+        fn from_mmqp_binary(message_binary: &[u8], cursor: &mut usize) -> Self {
+            MmqpAttributeValue::try_from_mmqp_binary(message_binary, cursor)
+                .expect("malformed attribute value")
+        }
+
+        // None for an unknown type byte or a Number that is not a decimal i64, rather than guessing
+        fn try_from_mmqp_binary(
+            message_binary: &[u8],
+            cursor: &mut usize,
+        ) -> Result<Self, DecodeError> {
+            let mut from = *cursor;
+            let type_byte = *message_binary.get(from).ok_or(DecodeError::Truncated)?;
+            from += 1;
+
+            let value = match type_byte {
+                STRING_TYPE => MmqpAttributeValue::String(String::try_from_mmqp_binary(
+                    message_binary,
+                    &mut from,
+                )?),
+                NUMBER_TYPE => {
+                    let number = String::try_from_mmqp_binary(message_binary, &mut from)?;
+                    MmqpAttributeValue::Number(number.parse().map_err(|_| DecodeError::Malformed)?)
+                }
+                BINARY_TYPE => MmqpAttributeValue::Binary(Vec::<u8>::try_from_mmqp_binary(
+                    message_binary,
+                    &mut from,
+                )?),
+                _ => return Err(DecodeError::Malformed),
+            };
+
+            *cursor = from;
+            Ok(value)
+        }
+
+        fn mmqp_binary_size(&self) -> usize {
+            //type byte + length prefix + payload
+            1 + self.payload_len().mmqp_binary_size() + self.payload_len()
+        }
+
+        fn raw(message_binary: &[u8], cursor: &mut usize) -> Vec<u8> {
+            let start = *cursor;
+            *cursor += 1; //type byte
+            let size = usize::from_mmqp_binary(message_binary, cursor);
+            *cursor += size;
+            message_binary[start..*cursor].to_vec()
+        }
+    }
+
+    // the whole map is encoded as:
+    // number of attributes (encoded as a usize), then for each attribute %key followed by the encoded value
+    impl MmqpEncoding for MmqpAttributes {
+        fn to_mmqp_binary(&self) -> Result<Vec<u8>, String> {
+            let mut binary: Vec<u8> = self.len().to_mmqp_binary()?;
+
+            for (key, value) in self.iter() {
+                binary.extend(key.to_mmqp_binary()?);
+                binary.extend(value.to_mmqp_binary()?);
+            }
+
+            Ok(binary)
+        }
+
+        fn from_mmqp_binary(message_binary: &[u8], cursor: &mut usize) -> Self {
+            MmqpAttributes::try_from_mmqp_binary(message_binary, cursor)
+                .expect("malformed attributes")
+        }
+
+        // the count is not trusted, each attribute takes at least 3 bytes so a count the binary cannot
+        // hold fails when the bytes run out
+        fn try_from_mmqp_binary(
+            message_binary: &[u8],
+            cursor: &mut usize,
+        ) -> Result<Self, DecodeError> {
+            let mut from = *cursor;
+            let count = usize::try_from_mmqp_binary(message_binary, &mut from)?;
+
+            let mut attributes = MmqpAttributes::new();
+            for _ in 0..count {
+                let key = String::try_from_mmqp_binary(message_binary, &mut from)?;
+                let value = MmqpAttributeValue::try_from_mmqp_binary(message_binary, &mut from)?;
+                attributes.insert(key, value);
+            }
+
+            *cursor = from;
+            Ok(attributes)
+        }
+
+        fn mmqp_binary_size(&self) -> usize {
+            let mut size = self.len().mmqp_binary_size();
+
+            for (key, value) in self.iter() {
+                size += key.mmqp_binary_size();
+                size += value.mmqp_binary_size();
+            }
+
+            size
+        }
+
+        fn raw(message_binary: &[u8], cursor: &mut usize) -> Vec<u8> {
+            let start = *cursor;
+            let count = usize::from_mmqp_binary(message_binary, cursor);
+
+            for _ in 0..count {
+                String::raw(message_binary, cursor);
+                MmqpAttributeValue::raw(message_binary, cursor);
+            }
+
+            message_binary[start..*cursor].to_vec()
+        }
+    }
+}
@@ -0,0 +1,150 @@
+pub mod blob_store {
+    use crate::normalised_message::normalised_message::MmqpNormalisedMessage;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    #[test]
+    fn offload_and_stream() {
+        use crate::serialiser::MmqpSerialisable;
+        use crate::SerialisationStrategy;
+
+        let dir = std::env::temp_dir().join(format!("mmqp-blobs-{}", std::process::id()));
+        let blobs = BlobStore::new(&dir).unwrap();
+
+        let mut message = crate::MmqpMessage::new();
+        message.set_binary(vec![7u8; 100_000]);
+        let mut norm =
+            crate::normalised_message::normalised_message::Receivable::normalise(&message);
+        let inline = norm.serialise(SerialisationStrategy::Storage);
+
+        blobs.offload(&mut norm).unwrap();
+        assert!(norm.offloaded);
+        assert!(norm.message.len() < 100);
+
+        // the reference survives a round trip through the read buffers
+        let mut stored = norm.serialise(SerialisationStrategy::Storage).into_vec();
+        let restored = MmqpNormalisedMessage::deserialise(&mut stored, &mut 0);
+        assert!(restored.offloaded);
+
+        // streaming gives the same bytes as a message that was never offloaded
+        let mut streamed: Vec<u8> = vec![];
+        blobs.write_message(&restored, &mut streamed).unwrap();
+        assert_eq!(streamed, inline.into_vec());
+
+        // a copy gets its own body, releasing one leaves the other
+        let mut copy = restored.clone();
+        blobs.duplicate(&mut copy).unwrap();
+        assert_ne!(copy.message, restored.message);
+
+        // loading puts the body back inline
+        let mut loaded = copy.clone();
+        blobs.load(&mut loaded).unwrap();
+        assert!(!loaded.offloaded);
+        assert_eq!(loaded.message, vec![7u8; 100_000]);
+
+        blobs.release(&restored);
+        assert!(blobs.write_message(&restored, &mut vec![]).is_err());
+        assert!(blobs.write_message(&copy, &mut vec![]).is_ok());
+        blobs.release(&copy);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // bodies over a queue's offload threshold are written here, one file per message, so only a short
+    // reference has to sit in the read buffers. Shared by every queue so a reference stays valid when a
+    // message is dead lettered
+    #[derive(Debug, Clone)]
+    pub struct BlobStore {
+        dir: PathBuf,
+    }
+
+    impl BlobStore {
+        pub fn new(dir: impl Into<PathBuf>) -> std::io::Result<BlobStore> {
+            let dir = dir.into();
+            std::fs::create_dir_all(&dir)?;
+            Ok(BlobStore { dir })
+        }
+
+        fn path(&self, key: &[u8]) -> PathBuf {
+            self.dir.join(String::from_utf8_lossy(key).as_ref())
+        }
+
+        // move the body of message to disk and replace it with the key
+        pub fn offload(&self, message: &mut MmqpNormalisedMessage) -> std::io::Result<()> {
+            // message ids are random bytes, url safe base64 keeps the key usable as a file name
+            let key = base64::encode_config(message.message_id, base64::URL_SAFE_NO_PAD);
+
+            // written under a temporary name first so a crash never leaves a partial body behind the key
+            let path = self.path(key.as_bytes());
+            let partial = path.with_extension("partial");
+            let mut file = File::create(&partial)?;
+            file.write_all(&message.message)?;
+            file.sync_all()?;
+            std::fs::rename(partial, path)?;
+
+            message.message = key.into_bytes();
+            message.offloaded = true;
+            Ok(())
+        }
+
+        // give a copy of an offloaded message its own stored body, so each copy can be released on its own.
+        // does nothing for inline messages
+        pub fn duplicate(&self, message: &mut MmqpNormalisedMessage) -> std::io::Result<()> {
+            if !message.offloaded {
+                return Ok(());
+            }
+
+            // the copy may keep the message id, so the key is random instead
+            let key = base64::encode_config(
+                MmqpNormalisedMessage::generate_message_id(),
+                base64::URL_SAFE_NO_PAD,
+            );
+            let path = self.path(key.as_bytes());
+            let partial = path.with_extension("partial");
+            std::fs::copy(self.path(&message.message), &partial)?;
+            std::fs::rename(partial, path)?;
+
+            message.message = key.into_bytes();
+            Ok(())
+        }
+
+        // put the stored body back in the message. The stored copy is left for the original to release.
+        // does nothing for inline messages
+        pub fn load(&self, message: &mut MmqpNormalisedMessage) -> std::io::Result<()> {
+            if !message.offloaded {
+                return Ok(());
+            }
+
+            message.message = std::fs::read(self.path(&message.message))?;
+            message.offloaded = false;
+            Ok(())
+        }
+
+        // the stored body of an offloaded message and its length
+        pub fn open(&self, message: &MmqpNormalisedMessage) -> std::io::Result<(File, usize)> {
+            let file = File::open(self.path(&message.message))?;
+            let len = file.metadata()?.len() as usize;
+            Ok((file, len))
+        }
+
+        // write the message in the same form as an inline one, copying the body straight from disk
+        pub fn write_message(
+            &self,
+            message: &MmqpNormalisedMessage,
+            out: &mut impl Write,
+        ) -> std::io::Result<()> {
+            let (mut file, len) = self.open(message)?;
+            message.write_with_body(&mut file, len, out)
+        }
+
+        // remove the body of a message that has left the server. Does nothing for inline messages
+        pub fn release(&self, message: &MmqpNormalisedMessage) {
+            if message.offloaded {
+                if let Err(e) = std::fs::remove_file(self.path(&message.message)) {
+                    tracing::warn!(error = %e, "could not remove message body");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,197 @@
+pub mod browse {
+    use crate::encoding::encoding::MmqpEncoding;
+
+    #[test]
+    fn browse_page_round_trip() {
+        let page = BrowsePage {
+            entries: vec![
+                BrowseEntry {
+                    message_id: "bWVzc2FnZWlk".to_string(),
+                    group: "".to_string(),
+                    state: BrowseState::Ready,
+                    age: 1500,
+                    available_in: 0,
+                    receive_count: 0,
+                    priority: 0,
+                    size: 11,
+                },
+                BrowseEntry {
+                    message_id: "b3RoZXI".to_string(),
+                    group: "orders".to_string(),
+                    state: BrowseState::InFlight,
+                    age: 70_000,
+                    available_in: 29_000,
+                    receive_count: 3,
+                    priority: 2,
+                    size: 300_000,
+                },
+            ],
+            next: Some(BrowsePosition {
+                section: 1,
+                position: 4096,
+            }),
+        };
+
+        let binary = serialise_browse_page(&page);
+        assert_eq!(deserialise_browse_page(&binary), page);
+
+        let end = BrowsePage {
+            entries: vec![],
+            next: None,
+        };
+        assert_eq!(deserialise_browse_page(&serialise_browse_page(&end)), end);
+    }
+
+    // where a browse starts. The sections of a queue are its read buffers in the order they are polled
+    // (highest priority first), then the messages that are not in the read buffers: delayed and returned
+    // messages by the time they become available, then in flight messages by the time they are returned.
+    // position is a read buffer position in the read buffer sections and a count of messages to skip in
+    // the last one
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct BrowsePosition {
+        pub section: usize,
+        pub position: u64,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub enum BrowseState {
+        // waiting in the read buffers
+        Ready,
+
+        // delayed, or returned after a visibility timeout or negative ack
+        Delayed,
+
+        // polled and not deleted yet
+        InFlight,
+    }
+
+    impl BrowseState {
+        pub fn code(&self) -> u8 {
+            match self {
+                BrowseState::Ready => b'R',
+                BrowseState::Delayed => b'D',
+                BrowseState::InFlight => b'F',
+            }
+        }
+
+        pub fn from_code(code: u8) -> BrowseState {
+            match code {
+                b'D' => BrowseState::Delayed,
+                b'F' => BrowseState::InFlight,
+                _ => BrowseState::Ready,
+            }
+        }
+    }
+
+    // what a browse shows of a message, the body is left out
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct BrowseEntry {
+        pub message_id: String,
+        pub group: String,
+        pub state: BrowseState,
+
+        // ms since the message was received
+        pub age: u64,
+
+        // ms until the message can be polled, 0 if it can be now
+        pub available_in: u64,
+
+        pub receive_count: u32,
+        pub priority: u8,
+
+        // body bytes, of the stored body for offloaded messages
+        pub size: u64,
+    }
+
+    // next is where the following page starts, None once the end of the queue was reached
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct BrowsePage {
+        pub entries: Vec<BrowseEntry>,
+        pub next: Option<BrowsePosition>,
+    }
+
+    const BROWSE_HEADER: &[u8] = b"MMQP|0.1|R|BROWSE|";
+
+    // MMQP|0.1|R|BROWSE|more|&section|&position|&count|entry|entry...
+    // entry: %messageId|%group|state|&age|&availableIn|&receiveCount|priority|&size
+    // more is 1 if the next page starts at section/position, 0 at the end of the queue
+    pub fn serialise_browse_page(page: &BrowsePage) -> Vec<u8> {
+        let mut binary: Vec<u8> = BROWSE_HEADER.to_vec();
+        let next = page.next.unwrap_or_default();
+        binary.push(page.next.is_some() as u8);
+        binary.extend(b"|");
+        binary.extend(next.section.to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.extend((next.position as usize).to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.extend(page.entries.len().to_mmqp_binary().unwrap());
+
+        for entry in page.entries.iter() {
+            binary.extend(b"|");
+            binary.extend(entry.message_id.to_mmqp_binary().unwrap());
+            binary.extend(b"|");
+            binary.extend(entry.group.to_mmqp_binary().unwrap());
+            binary.extend(b"|");
+            binary.push(entry.state.code());
+            binary.extend(b"|");
+            binary.extend((entry.age as usize).to_mmqp_binary().unwrap());
+            binary.extend(b"|");
+            binary.extend((entry.available_in as usize).to_mmqp_binary().unwrap());
+            binary.extend(b"|");
+            binary.extend((entry.receive_count as usize).to_mmqp_binary().unwrap());
+            binary.extend(b"|");
+            binary.push(entry.priority);
+            binary.extend(b"|");
+            binary.extend((entry.size as usize).to_mmqp_binary().unwrap());
+        }
+
+        binary.push(0x00);
+        binary
+    }
+
+    pub fn deserialise_browse_page(binary: &[u8]) -> BrowsePage {
+        let mut cursor = BROWSE_HEADER.len();
+        let more = binary[cursor] == 1;
+        cursor += 2;
+        let section = usize::from_mmqp_binary(binary, &mut cursor);
+        cursor += 1; //pipe
+        let position = usize::from_mmqp_binary(binary, &mut cursor) as u64;
+        cursor += 1; //pipe
+        let count = usize::from_mmqp_binary(binary, &mut cursor);
+
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            cursor += 1; //pipe
+            let message_id = String::from_mmqp_binary(binary, &mut cursor);
+            cursor += 1; //pipe
+            let group = String::from_mmqp_binary(binary, &mut cursor);
+            cursor += 1; //pipe
+            let state = BrowseState::from_code(binary[cursor]);
+            cursor += 2;
+            let age = usize::from_mmqp_binary(binary, &mut cursor) as u64;
+            cursor += 1; //pipe
+            let available_in = usize::from_mmqp_binary(binary, &mut cursor) as u64;
+            cursor += 1; //pipe
+            let receive_count = usize::from_mmqp_binary(binary, &mut cursor) as u32;
+            cursor += 1; //pipe
+            let priority = binary[cursor];
+            cursor += 2;
+            let size = usize::from_mmqp_binary(binary, &mut cursor) as u64;
+            entries.push(BrowseEntry {
+                message_id,
+                group,
+                state,
+                age,
+                available_in,
+                receive_count,
+                priority,
+                size,
+            });
+        }
+
+        BrowsePage {
+            entries,
+            next: Some(BrowsePosition { section, position }).filter(|_| more),
+        }
+    }
+}
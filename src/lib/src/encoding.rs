@@ -1,8 +1,49 @@
 pub mod encoding {
 
+    // the length a %string style value at the cursor claims to have, without moving the cursor or
+    // allocating anything. None if the length itself is malformed or the value would run past the end
+    // of the binary, which means the request cannot be trusted
+    pub fn peek_mmqp_length(message_binary: &[u8], cursor: usize) -> Option<usize> {
+        let size_for_size = *message_binary.get(cursor)? as usize;
+        if size_for_size > 8 {
+            return None;
+        }
+
+        let mut bytes: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
+        for i in 0..size_for_size {
+            bytes[8 - (size_for_size - i)] = *message_binary.get(cursor + 1 + i)?;
+        }
+        let length = usize::from_be_bytes(bytes);
+
+        let start = cursor + 1 + size_for_size;
+        if length > message_binary.len().saturating_sub(start) {
+            return None;
+        }
+
+        Some(length)
+    }
+
+    // why a value could not be read. Truncated values may still arrive, malformed ones never will
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum DecodeError {
+        Truncated,
+        Malformed,
+    }
+
     pub trait MmqpEncoding {
         fn to_mmqp_binary(&self) -> Result<Vec<u8>, String>;
+
+        // for bytes this library wrote, anything else should use try_from_mmqp_binary
         fn from_mmqp_binary(message_binary: &[u8], cursor: &mut usize) -> Self;
+
+        // for bytes from a client or a file. The cursor is only moved when a value is read
+        fn try_from_mmqp_binary(
+            message_binary: &[u8],
+            cursor: &mut usize,
+        ) -> Result<Self, DecodeError>
+        where
+            Self: Sized;
+
         fn mmqp_binary_size(&self) -> usize;
         fn raw(message_binary: &[u8], cursor: &mut usize) -> Vec<u8>;
     }
@@ -16,9 +57,9 @@ pub mod encoding {
                 0 => 0,
                 1..=255 => 1,
                 256..=65535 => 2,
-                65537..=16777215 => 3,
+                65536..=16777215 => 3,
                 16777216..=4294967295 => 4,
-                429496726..=1099511627775 => 5,
+                4294967296..=1099511627775 => 5,
                 1099511627776..=281474976710655 => 6,
                 281474976710656..=72057594037927935 => 7,
                 72057594037927936..=18446744073709551615 => 8,
@@ -41,9 +82,8 @@ pub mod encoding {
             let size_for_size = message_binary[from];
             from += 1;
 
-            println!("{}", size_for_size);
-
             if size_for_size == 0 {
+                *cursor = from;
                 return 0;
             }
 
@@ -57,15 +97,36 @@ pub mod encoding {
             usize::from_be_bytes(bytes)
         }
 
+        fn try_from_mmqp_binary(
+            message_binary: &[u8],
+            cursor: &mut usize,
+        ) -> Result<Self, DecodeError> {
+            let size_for_size =
+                *message_binary.get(*cursor).ok_or(DecodeError::Truncated)? as usize;
+            if size_for_size > 8 {
+                return Err(DecodeError::Malformed);
+            }
+            if *cursor + 1 + size_for_size > message_binary.len() {
+                return Err(DecodeError::Truncated);
+            }
+
+            let mut bytes: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
+            for i in 0..size_for_size {
+                bytes[8 - (size_for_size - i)] = message_binary[*cursor + 1 + i];
+            }
+            *cursor += 1 + size_for_size;
+            Ok(usize::from_be_bytes(bytes))
+        }
+
         fn mmqp_binary_size(&self) -> usize {
             let length = self;
             let required_size: usize = match length {
                 0 => 0,
                 1..=255 => 1,
                 256..=65535 => 2,
-                65537..=16777215 => 3,
+                65536..=16777215 => 3,
                 16777216..=4294967295 => 4,
-                429496726..=1099511627775 => 5,
+                4294967296..=1099511627775 => 5,
                 1099511627776..=281474976710655 => 6,
                 281474976710656..=72057594037927935 => 7,
                 72057594037927936..=18446744073709551615 => 8,
@@ -105,9 +166,9 @@ pub mod encoding {
                 0 => 0,
                 1..=255 => 1,
                 256..=65535 => 2,
-                65537..=16777215 => 3,
+                65536..=16777215 => 3,
                 16777216..=4294967295 => 4,
-                429496726..=1099511627775 => 5,
+                4294967296..=1099511627775 => 5,
                 1099511627776..=281474976710655 => 6,
                 281474976710656..=72057594037927935 => 7,
                 72057594037927936..=18446744073709551615 => 8,
@@ -144,7 +205,16 @@ pub mod encoding {
             let actual_size = usize::from_be_bytes(bytes);
             let from = *cursor;
             *cursor += actual_size;
-            String::from_utf8(message_binary[from..*cursor].to_vec()).unwrap()
+            // invalid utf8 must not take the server down, binary payloads should use Vec<u8> instead
+            String::from_utf8_lossy(&message_binary[from..*cursor]).into_owned()
+        }
+
+        fn try_from_mmqp_binary(
+            message_binary: &[u8],
+            cursor: &mut usize,
+        ) -> Result<Self, DecodeError> {
+            let bytes = Vec::<u8>::try_from_mmqp_binary(message_binary, cursor)?;
+            Ok(String::from_utf8_lossy(&bytes).into_owned())
         }
 
         fn mmqp_binary_size(&self) -> usize {
@@ -162,9 +232,9 @@ pub mod encoding {
                 0 => 0,
                 1..=255 => 1,
                 256..=65535 => 2,
-                65537..=16777215 => 3,
+                65536..=16777215 => 3,
                 16777216..=4294967295 => 4,
-                429496726..=1099511627775 => 5,
+                4294967296..=1099511627775 => 5,
                 1099511627776..=281474976710655 => 6,
                 281474976710656..=72057594037927935 => 7,
                 72057594037927936..=18446744073709551615 => 8,
@@ -197,4 +267,52 @@ pub mod encoding {
             message_binary[from..*cursor].to_vec()
         }
     }
+
+    // arbitrary bytes, encoded exactly like a %string but never interpreted as utf8
+    impl MmqpEncoding for Vec<u8> {
+        fn to_mmqp_binary(&self) -> Result<Vec<u8>, String> {
+            if self.len() == 0 {
+                return Result::Ok(vec![0]);
+            }
+
+            let mut binary: Vec<u8> = self.len().to_mmqp_binary()?;
+            binary.extend(self);
+
+            Result::Ok(binary)
+        }
+
+        fn from_mmqp_binary(message_binary: &[u8], cursor: &mut usize) -> Self {
+            let actual_size = usize::from_mmqp_binary(message_binary, cursor);
+            let from = *cursor;
+            *cursor += actual_size;
+            message_binary[from..*cursor].to_vec()
+        }
+
+        // the length is checked against what is left before anything is allocated
+        fn try_from_mmqp_binary(
+            message_binary: &[u8],
+            cursor: &mut usize,
+        ) -> Result<Self, DecodeError> {
+            let mut from = *cursor;
+            let length = usize::try_from_mmqp_binary(message_binary, &mut from)?;
+            if length > message_binary.len() - from {
+                return Err(DecodeError::Truncated);
+            }
+            *cursor = from + length;
+            Ok(message_binary[from..*cursor].to_vec())
+        }
+
+        fn mmqp_binary_size(&self) -> usize {
+            if self.len() == 0 {
+                return 1;
+            }
+
+            //size prefix (which includes its own size byte) plus the bytes themselves
+            self.len().mmqp_binary_size() + self.len()
+        }
+
+        fn raw(message_binary: &[u8], cursor: &mut usize) -> Vec<u8> {
+            Vec::<u8>::from_mmqp_binary(message_binary, cursor)
+        }
+    }
 }
@@ -0,0 +1,186 @@
+pub mod exchange {
+    use std::collections::HashMap;
+
+    #[test]
+    fn topic_patterns() {
+        assert!(pattern_matches("orders.*.created", "orders.eu.created"));
+        assert!(!pattern_matches("orders.*.created", "orders.created"));
+        assert!(!pattern_matches("orders.*", "orders.eu.created"));
+        assert!(pattern_matches("orders.#", "orders"));
+        assert!(pattern_matches("orders.#", "orders.eu.created"));
+        assert!(pattern_matches("#.created", "orders.eu.created"));
+        assert!(pattern_matches("#", ""));
+        assert!(pattern_matches("*.#.created", "orders.created"));
+        assert!(!pattern_matches("*.#.created", "created"));
+    }
+
+    #[test]
+    fn routing() {
+        let mut direct = ExchangeConfiguration::new("direct".to_string(), ExchangeType::Direct);
+        direct.bind("errors", "error");
+        direct.bind("all", "error");
+        direct.bind("all", "info");
+        // wildcards are plain characters on a direct exchange
+        direct.bind("wild", "*");
+        assert!(!direct.bind("all", "info"), "already bound");
+
+        assert_eq!(direct.route("error"), vec!["errors", "all"]);
+        assert_eq!(direct.route("info"), vec!["all"]);
+        assert!(direct.route("debug").is_empty());
+
+        let mut topic = ExchangeConfiguration::new("topic".to_string(), ExchangeType::Topic);
+        topic.bind("eu", "orders.eu.*");
+        topic.bind("orders", "orders.#");
+        topic.bind("orders", "*.eu.created");
+        // a queue gets one copy even when several of its bindings match
+        assert_eq!(topic.route("orders.eu.created"), vec!["eu", "orders"]);
+        assert_eq!(topic.route("orders.us"), vec!["orders"]);
+
+        assert!(topic.unbind("orders", "orders.#"));
+        assert!(!topic.unbind("orders", "orders.#"));
+        assert!(topic.route("orders.us").is_empty());
+
+        let json = serialise_exchanges(&[direct.clone(), topic.clone()]);
+        assert_eq!(
+            deserialise_exchanges(&json).unwrap().into_vec(),
+            vec![direct, topic]
+        );
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum ExchangeType {
+        // a binding matches when its pattern is exactly the routing key
+        Direct,
+
+        // routing keys and patterns are words separated by dots. * matches exactly one word and
+        // # matches zero or more words
+        Topic,
+    }
+
+    impl ExchangeType {
+        pub fn name(&self) -> &'static str {
+            match self {
+                ExchangeType::Direct => "direct",
+                ExchangeType::Topic => "topic",
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<ExchangeType> {
+            match name.to_ascii_lowercase().as_str() {
+                "direct" => Some(ExchangeType::Direct),
+                "topic" => Some(ExchangeType::Topic),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct Binding {
+        pub queue: String,
+        pub pattern: String,
+    }
+
+    // messages sent to an exchange are delivered to every queue with a binding that matches the
+    // routing key they were sent with
+    #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct ExchangeConfiguration {
+        pub name: String,
+        pub exchange_type: ExchangeType,
+
+        // in the order they were added
+        #[serde(default)]
+        pub bindings: Vec<Binding>,
+    }
+
+    // exchange name -> exchange
+    pub type ExchangeMap = HashMap<String, ExchangeConfiguration>;
+
+    impl ExchangeConfiguration {
+        pub fn new(name: String, exchange_type: ExchangeType) -> ExchangeConfiguration {
+            ExchangeConfiguration {
+                name,
+                exchange_type,
+                bindings: vec![],
+            }
+        }
+
+        // returns false if the queue was already bound with this pattern
+        pub fn bind(&mut self, queue: &str, pattern: &str) -> bool {
+            let binding = Binding {
+                queue: queue.to_string(),
+                pattern: pattern.to_string(),
+            };
+            if self.bindings.contains(&binding) {
+                return false;
+            }
+            self.bindings.push(binding);
+            true
+        }
+
+        // returns false if there was no such binding
+        pub fn unbind(&mut self, queue: &str, pattern: &str) -> bool {
+            let before = self.bindings.len();
+            self.bindings
+                .retain(|binding| binding.queue != queue || binding.pattern != pattern);
+            self.bindings.len() != before
+        }
+
+        // the queues a message with this routing key is delivered to, each one once, in the order of
+        // their first matching binding
+        pub fn route(&self, routing_key: &str) -> Vec<String> {
+            let mut queues: Vec<String> = vec![];
+
+            for binding in self.bindings.iter() {
+                let matched = match self.exchange_type {
+                    ExchangeType::Direct => binding.pattern == routing_key,
+                    ExchangeType::Topic => pattern_matches(&binding.pattern, routing_key),
+                };
+                if matched && !queues.contains(&binding.queue) {
+                    queues.push(binding.queue.clone());
+                }
+            }
+
+            queues
+        }
+    }
+
+    // topic exchange matching, see ExchangeType::Topic
+    pub fn pattern_matches(pattern: &str, routing_key: &str) -> bool {
+        let pattern: Vec<&str> = pattern.split('.').collect();
+        // an empty key has no words, so only patterns made of # match it
+        let key: Vec<&str> = if routing_key.is_empty() {
+            vec![]
+        } else {
+            routing_key.split('.').collect()
+        };
+
+        words_match(&pattern, &key)
+    }
+
+    fn words_match(pattern: &[&str], key: &[&str]) -> bool {
+        match (pattern.first(), key.first()) {
+            (None, None) => true,
+            // either # is done, or it takes one more word
+            (Some(&"#"), _) => {
+                words_match(&pattern[1..], key)
+                    || (!key.is_empty() && words_match(pattern, &key[1..]))
+            }
+            (Some(&"*"), Some(_)) => words_match(&pattern[1..], &key[1..]),
+            (Some(p), Some(k)) if p == k => words_match(&pattern[1..], &key[1..]),
+            _ => false,
+        }
+    }
+
+    pub fn deserialise_exchanges(
+        settings: &str,
+    ) -> Result<Box<[ExchangeConfiguration]>, serde_json::Error> {
+        serde_json::from_str(settings)
+    }
+
+    // sorted by name so the file does not churn between saves
+    pub fn serialise_exchanges(exchanges: &[ExchangeConfiguration]) -> String {
+        let mut exchanges = exchanges.to_vec();
+        exchanges.sort_by(|a, b| a.name.cmp(&b.name));
+        serde_json::to_string_pretty(&exchanges).unwrap()
+    }
+}
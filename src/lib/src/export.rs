@@ -0,0 +1,330 @@
+pub mod export {
+    use crate::attributes::attributes::MmqpAttributes;
+    use crate::browse::browse::BrowseState;
+    use crate::encoding::encoding::{DecodeError, MmqpEncoding};
+    use crate::normalised_message::normalised_message::MmqpNormalisedMessage;
+    use crate::queue::queue::QueueConfiguration;
+    use crate::serialiser::MmqpSerialisable;
+    use crate::{MmqpBodyKind, SerialisationStrategy};
+
+    #[test]
+    fn export_round_trip() {
+        use crate::attributes::attributes::MmqpAttributeValue;
+        use crate::normalised_message::normalised_message::Receivable;
+
+        let mut text = crate::MmqpMessage::new();
+        text.set_text("hello");
+        text.message_group = "orders".to_string();
+        text.attributes.insert(
+            "region".to_string(),
+            MmqpAttributeValue::String("eu".to_string()),
+        );
+        let mut binary = crate::MmqpMessage::new();
+        binary.set_binary(vec![0, 159, 146, 150]);
+        binary.priority = 3;
+
+        let mut in_flight = binary.normalise();
+        in_flight.receive_count = 2;
+        let export = QueueExport {
+            configuration: QueueConfiguration::new("orders".to_string()),
+            messages: vec![
+                (BrowseState::Ready, text.normalise()),
+                (BrowseState::InFlight, in_flight),
+            ],
+        };
+
+        for format in [ExportFormat::JsonLines, ExportFormat::Native] {
+            let bytes = serialise_export(&export, format);
+            let imported = deserialise_export(&bytes, format).unwrap();
+            assert_eq!(imported.configuration.name, "orders");
+            assert_eq!(imported.messages.len(), export.messages.len());
+            for (imported, exported) in imported.messages.iter().zip(export.messages.iter()) {
+                assert_eq!(imported.0, exported.0);
+                assert_eq!(
+                    imported.1.serialise(SerialisationStrategy::Storage),
+                    exported.1.serialise(SerialisationStrategy::Storage)
+                );
+            }
+        }
+
+        let response = serialise_export_response(b"contents");
+        assert_eq!(
+            deserialise_export_response(&response),
+            Some(b"contents".to_vec())
+        );
+        assert_eq!(
+            deserialise_export_response(b"MMQP|0.1|R|QUEUE_NOT_FOUND|q|0"),
+            None
+        );
+
+        assert!(deserialise_export(b"not an export", ExportFormat::Native).is_err());
+
+        // corrupt records are errors wherever the damage is
+        let native = serialise_export(&export, ExportFormat::Native);
+        for len in NATIVE_HEADER.len()..native.len() {
+            assert!(deserialise_export(&native[..len], ExportFormat::Native).is_err());
+        }
+        let record = native.len()
+            - export.messages[1]
+                .1
+                .serialise(SerialisationStrategy::Storage)
+                .len();
+        // the record length, then the group and attribute lengths after its 100 fixed bytes
+        for (offset, value) in [(0, 0xff), (1, 0x00), (2 + 100, 0xff), (2 + 101, 0x09)] {
+            let mut corrupt = native.clone();
+            corrupt[record + offset] = value;
+            assert!(deserialise_export(&corrupt, ExportFormat::Native).is_err());
+        }
+        assert!(deserialise_export(b"{}", ExportFormat::JsonLines).is_err());
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ExportFormat {
+        // a json object with the configuration on the first line, then one json object per message
+        JsonLines,
+
+        // the configuration as json, then each message as it is kept in the read buffers
+        Native,
+    }
+
+    impl ExportFormat {
+        pub fn name(&self) -> &'static str {
+            match self {
+                ExportFormat::JsonLines => "jsonl",
+                ExportFormat::Native => "native",
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<ExportFormat> {
+            match name.to_ascii_lowercase().as_str() {
+                "jsonl" => Some(ExportFormat::JsonLines),
+                "native" => Some(ExportFormat::Native),
+                _ => None,
+            }
+        }
+    }
+
+    // a snapshot of a queue. Every message is included, with the state it was in, and with its body inline
+    // even if it was offloaded
+    #[derive(Debug, Clone)]
+    pub struct QueueExport {
+        pub configuration: QueueConfiguration,
+        pub messages: Vec<(BrowseState, MmqpNormalisedMessage)>,
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct ExportHeader {
+        configuration: QueueConfiguration,
+    }
+
+    // a message in the json lines format. Text bodies are kept readable when they are utf8, anything else
+    // is base64
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct ExportedMessage {
+        state: BrowseState,
+        message_id: String,
+        message_group_id: String,
+        received_time: u128,
+        available_time: u128,
+        receive_count: u32,
+        priority: u8,
+        body_kind: MmqpBodyKind,
+        #[serde(default, skip_serializing_if = "MmqpAttributes::is_empty")]
+        attributes: MmqpAttributes,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        base64: Option<String>,
+    }
+
+    const NATIVE_HEADER: &[u8] = b"MMQP-EXPORT|";
+
+    const EXPORT_RESPONSE_HEADER: &[u8] = b"MMQP|0.1|R|EXPORT|";
+
+    // MMQP|0.1|R|EXPORT|%contents
+    pub fn serialise_export_response(contents: &[u8]) -> Vec<u8> {
+        let mut binary = EXPORT_RESPONSE_HEADER.to_vec();
+        binary.extend(contents.to_vec().to_mmqp_binary().unwrap());
+        binary.push(0x00);
+        binary
+    }
+
+    // None if the response is not an export, the server responds with an error code instead
+    pub fn deserialise_export_response(binary: &[u8]) -> Option<Vec<u8>> {
+        if !binary.starts_with(EXPORT_RESPONSE_HEADER) {
+            return None;
+        }
+        let mut cursor = EXPORT_RESPONSE_HEADER.len();
+        Vec::<u8>::try_from_mmqp_binary(binary, &mut cursor).ok()
+    }
+
+    pub fn serialise_export(export: &QueueExport, format: ExportFormat) -> Vec<u8> {
+        match format {
+            ExportFormat::JsonLines => serialise_json_lines(export),
+            ExportFormat::Native => serialise_native(export),
+        }
+    }
+
+    pub fn deserialise_export(bytes: &[u8], format: ExportFormat) -> Result<QueueExport, String> {
+        match format {
+            ExportFormat::JsonLines => deserialise_json_lines(bytes),
+            ExportFormat::Native => deserialise_native(bytes),
+        }
+    }
+
+    fn serialise_json_lines(export: &QueueExport) -> Vec<u8> {
+        let header = ExportHeader {
+            configuration: export.configuration.clone(),
+        };
+        let mut lines = vec![serde_json::to_string(&header).unwrap()];
+
+        for (state, message) in export.messages.iter() {
+            let text = match message.body_kind {
+                MmqpBodyKind::Text => String::from_utf8(message.message.clone()).ok(),
+                MmqpBodyKind::Binary => None,
+            };
+            let base64 = match text {
+                Some(_) => None,
+                None => Some(base64::encode(&message.message)),
+            };
+
+            let exported = ExportedMessage {
+                state: *state,
+                message_id: message.message_id_string(),
+                message_group_id: message.message_group_id.clone(),
+                received_time: message.received_time,
+                available_time: message.available_time,
+                receive_count: message.receive_count,
+                priority: message.priority,
+                body_kind: message.body_kind,
+                attributes: message.attributes.clone(),
+                text,
+                base64,
+            };
+            lines.push(serde_json::to_string(&exported).unwrap());
+        }
+
+        let mut bytes = lines.join("\n").into_bytes();
+        bytes.push(b'\n');
+        bytes
+    }
+
+    fn deserialise_json_lines(bytes: &[u8]) -> Result<QueueExport, String> {
+        let contents = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+        let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+
+        let header = lines.next().ok_or("the export is empty")?;
+        let header: ExportHeader =
+            serde_json::from_str(header).map_err(|e| format!("line 1: {}", e))?;
+
+        let mut messages = vec![];
+        for (index, line) in lines.enumerate() {
+            let line_number = index + 2;
+            let exported: ExportedMessage =
+                serde_json::from_str(line).map_err(|e| format!("line {}: {}", line_number, e))?;
+
+            let id = base64::decode(&exported.message_id)
+                .map_err(|e| format!("line {}: message_id: {}", line_number, e))?;
+            let message_id: [u8; 64] = id
+                .try_into()
+                .map_err(|_| format!("line {}: message_id is not 64 bytes", line_number))?;
+
+            let body = match (exported.text, exported.base64) {
+                (Some(text), None) => text.into_bytes(),
+                (None, Some(body)) => {
+                    base64::decode(body).map_err(|e| format!("line {}: {}", line_number, e))?
+                }
+                _ => {
+                    return Err(format!(
+                        "line {}: a message needs one of text or base64",
+                        line_number
+                    ))
+                }
+            };
+
+            messages.push((
+                exported.state,
+                MmqpNormalisedMessage {
+                    body_kind: exported.body_kind,
+                    message: body,
+                    offloaded: false,
+                    message_id,
+                    message_group_id: exported.message_group_id,
+                    attributes: exported.attributes,
+                    priority: exported.priority,
+                    received_time: exported.received_time,
+                    available_time: exported.available_time,
+                    receive_count: exported.receive_count,
+                },
+            ));
+        }
+
+        Ok(QueueExport {
+            configuration: header.configuration,
+            messages,
+        })
+    }
+
+    // MMQP-EXPORT|%configuration|&count|state message|state message...
+    // each message is in the Storage serialisation, state is a BrowseState code
+    fn serialise_native(export: &QueueExport) -> Vec<u8> {
+        let mut bytes = NATIVE_HEADER.to_vec();
+        let configuration = serde_json::to_string(&export.configuration).unwrap();
+        bytes.extend(configuration.to_mmqp_binary().unwrap());
+        bytes.extend(b"|");
+        bytes.extend(export.messages.len().to_mmqp_binary().unwrap());
+
+        for (state, message) in export.messages.iter() {
+            bytes.extend(b"|");
+            bytes.push(state.code());
+            bytes.extend(message.serialise(SerialisationStrategy::Storage).iter());
+        }
+
+        bytes
+    }
+
+    fn deserialise_native(bytes: &[u8]) -> Result<QueueExport, String> {
+        if !bytes.starts_with(NATIVE_HEADER) {
+            return Err("not a native export".to_string());
+        }
+
+        // every length is checked before it is read so a truncated or corrupt file is an error, not a panic
+        let describe = |e: DecodeError| match e {
+            DecodeError::Truncated => "the export is truncated".to_string(),
+            DecodeError::Malformed => "the export is corrupt".to_string(),
+        };
+        let mut cursor = NATIVE_HEADER.len();
+
+        let configuration = String::try_from_mmqp_binary(bytes, &mut cursor).map_err(describe)?;
+        let configuration: QueueConfiguration =
+            serde_json::from_str(&configuration).map_err(|e| format!("configuration: {}", e))?;
+        cursor += 1;
+        let count = usize::try_from_mmqp_binary(bytes, &mut cursor).map_err(describe)?;
+
+        let mut messages = vec![];
+        for index in 0..count {
+            cursor += 1;
+            let state = BrowseState::from_code(
+                *bytes
+                    .get(cursor)
+                    .ok_or_else(|| describe(DecodeError::Truncated))?,
+            );
+            cursor += 1;
+            let message = MmqpNormalisedMessage::try_deserialise(bytes, &mut cursor)
+                .map_err(|e| format!("message {}: {}", index, describe(e)))?;
+            // exports carry bodies inline, a key into another server's blob directory is no use here
+            if message.offloaded {
+                return Err(format!(
+                    "message {} has no body in the export",
+                    message.message_id_string()
+                ));
+            }
+            messages.push((state, message));
+        }
+
+        Ok(QueueExport {
+            configuration,
+            messages,
+        })
+    }
+}
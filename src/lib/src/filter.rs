@@ -0,0 +1,481 @@
+pub mod filter {
+    use crate::attributes::attributes::{MmqpAttributeValue, MmqpAttributes};
+    use std::fmt;
+
+    #[test]
+    fn parse_and_match() {
+        let filter = parse_filter(r#"region = "eu" AND type IN ["order", "refund"]"#).unwrap();
+
+        let mut attributes = MmqpAttributes::new();
+        attributes.insert(
+            "region".to_string(),
+            MmqpAttributeValue::String("eu".to_string()),
+        );
+        attributes.insert(
+            "type".to_string(),
+            MmqpAttributeValue::String("refund".to_string()),
+        );
+        assert!(filter.matches("", &attributes));
+
+        attributes.insert(
+            "type".to_string(),
+            MmqpAttributeValue::String("invoice".to_string()),
+        );
+        assert!(!filter.matches("", &attributes));
+
+        // missing attributes never match, NOT turns that around
+        let filter = parse_filter("NOT (amount >= 100 OR group = 'vip')").unwrap();
+        assert!(filter.matches("mg1", &MmqpAttributes::new()));
+        attributes.insert("amount".to_string(), MmqpAttributeValue::Number(250));
+        assert!(!filter.matches("mg1", &attributes));
+        assert!(!filter.matches("vip", &MmqpAttributes::new()));
+
+        // numbers only compare with numbers
+        let filter = parse_filter("amount != -5 and amount not in [1, 2]").unwrap();
+        assert!(filter.matches("", &attributes));
+        let filter = parse_filter(r#"amount = "250""#).unwrap();
+        assert!(!filter.matches("", &attributes));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = parse_filter(r#"region = AND"#).unwrap_err();
+        assert_eq!(error.column, 10);
+        assert_eq!(
+            error.to_string(),
+            "expected a string or number, found `AND` at column 10"
+        );
+
+        let error = parse_filter(r#"type IN ["order" "refund"]"#).unwrap_err();
+        assert_eq!(error.column, 18);
+        assert!(error.message.contains("`,` or `]`"), "{}", error);
+
+        let error = parse_filter(r#"region = "eu"#).unwrap_err();
+        assert_eq!(error.column, 10);
+        assert_eq!(error.message, "unterminated string");
+
+        let error = parse_filter("(region = 'eu'").unwrap_err();
+        assert_eq!(error.message, "expected `)`, found the end of the filter");
+
+        let error = parse_filter("region = 'eu' & type = 'order'").unwrap_err();
+        assert_eq!(error.column, 15);
+        assert_eq!(error.message, "unexpected character `&`");
+
+        assert!(parse_filter("").is_err());
+        assert!(parse_filter("region = 'eu' type").is_err());
+    }
+
+    // a parsed filter expression, evaluated against the group and attributes of a message
+    //
+    // filter  := or
+    // or      := and (OR and)*
+    // and     := unary (AND unary)*
+    // unary   := NOT unary | primary
+    // primary := '(' filter ')' | field op value | field [NOT] IN '[' value (',' value)* ']'
+    //
+    // field is an attribute name, or `group` for the message group. values are "strings", 'strings'
+    // or whole numbers. keywords are not case sensitive
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum FilterExpr {
+        And(Box<FilterExpr>, Box<FilterExpr>),
+        Or(Box<FilterExpr>, Box<FilterExpr>),
+        Not(Box<FilterExpr>),
+        Compare(FilterField, FilterOp, FilterValue),
+        In(FilterField, Vec<FilterValue>),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum FilterField {
+        Group,
+        Attribute(String),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum FilterOp {
+        Eq,
+        Ne,
+        Lt,
+        Le,
+        Gt,
+        Ge,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum FilterValue {
+        String(String),
+        Number(i64),
+    }
+
+    // what went wrong and where. column counts characters from 1
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct FilterError {
+        pub column: usize,
+        pub message: String,
+    }
+
+    impl fmt::Display for FilterError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} at column {}", self.message, self.column)
+        }
+    }
+
+    impl std::error::Error for FilterError {}
+
+    impl FilterExpr {
+        pub fn matches(&self, group: &str, attributes: &MmqpAttributes) -> bool {
+            match self {
+                FilterExpr::And(a, b) => {
+                    a.matches(group, attributes) && b.matches(group, attributes)
+                }
+                FilterExpr::Or(a, b) => {
+                    a.matches(group, attributes) || b.matches(group, attributes)
+                }
+                FilterExpr::Not(a) => !a.matches(group, attributes),
+                FilterExpr::Compare(field, op, value) => {
+                    match field_ordering(field, value, group, attributes) {
+                        Some(ordering) => match op {
+                            FilterOp::Eq => ordering.is_eq(),
+                            FilterOp::Ne => ordering.is_ne(),
+                            FilterOp::Lt => ordering.is_lt(),
+                            FilterOp::Le => ordering.is_le(),
+                            FilterOp::Gt => ordering.is_gt(),
+                            FilterOp::Ge => ordering.is_ge(),
+                        },
+                        None => false,
+                    }
+                }
+                FilterExpr::In(field, values) => values.iter().any(|value| {
+                    field_ordering(field, value, group, attributes)
+                        .is_some_and(|ordering| ordering.is_eq())
+                }),
+            }
+        }
+    }
+
+    // how the field compares to value. None when the attribute is missing or has a different type,
+    // so such a comparison never matches
+    fn field_ordering(
+        field: &FilterField,
+        value: &FilterValue,
+        group: &str,
+        attributes: &MmqpAttributes,
+    ) -> Option<std::cmp::Ordering> {
+        match (field, value) {
+            (FilterField::Group, FilterValue::String(s)) => Some(group.cmp(s.as_str())),
+            (FilterField::Group, FilterValue::Number(_)) => None,
+            (FilterField::Attribute(name), value) => match (attributes.get(name)?, value) {
+                (MmqpAttributeValue::String(a), FilterValue::String(s)) => Some(a.cmp(s)),
+                (MmqpAttributeValue::Number(a), FilterValue::Number(n)) => Some(a.cmp(n)),
+                _ => None,
+            },
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Token {
+        Ident(String),
+        String(String),
+        Number(i64),
+        Op(FilterOp),
+        LParen,
+        RParen,
+        LBracket,
+        RBracket,
+        Comma,
+    }
+
+    impl fmt::Display for Token {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Token::Ident(s) => write!(f, "`{}`", s),
+                Token::String(s) => write!(f, "string \"{}\"", s),
+                Token::Number(n) => write!(f, "number {}", n),
+                Token::Op(op) => {
+                    let op = match op {
+                        FilterOp::Eq => "=",
+                        FilterOp::Ne => "!=",
+                        FilterOp::Lt => "<",
+                        FilterOp::Le => "<=",
+                        FilterOp::Gt => ">",
+                        FilterOp::Ge => ">=",
+                    };
+                    write!(f, "`{}`", op)
+                }
+                Token::LParen => write!(f, "`(`"),
+                Token::RParen => write!(f, "`)`"),
+                Token::LBracket => write!(f, "`[`"),
+                Token::RBracket => write!(f, "`]`"),
+                Token::Comma => write!(f, "`,`"),
+            }
+        }
+    }
+
+    fn error(column: usize, message: String) -> FilterError {
+        FilterError { column, message }
+    }
+
+    // tokens with the column they start at
+    fn tokenise(source: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut tokens = vec![];
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            let column = i + 1;
+
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+
+            let token = match c {
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '[' => Token::LBracket,
+                ']' => Token::RBracket,
+                ',' => Token::Comma,
+                '=' => Token::Op(FilterOp::Eq),
+                '!' if chars.get(i + 1) == Some(&'=') => {
+                    i += 1;
+                    Token::Op(FilterOp::Ne)
+                }
+                '<' | '>' => {
+                    let or_equal = chars.get(i + 1) == Some(&'=');
+                    if or_equal {
+                        i += 1;
+                    }
+                    Token::Op(match (c, or_equal) {
+                        ('<', false) => FilterOp::Lt,
+                        ('<', true) => FilterOp::Le,
+                        ('>', false) => FilterOp::Gt,
+                        _ => FilterOp::Ge,
+                    })
+                }
+                '"' | '\'' => {
+                    let mut value = String::new();
+                    i += 1;
+                    loop {
+                        match chars.get(i) {
+                            None => return Err(error(column, "unterminated string".to_string())),
+                            Some('\\') if i + 1 < chars.len() => {
+                                value.push(chars[i + 1]);
+                                i += 2;
+                            }
+                            Some(q) if *q == c => break,
+                            Some(other) => {
+                                value.push(*other);
+                                i += 1;
+                            }
+                        }
+                    }
+                    Token::String(value)
+                }
+                '-' | '0'..='9' => {
+                    let start = i;
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                    let text: String = chars[start..i].iter().collect();
+                    i -= 1;
+                    match text.parse::<i64>() {
+                        Ok(n) => Token::Number(n),
+                        Err(_) => return Err(error(column, format!("invalid number `{}`", text))),
+                    }
+                }
+                c if c.is_alphanumeric() || c == '_' => {
+                    let start = i;
+                    while i + 1 < chars.len()
+                        && (chars[i + 1].is_alphanumeric() || "_.-".contains(chars[i + 1]))
+                    {
+                        i += 1;
+                    }
+                    Token::Ident(chars[start..=i].iter().collect())
+                }
+                other => return Err(error(column, format!("unexpected character `{}`", other))),
+            };
+
+            tokens.push((column, token));
+            i += 1;
+        }
+
+        Ok(tokens)
+    }
+
+    struct Parser {
+        tokens: Vec<(usize, Token)>,
+        position: usize,
+        // column just past the last character, for errors at the end of the filter
+        end_column: usize,
+    }
+
+    impl Parser {
+        fn peek(&self) -> Option<&Token> {
+            self.tokens.get(self.position).map(|(_, token)| token)
+        }
+
+        fn column(&self) -> usize {
+            self.tokens
+                .get(self.position)
+                .map_or(self.end_column, |(column, _)| *column)
+        }
+
+        fn found(&self) -> String {
+            match self.peek() {
+                Some(token) => token.to_string(),
+                None => "the end of the filter".to_string(),
+            }
+        }
+
+        fn expected(&self, what: &str) -> FilterError {
+            error(
+                self.column(),
+                format!("expected {}, found {}", what, self.found()),
+            )
+        }
+
+        fn next(&mut self) -> Option<Token> {
+            let token = self
+                .tokens
+                .get(self.position)
+                .map(|(_, token)| token.clone());
+            self.position += 1;
+            token
+        }
+
+        fn keyword(&mut self, keyword: &str) -> bool {
+            match self.peek() {
+                Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword) => {
+                    self.position += 1;
+                    true
+                }
+                _ => false,
+            }
+        }
+
+        fn expect(&mut self, token: Token) -> Result<(), FilterError> {
+            if self.peek() == Some(&token) {
+                self.position += 1;
+                Ok(())
+            } else {
+                Err(self.expected(&token.to_string()))
+            }
+        }
+
+        fn or(&mut self) -> Result<FilterExpr, FilterError> {
+            let mut expr = self.and()?;
+            while self.keyword("OR") {
+                expr = FilterExpr::Or(Box::new(expr), Box::new(self.and()?));
+            }
+            Ok(expr)
+        }
+
+        fn and(&mut self) -> Result<FilterExpr, FilterError> {
+            let mut expr = self.unary()?;
+            while self.keyword("AND") {
+                expr = FilterExpr::And(Box::new(expr), Box::new(self.unary()?));
+            }
+            Ok(expr)
+        }
+
+        fn unary(&mut self) -> Result<FilterExpr, FilterError> {
+            if self.keyword("NOT") {
+                return Ok(FilterExpr::Not(Box::new(self.unary()?)));
+            }
+            self.primary()
+        }
+
+        fn primary(&mut self) -> Result<FilterExpr, FilterError> {
+            if self.peek() == Some(&Token::LParen) {
+                self.position += 1;
+                let expr = self.or()?;
+                self.expect(Token::RParen)?;
+                return Ok(expr);
+            }
+
+            let field = match self.peek() {
+                Some(Token::Ident(name)) if !is_keyword(name) => {
+                    if name.eq_ignore_ascii_case("group") {
+                        FilterField::Group
+                    } else {
+                        FilterField::Attribute(name.clone())
+                    }
+                }
+                _ => return Err(self.expected("an attribute name or `group`")),
+            };
+            self.position += 1;
+
+            if self.keyword("NOT") {
+                if !self.keyword("IN") {
+                    return Err(self.expected("`IN`"));
+                }
+                return Ok(FilterExpr::Not(Box::new(FilterExpr::In(
+                    field,
+                    self.list()?,
+                ))));
+            }
+            if self.keyword("IN") {
+                return Ok(FilterExpr::In(field, self.list()?));
+            }
+
+            match self.peek() {
+                Some(Token::Op(op)) => {
+                    let op = *op;
+                    self.position += 1;
+                    Ok(FilterExpr::Compare(field, op, self.value()?))
+                }
+                _ => Err(self.expected("a comparison (=, !=, <, <=, >, >=) or `IN`")),
+            }
+        }
+
+        fn list(&mut self) -> Result<Vec<FilterValue>, FilterError> {
+            self.expect(Token::LBracket)?;
+            let mut values = vec![self.value()?];
+            loop {
+                match self.peek() {
+                    Some(Token::Comma) => {
+                        self.position += 1;
+                        values.push(self.value()?);
+                    }
+                    Some(Token::RBracket) => {
+                        self.position += 1;
+                        return Ok(values);
+                    }
+                    _ => return Err(self.expected("`,` or `]`")),
+                }
+            }
+        }
+
+        fn value(&mut self) -> Result<FilterValue, FilterError> {
+            match self.peek() {
+                Some(Token::String(_)) | Some(Token::Number(_)) => match self.next() {
+                    Some(Token::String(s)) => Ok(FilterValue::String(s)),
+                    Some(Token::Number(n)) => Ok(FilterValue::Number(n)),
+                    _ => unreachable!(),
+                },
+                _ => Err(self.expected("a string or number")),
+            }
+        }
+    }
+
+    fn is_keyword(word: &str) -> bool {
+        ["AND", "OR", "NOT", "IN"]
+            .iter()
+            .any(|keyword| word.eq_ignore_ascii_case(keyword))
+    }
+
+    pub fn parse_filter(source: &str) -> Result<FilterExpr, FilterError> {
+        let mut parser = Parser {
+            tokens: tokenise(source)?,
+            position: 0,
+            end_column: source.chars().count() + 1,
+        };
+
+        let expr = parser.or()?;
+        if parser.peek().is_some() {
+            return Err(parser.expected("`AND`, `OR` or the end of the filter"));
+        }
+        Ok(expr)
+    }
+}
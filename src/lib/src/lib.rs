@@ -1,22 +1,36 @@
+pub mod admin;
+pub mod attributes;
+pub mod blob_store;
+pub mod browse;
 pub mod encoding;
+pub mod exchange;
+pub mod export;
+pub mod filter;
 pub mod memory_storage;
 pub mod normalised_message;
 pub mod page_io;
 pub mod queue;
+pub mod response;
 pub mod tcp_parse;
+pub mod topic;
+pub mod trace;
 pub mod tests;
 
 extern crate core;
 
+pub use crate::admin::admin::MmqpAdminCommand;
+pub use crate::attributes::attributes::{MmqpAttributeValue, MmqpAttributes};
 pub use crate::core::*;
 pub use crate::encoding::*;
 pub use crate::memory_storage::*;
 pub use crate::page_io::*;
 pub use crate::queue::queue::Queue;
+pub use crate::response::response::{MmqpEntryResult, MmqpResponseCode};
 pub use crate::serialiser::*;
 pub use crate::tcp_parse::*;
 
 pub mod serialiser {
+    use crate::attributes::attributes::MmqpAttributes;
     use crate::encoding::encoding::MmqpEncoding;
     use std::borrow::BorrowMut;
 
@@ -32,15 +46,62 @@ pub mod serialiser {
         fn get_size(&self) -> usize;
     }
 
-    #[derive(Debug, Clone, PartialEq, Eq)]
+    // marks how the body bytes should be interpreted. Written as a single byte directly before the body
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub enum MmqpBodyKind {
+        Text,
+        Binary,
+    }
+
+    impl MmqpBodyKind {
+        pub fn to_byte(&self) -> u8 {
+            match self {
+                MmqpBodyKind::Text => b'T',
+                MmqpBodyKind::Binary => b'B',
+            }
+        }
+
+        // None for any other byte, rather than guessing
+        pub fn from_byte(byte: u8) -> Option<MmqpBodyKind> {
+            match byte {
+                b'T' => Some(MmqpBodyKind::Text),
+                b'B' => Some(MmqpBodyKind::Binary),
+                _ => None,
+            }
+        }
+    }
+
+    // Debug is written out so the password never ends up in logs
+    #[derive(Clone, PartialEq, Eq)]
     pub struct MmqpMessage {
         pub version_major: u8,
         pub version_minor: u8,
         pub username: String,
         pub password: String,
         pub target_queue: String,
-        pub message: String,
+        pub body_kind: MmqpBodyKind,
+        pub message: Vec<u8>,
         pub message_group: String,
+        pub attributes: MmqpAttributes,
+        // 0 is the default, higher is more urgent. Only priority queues order by it
+        pub priority: u8,
+    }
+
+    impl std::fmt::Debug for MmqpMessage {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("MmqpMessage")
+                .field("version_major", &self.version_major)
+                .field("version_minor", &self.version_minor)
+                .field("username", &self.username)
+                .field("password", &"<redacted>")
+                .field("target_queue", &self.target_queue)
+                .field("body_kind", &self.body_kind)
+                .field("message", &self.message)
+                .field("message_group", &self.message_group)
+                .field("attributes", &self.attributes)
+                .field("priority", &self.priority)
+                .finish()
+        }
     }
 
     impl MmqpMessage {
@@ -48,13 +109,134 @@ pub mod serialiser {
             MmqpMessage {
                 password: "".to_string(),
                 username: "".to_string(),
-                message: "A message".to_string(),
+                body_kind: MmqpBodyKind::Text,
+                message: b"A message".to_vec(),
                 version_minor: 1,
                 version_major: 0,
                 message_group: "mg1".to_string(),
                 target_queue: "queue1".to_string(),
+                attributes: MmqpAttributes::new(),
+                priority: 0,
             }
         }
+
+        pub fn set_text(&mut self, text: &str) {
+            self.body_kind = MmqpBodyKind::Text;
+            self.message = text.as_bytes().to_vec();
+        }
+
+        pub fn set_binary(&mut self, bytes: Vec<u8>) {
+            self.body_kind = MmqpBodyKind::Binary;
+            self.message = bytes;
+        }
+
+        // MMQP|0.1|T|%username:%password|%topic|%messageGroupId|&attributes|priority|kind%message
+        // the same frame as a send with target_queue naming a topic, only the command differs
+        pub fn serialise_publish(&self) -> Box<[u8]> {
+            let mut message_binary = self.serialise(SerialisationStrategy::Wire);
+            message_binary[9] = b'T'; // MMQP|x.x|
+            message_binary
+        }
+
+        // MMQP|0.1|X|%username:%password|%routingKey|%exchange|%messageGroupId|&attributes|priority|kind%message
+        // a send with target_queue naming an exchange, preceded by the routing key
+        pub fn serialise_route(&self, routing_key: &str) -> Box<[u8]> {
+            let pipe = b"|";
+            let mut message_binary: Vec<u8> = Vec::new();
+            message_binary.extend("MMQP".as_bytes());
+            message_binary.extend(pipe);
+            message_binary.push(self.version_major);
+            message_binary.extend(".".as_bytes());
+            message_binary.push(self.version_minor);
+            message_binary.extend(pipe);
+            message_binary.extend(b"X");
+            message_binary.extend(pipe);
+            message_binary.extend(self.username.to_mmqp_binary().unwrap());
+            message_binary.extend(b":");
+            message_binary.extend(self.password.to_mmqp_binary().unwrap());
+            message_binary.extend(pipe);
+            message_binary.extend(routing_key.to_string().to_mmqp_binary().unwrap());
+            message_binary.extend(pipe);
+            self.serialise_fields(&mut message_binary);
+            message_binary.push(0x00);
+            message_binary.into_boxed_slice()
+        }
+
+        // %queue|%messageGroupId|&attributes|priority|kind%message
+        // everything after the auth section, shared by single and batched sends
+        fn serialise_fields(&self, message_binary: &mut Vec<u8>) {
+            let pipe = b"|";
+
+            message_binary.extend(self.target_queue.to_mmqp_binary().unwrap());
+
+            message_binary.extend(pipe);
+
+            message_binary.extend(self.message_group.to_mmqp_binary().unwrap());
+
+            message_binary.extend(pipe);
+
+            message_binary.extend(self.attributes.to_mmqp_binary().unwrap());
+
+            message_binary.extend(pipe);
+
+            message_binary.push(self.priority);
+
+            message_binary.extend(pipe);
+
+            message_binary.push(self.body_kind.to_byte());
+
+            message_binary.extend(self.message.to_mmqp_binary().unwrap());
+        }
+    }
+
+    // several messages sent in a single frame. Credentials are sent once for the whole batch
+    // and the target queue of each message is kept, so one batch can span many queues
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct MmqpBatch {
+        pub version_major: u8,
+        pub version_minor: u8,
+        pub username: String,
+        pub password: String,
+        pub messages: Vec<MmqpMessage>,
+    }
+
+    impl MmqpBatch {
+        pub fn new(username: String, password: String) -> MmqpBatch {
+            MmqpBatch {
+                version_major: 0,
+                version_minor: 1,
+                username,
+                password,
+                messages: vec![],
+            }
+        }
+
+        // MMQP|0.1|m|%username:%password|&count|entry|entry...
+        pub fn serialise(&self) -> Box<[u8]> {
+            let pipe = b"|";
+            let mut message_binary: Vec<u8> = Vec::new();
+            message_binary.extend("MMQP".as_bytes());
+            message_binary.extend(pipe);
+            message_binary.push(self.version_major);
+            message_binary.extend(".".as_bytes());
+            message_binary.push(self.version_minor);
+            message_binary.extend(pipe);
+            message_binary.extend(b"m");
+            message_binary.extend(pipe);
+            message_binary.extend(self.username.to_mmqp_binary().unwrap());
+            message_binary.extend(b":");
+            message_binary.extend(self.password.to_mmqp_binary().unwrap());
+            message_binary.extend(pipe);
+            message_binary.extend(self.messages.len().to_mmqp_binary().unwrap());
+
+            for message in self.messages.iter() {
+                message_binary.extend(pipe);
+                message.serialise_fields(&mut message_binary);
+            }
+
+            message_binary.push(0x00);
+            message_binary.into_boxed_slice()
+        }
     }
 
     impl MmqpSerialisable for MmqpMessage {
@@ -85,15 +267,7 @@ pub mod serialiser {
 
             message_binary.extend(pipe);
 
-            message_binary.extend(self.target_queue.to_mmqp_binary().unwrap());
-
-            message_binary.extend(pipe);
-
-            message_binary.extend(self.message_group.to_mmqp_binary().unwrap());
-
-            message_binary.extend(pipe);
-
-            message_binary.extend(self.message.to_mmqp_binary().unwrap());
+            self.serialise_fields(&mut message_binary);
 
             message_binary.push(0x00);
 
@@ -151,7 +325,17 @@ pub mod serialiser {
 
             cursor += 1; //pipe
 
-            let message = String::from_mmqp_binary(&message_binary, &mut cursor);
+            let attributes = MmqpAttributes::from_mmqp_binary(&message_binary, &mut cursor);
+
+            cursor += 1; //pipe
+
+            let priority = message_binary[cursor];
+            cursor += 2; //priority and pipe
+
+            let body_kind = MmqpBodyKind::from_byte(message_binary[cursor]).expect("malformed body kind");
+            cursor += 1;
+
+            let message = Vec::<u8>::from_mmqp_binary(&message_binary, &mut cursor);
             cursor += 1; //null
             *c = cursor;
             MmqpMessage {
@@ -160,8 +344,11 @@ pub mod serialiser {
                 username,
                 password,
                 target_queue,
+                body_kind,
                 message,
                 message_group,
+                attributes,
+                priority,
             }
         }
 
@@ -175,12 +362,13 @@ pub mod serialiser {
 
         fn get_size(&self) -> usize {
             let mut s = 0;
-            s += 16; // fixed length headers
+            s += 20; // fixed length headers, including the priority and body kind
 
             s += self.password.mmqp_binary_size();
             s += self.target_queue.mmqp_binary_size();
             s += self.username.mmqp_binary_size();
             s += self.message_group.mmqp_binary_size();
+            s += self.attributes.mmqp_binary_size();
             s += self.message.mmqp_binary_size();
 
             s
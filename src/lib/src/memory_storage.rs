@@ -18,6 +18,15 @@ pub mod queue_readers {
         // if the next buffer is the current read buffer, a new buffer will be created to hold the data
         pub current_write_buffer: usize,
         pub num_buffers: usize,
+
+        // the number of buffers asked for at creation. extra buffers added under load are released
+        // again by compact once everything has been read
+        configured_num_buffers: usize,
+
+        // total bytes ever read and pushed. A value's position is the number of bytes pushed before it,
+        // positions are never reused so they can be held on to as cursors (see peek_at)
+        read_position: u64,
+        write_position: u64,
     }
 
     pub struct ReadBuffer<T: MmqpSerialisable> {
@@ -40,27 +49,18 @@ pub mod queue_readers {
                 current_write_buffer: 0,
                 num_buffers,
                 buffer_size,
+                configured_num_buffers: num_buffers,
+                read_position: 0,
+                write_position: 0,
             }
         }
 
         pub fn push_value(&mut self, value: T) {
-            let mut buffer = self.buffers[self.current_write_buffer].lock().unwrap();
-            let size = value.get_size();
-            if buffer.has_capacity(size) {
-                buffer.push_value(value);
-            } else {
-                drop(buffer);
-                if self.next_write_buffer() == self.current_read_buffer {
-                    let mut buffer = self.add_and_go_next();
-                    buffer.push_value(value);
-                } else {
-                    let mut buffer = self.go_next();
-                    buffer.push_value(value);
-                }
-            }
+            self.push_raw(value.serialise(SerialisationStrategy::Storage));
         }
 
         pub fn push_raw(&mut self, value: Box<[u8]>) {
+            self.write_position += value.len() as u64;
             let mut buffer = self.buffers[self.current_write_buffer].lock().unwrap();
             let size = value.len();
             if buffer.has_capacity(size) {
@@ -77,21 +77,26 @@ pub mod queue_readers {
             }
         }
 
-        pub fn add_and_go_next(&mut self) -> MutexGuard<ReadBuffer<T>> {
+        pub fn add_and_go_next(&mut self) -> MutexGuard<'_, ReadBuffer<T>> {
+            // the new buffer goes directly after the write buffer so the read order is kept.
+            // if the reader is further along the vec it has to move up with its buffer
             self.num_buffers += 1;
             self.current_write_buffer += 1;
+            if self.current_read_buffer >= self.current_write_buffer {
+                self.current_read_buffer += 1;
+            }
             let new_buffer = Mutex::new(ReadBuffer::<T>::new(self.buffer_size));
-            self.buffers.push(new_buffer);
+            self.buffers.insert(self.current_write_buffer, new_buffer);
 
             self.get_writer()
         }
 
-        pub fn go_next(&mut self) -> MutexGuard<ReadBuffer<T>> {
+        pub fn go_next(&mut self) -> MutexGuard<'_, ReadBuffer<T>> {
             self.current_write_buffer = self.next_write_buffer();
             self.get_writer()
         }
 
-        pub fn get_writer(&mut self) -> MutexGuard<ReadBuffer<T>> {
+        pub fn get_writer(&mut self) -> MutexGuard<'_, ReadBuffer<T>> {
             self.buffers[self.current_write_buffer].lock().unwrap()
         }
 
@@ -116,8 +121,11 @@ pub mod queue_readers {
             let current_buffer = self.buffers[self.current_read_buffer].lock().unwrap();
             if current_buffer.cursor == current_buffer.buffer.len() {
                 drop(current_buffer);
+                if next == self.current_read_buffer {
+                    return false;
+                }
                 let next_buffer = self.buffers[next].lock().unwrap();
-                return if next_buffer.cursor != 0 {
+                return if next_buffer.cursor < next_buffer.buffer.len() {
                     drop(next_buffer);
                     true
                 } else {
@@ -128,19 +136,118 @@ pub mod queue_readers {
             true
         }
 
+        // bytes pushed that have not been read yet
+        pub fn buffered_bytes(&self) -> usize {
+            self.buffers
+                .iter()
+                .map(|buffer| {
+                    let buffer = buffer.lock().unwrap();
+                    buffer.buffer.len() - buffer.cursor
+                })
+                .sum()
+        }
+
+        // true when every value that was pushed has been read
+        pub fn is_empty(&self) -> bool {
+            self.buffers.iter().all(|buffer| {
+                let buffer = buffer.lock().unwrap();
+                buffer.cursor == buffer.buffer.len()
+            })
+        }
+
+        // drop everything that has been pushed, read or not, and release the memory
+        pub fn clear(&mut self) {
+            self.buffers.truncate(self.configured_num_buffers);
+            for buffer in self.buffers.iter_mut() {
+                *buffer = Mutex::new(ReadBuffer::new(self.buffer_size));
+            }
+            self.num_buffers = self.buffers.len();
+            self.current_read_buffer = 0;
+            self.current_read_buffer_cursor = 0;
+            self.current_write_buffer = 0;
+            self.read_position = self.write_position;
+        }
+
+        // position of the next value next() returns
+        pub fn read_position(&self) -> u64 {
+            self.read_position
+        }
+
+        // position the next pushed value gets
+        pub fn write_position(&self) -> u64 {
+            self.write_position
+        }
+
+        // read the value at position without consuming it, along with the position of the value after it.
+        // None if nothing has been pushed there yet or it has already been read.
+        // position has to be one returned by this, read_position or write_position
+        pub fn peek_at(&self, position: u64) -> Option<(T, u64)> {
+            if position < self.read_position || position >= self.write_position {
+                return None;
+            }
+
+            // unread bytes run from the read buffer's cursor through each following buffer to the writer
+            let mut start = self.read_position;
+            let mut index = self.current_read_buffer;
+            loop {
+                let mut buffer = self.buffers[index].lock().unwrap();
+                let unread = (buffer.buffer.len() - buffer.cursor) as u64;
+                if position < start + unread {
+                    let mut cursor = buffer.cursor + (position - start) as usize;
+                    let before = cursor;
+                    let value = T::deserialise(&mut buffer.buffer, &mut cursor);
+                    return Some((value, position + (cursor - before) as u64));
+                }
+                start += unread;
+
+                if index == self.current_write_buffer {
+                    return None;
+                }
+                index = (index + 1) % self.num_buffers;
+            }
+        }
+
+        // once drained, shrink back to the configured number of buffers and give back any memory
+        // buffers grew past their configured size (eg after a traffic spike or an oversized value).
+        // does nothing while there are values left to read
+        pub fn compact(&mut self) -> bool {
+            if !self.is_empty() {
+                return false;
+            }
+
+            let buffer_size = self.buffer_size;
+            let oversized = self
+                .buffers
+                .iter()
+                .any(|buffer| buffer.lock().unwrap().buffer.capacity() > buffer_size);
+
+            if self.buffers.len() <= self.configured_num_buffers && !oversized {
+                return false;
+            }
+
+            self.clear();
+            true
+        }
+
         pub fn next(&mut self) -> Option<T> {
             let mut buffer = self.buffers[self.current_read_buffer].lock().unwrap();
+            let before = buffer.cursor;
             let value = buffer.next();
 
             match value {
-                Some(val) => Some(val),
+                Some(val) => {
+                    self.read_position += (buffer.cursor - before) as u64;
+                    Some(val)
+                }
                 None => {
                     drop(buffer);
                     if self.has_next() {
                         self.current_read_buffer_cursor = 0;
                         self.current_read_buffer = self.next_read_buffer();
                         let mut buffer = self.buffers[self.current_read_buffer].lock().unwrap();
+                        let before = buffer.cursor;
                         let value = buffer.next();
+                        self.read_position += (buffer.cursor - before) as u64;
                         value
                     } else {
                         None
@@ -151,17 +258,23 @@ pub mod queue_readers {
 
         pub fn next_raw(&mut self) -> Option<Vec<u8>> {
             let mut buffer = self.buffers[self.current_read_buffer].lock().unwrap();
+            let before = buffer.cursor;
             let value = buffer.next_raw();
 
             match value {
-                Some(val) => Some(val),
+                Some(val) => {
+                    self.read_position += (buffer.cursor - before) as u64;
+                    Some(val)
+                }
                 None => {
                     drop(buffer);
                     if self.has_next() {
                         self.current_read_buffer_cursor = 0;
                         self.current_read_buffer = self.next_read_buffer();
                         let mut buffer = self.buffers[self.current_read_buffer].lock().unwrap();
+                        let before = buffer.cursor;
                         let value = buffer.next_raw();
+                        self.read_position += (buffer.cursor - before) as u64;
                         value
                     } else {
                         None
@@ -182,7 +295,7 @@ pub mod queue_readers {
 
         pub fn next(&mut self) -> Option<T> {
             //buffer is empty, return error
-            if self.buffer.len() == 0 {
+            if self.buffer.is_empty() {
                 return None;
             }
 
@@ -198,7 +311,7 @@ pub mod queue_readers {
 
         pub fn next_raw(&mut self) -> Option<Vec<u8>> {
             //buffer is empty, return error
-            if self.buffer.len() == 0 {
+            if self.buffer.is_empty() {
                 return None;
             }
 
@@ -1,18 +1,33 @@
 pub mod normalised_message {
-    use crate::encoding::encoding::MmqpEncoding;
-    use crate::serialiser::{MmqpMessage, MmqpSerialisable};
+    use crate::attributes::attributes::{MmqpAttributeValue, MmqpAttributes, TTL_ATTRIBUTE};
+    use crate::encoding::encoding::{DecodeError, MmqpEncoding};
+    use crate::serialiser::{MmqpBodyKind, MmqpMessage, MmqpSerialisable};
+    use rand::RngCore;
     use std::borrow::BorrowMut;
+    use std::io::{Read, Write};
     use std::time::SystemTime;
     use crate::SerialisationStrategy;
+    use crate::trace::trace::sanitise_trace_attributes;
 
+    // written before the body kind byte when the body is a blob store key
+    const OFFLOADED_MARKER: u8 = b'O';
+
+    #[derive(Debug, Clone)]
     pub struct MmqpNormalisedMessage {
         //original message field from Mmqp.message
-        pub message: String,
+        pub body_kind: MmqpBodyKind,
+        pub message: Vec<u8>,
+
+        // the body lives in the blob store and message holds its key. Only ever true inside the server,
+        // polled messages are written with the real body
+        pub offloaded: bool,
 
         //auto generated unique ID - 64 random bytes, will be sent as a base64 string
         pub message_id: [u8; 64],
 
         pub message_group_id: String,
+        pub attributes: MmqpAttributes,
+        pub priority: u8,
         pub received_time: u128,
         pub available_time: u128,
         pub receive_count: u32,
@@ -21,24 +36,145 @@ pub mod normalised_message {
     impl MmqpNormalisedMessage {
         pub fn new(message: MmqpMessage) -> MmqpNormalisedMessage {
             MmqpNormalisedMessage {
+                body_kind: message.body_kind,
                 message: message.message,
+                offloaded: false,
                 message_id: [0; 64],
                 message_group_id: message.message_group,
+                attributes: sanitise_trace_attributes(message.attributes),
+                priority: message.priority,
                 received_time: 0,
                 available_time: 0,
                 receive_count: 1,
             }
         }
-    }
 
-    impl MmqpSerialisable for MmqpNormalisedMessage {
-        fn serialise(&self, strategy:SerialisationStrategy) -> Box<[u8]> {
+        pub fn generate_message_id() -> [u8; 64] {
+            let mut message_id = [0u8; 64];
+            rand::thread_rng().fill_bytes(&mut message_id);
+            message_id
+        }
+
+        // the time (ms) this message expires, if at all. The shorter of the queue retention and the
+        // message's own ttl attribute wins. Both are measured from when the message was received
+        pub fn expiry_time(&self, retention: Option<u64>) -> Option<u128> {
+            let ttl = match self.attributes.get(TTL_ATTRIBUTE) {
+                Some(MmqpAttributeValue::Number(ttl)) if *ttl >= 0 => Some(*ttl as u64),
+                _ => None,
+            };
+
+            let lifetime = match (retention, ttl) {
+                (Some(retention), Some(ttl)) => Some(retention.min(ttl)),
+                (Some(retention), None) => Some(retention),
+                (None, ttl) => ttl,
+            };
+
+            lifetime.map(|lifetime| self.received_time + lifetime as u128)
+        }
+
+        // the form the id takes whenever it leaves the server or is used as a key
+        pub fn message_id_string(&self) -> String {
+            base64::encode(self.message_id)
+        }
+
+        // everything before the body: id, times, receive count, group, attributes and priority
+        fn serialise_head(&self) -> Vec<u8> {
             let mut message_binary: Vec<u8> = Vec::new();
             message_binary.extend(self.message_id);
             message_binary.extend(self.received_time.to_be_bytes());
             message_binary.extend(self.available_time.to_be_bytes());
             message_binary.extend(self.receive_count.to_be_bytes());
             message_binary.extend(self.message_group_id.to_mmqp_binary().unwrap());
+            message_binary.extend(self.attributes.to_mmqp_binary().unwrap());
+            message_binary.push(self.priority);
+            message_binary
+        }
+
+        // write the Storage form of this message with the body copied from body rather than held in memory.
+        // used to stream offloaded bodies back to a client, the output is the same as if the body had never been offloaded
+        pub fn write_with_body(
+            &self,
+            body: &mut impl Read,
+            body_len: usize,
+            out: &mut impl Write,
+        ) -> std::io::Result<()> {
+            let head = self.serialise_head();
+            let size = head.len() + 1 + body_len.mmqp_binary_size() + body_len + 1;
+
+            out.write_all(&size.to_mmqp_binary().unwrap())?;
+            out.write_all(&head)?;
+            out.write_all(&[self.body_kind.to_byte()])?;
+            out.write_all(&body_len.to_mmqp_binary().unwrap())?;
+            let copied = std::io::copy(&mut body.take(body_len as u64), out)?;
+            if copied != body_len as u64 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "message body shorter than expected",
+                ));
+            }
+            out.write_all(&[0x00])
+        }
+
+        // the Storage form of a message that may be corrupt, eg from an import file. Every field has to fit
+        // inside the record's own length, and the cursor is only moved when the whole record is read
+        pub fn try_deserialise(message_binary: &[u8], c: &mut usize) -> Result<Self, DecodeError> {
+            let mut cursor = *c;
+            let size = usize::try_from_mmqp_binary(message_binary, &mut cursor)?;
+            let end = cursor.checked_add(size).filter(|end| *end <= message_binary.len()).ok_or(DecodeError::Truncated)?;
+            let record = &message_binary[..end];
+
+            let message_id: [u8; 64] = take(record, &mut cursor, 64)?.try_into().unwrap();
+            let received_time = u128::from_be_bytes(take(record, &mut cursor, 16)?.try_into().unwrap());
+            let available_time = u128::from_be_bytes(take(record, &mut cursor, 16)?.try_into().unwrap());
+            let receive_count = u32::from_be_bytes(take(record, &mut cursor, 4)?.try_into().unwrap());
+
+            // a field running past the end of the record is corrupt rather than truncated
+            let corrupt = |_| DecodeError::Malformed;
+            let message_group_id = String::try_from_mmqp_binary(record, &mut cursor).map_err(corrupt)?;
+            let attributes = MmqpAttributes::try_from_mmqp_binary(record, &mut cursor).map_err(corrupt)?;
+            let priority = take(record, &mut cursor, 1)?[0];
+            let offloaded = record.get(cursor) == Some(&OFFLOADED_MARKER);
+            if offloaded {
+                cursor += 1;
+            }
+            let body_kind = MmqpBodyKind::from_byte(take(record, &mut cursor, 1)?[0]).ok_or(DecodeError::Malformed)?;
+            let message = Vec::<u8>::try_from_mmqp_binary(record, &mut cursor).map_err(corrupt)?;
+
+            // the record ends with 0x00 after the body
+            if cursor + 1 != end {
+                return Err(DecodeError::Malformed);
+            }
+            *c = end;
+
+            Ok(MmqpNormalisedMessage {
+                body_kind,
+                message,
+                offloaded,
+                message_id,
+                message_group_id,
+                attributes,
+                priority,
+                received_time,
+                available_time,
+                receive_count,
+            })
+        }
+    }
+
+    // len bytes of a record, Malformed when the record is shorter
+    fn take<'a>(record: &'a [u8], cursor: &mut usize, len: usize) -> Result<&'a [u8], DecodeError> {
+        let bytes = record.get(*cursor..*cursor + len).ok_or(DecodeError::Malformed)?;
+        *cursor += len;
+        Ok(bytes)
+    }
+
+    impl MmqpSerialisable for MmqpNormalisedMessage {
+        fn serialise(&self, strategy:SerialisationStrategy) -> Box<[u8]> {
+            let mut message_binary: Vec<u8> = self.serialise_head();
+            if self.offloaded {
+                message_binary.push(OFFLOADED_MARKER);
+            }
+            message_binary.push(self.body_kind.to_byte());
             message_binary.extend(self.message.to_mmqp_binary().unwrap());
             message_binary.push(0x00);
 
@@ -88,15 +224,28 @@ pub mod normalised_message {
             }
 
             let message_group_id = String::from_mmqp_binary(message_binary, &mut cursor);
-            let message = String::from_mmqp_binary(message_binary, &mut cursor);
+            let attributes = MmqpAttributes::from_mmqp_binary(message_binary, &mut cursor);
+            let priority = message_binary[cursor];
+            cursor += 1;
+            let offloaded = message_binary[cursor] == OFFLOADED_MARKER;
+            if offloaded {
+                cursor += 1;
+            }
+            let body_kind = MmqpBodyKind::from_byte(message_binary[cursor]).expect("malformed body kind");
+            cursor += 1;
+            let message = Vec::<u8>::from_mmqp_binary(message_binary, &mut cursor);
             cursor += 1;
 
             *c = cursor;
 
             MmqpNormalisedMessage {
+                body_kind,
                 message,
+                offloaded,
                 message_id: message_id_binary,
                 message_group_id,
+                attributes,
+                priority,
                 received_time: u128::from_be_bytes(received_time_bytes),
                 available_time: u128::from_be_bytes(available_time_bytes),
                 receive_count: u32::from_be_bytes(receive_count_bytes),
@@ -118,6 +267,12 @@ pub mod normalised_message {
             size += 16; // 128bit available time
             size += 32 / 8; // receive count
             size += self.message_group_id.mmqp_binary_size();
+            size += self.attributes.mmqp_binary_size();
+            size += 1; // priority
+            size += 1; // body kind
+            if self.offloaded {
+                size += 1; // offloaded marker
+            }
             size += self.message.mmqp_binary_size();
             size
         }
@@ -126,9 +281,16 @@ pub mod normalised_message {
     pub trait Receivable {
         fn normalise(&self) -> MmqpNormalisedMessage;
         fn normalise_serialised(&self) -> Box<[u8]>;
+
+        // the size of the body, so it can be checked before the message is copied by normalise
+        fn body_len(&self) -> usize;
     }
 
     impl Receivable for MmqpMessage {
+        fn body_len(&self) -> usize {
+            self.message.len()
+        }
+
         fn normalise(&self) -> MmqpNormalisedMessage {
             let current_time_ms = SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
@@ -136,11 +298,15 @@ pub mod normalised_message {
                 .as_millis();
 
             MmqpNormalisedMessage {
+                body_kind: self.body_kind,
                 message: self.message.clone(),
+                offloaded: false,
                 available_time: current_time_ms,
                 received_time: current_time_ms,
-                message_id: [0; 64], //todo: generate a unique ID
+                message_id: MmqpNormalisedMessage::generate_message_id(),
                 message_group_id: self.message_group.clone(),
+                attributes: sanitise_trace_attributes(self.attributes.clone()),
+                priority: self.priority,
 
                 //receive refers to how many times it has been sent to a client
                 //0 here because it has never been seent to a client
@@ -1,10 +1,15 @@
 pub mod queue {
+    use crate::blob_store::blob_store::BlobStore;
+    use crate::browse::browse::{BrowseEntry, BrowsePage, BrowsePosition, BrowseState};
+    use crate::encoding::encoding::MmqpEncoding;
+    use crate::filter::filter::{parse_filter, FilterError};
     use crate::memory_storage::queue_readers::RotatingReadBuffers;
     use crate::normalised_message::normalised_message::{MmqpNormalisedMessage, Receivable};
+    use crate::response::response::MmqpResponseCode;
     use crate::serialiser::MmqpSerialisable;
     use crate::SerialisationStrategy;
-    use std::borrow::{Borrow, BorrowMut};
     use std::collections::{BTreeMap, HashMap};
+    use std::fmt;
     use std::net::TcpStream;
     use std::time::SystemTime;
 
@@ -13,10 +18,918 @@ pub mod queue {
         println!("compiles");
     }
 
+    #[test]
+    fn delete_and_visibility() {
+        let mut queue = Queue::new(QueueConfiguration::new("queue1".to_string()));
+
+        let first = queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        let second = queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        assert_ne!(first, second, "message ids should be unique");
+
+        let read = queue.read_next().unwrap();
+        assert_eq!(read.message_id_string(), first);
+        assert_eq!(read.receive_count, 1);
+        assert!(queue.pending_sent().contains_key(&first));
+
+        assert!(queue.delete_message(&first));
+        assert!(!queue.delete_message(&first), "already deleted");
+
+        // hidden while in flight, visible again once its timeout is set to the past
+        let read = queue.read_next().unwrap();
+        assert_eq!(read.message_id_string(), second);
+        assert!(queue.read_next().is_none());
+
+        assert!(queue.change_visibility(&second, 0));
+        let read = queue.read_next().unwrap();
+        assert_eq!(read.message_id_string(), second);
+        assert_eq!(read.receive_count, 2);
+
+        assert!(!queue.change_visibility("not a message id", 1000));
+    }
+
+    #[test]
+    fn visibility_deadlines() {
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.visibility_timeout = 20;
+        let mut queue = Queue::new(config);
+
+        let first = queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        let second = queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        queue.read_next().unwrap();
+        queue.read_next().unwrap();
+
+        // only the message whose lease was not extended comes back once the default timeout passes
+        assert!(queue.change_visibility(&second, 60_000));
+        std::thread::sleep(std::time::Duration::from_millis(30));
+        let read = queue.read_next().unwrap();
+        assert_eq!(read.message_id_string(), first);
+        assert!(queue.read_next().is_none());
+
+        assert!(queue.delete_message(&second));
+        assert!(queue.delete_message(&first));
+        assert!(queue.pending_sent().is_empty());
+    }
+
+    #[test]
+    fn heartbeat_and_negative_ack() {
+        let mut queue = Queue::new(QueueConfiguration::new("queue1".to_string()));
+
+        let id = queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        queue.read_next().unwrap();
+
+        assert!(queue.change_visibility(&id, 60_000));
+        assert!(
+            queue.pending_sent()[&id].available_time
+                > current_time_ms() + queue.visibility_timeout() as u128,
+            "lease should have been extended past the queue default"
+        );
+        assert!(queue.read_next().is_none());
+        assert!(queue.pending_sent().contains_key(&id));
+
+        assert!(queue.change_visibility(&id, 0));
+        assert!(!queue.pending_sent().contains_key(&id));
+        assert_eq!(queue.pending_received().len(), 1);
+
+        let read = queue.read_next().unwrap();
+        assert_eq!(read.message_id_string(), id);
+        assert_eq!(read.receive_count, 2);
+    }
+
+    #[test]
+    fn expiry() {
+        use crate::attributes::attributes::{MmqpAttributeValue, TTL_ATTRIBUTE};
+
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.retention = Some(60_000);
+        let mut queue = Queue::new(config);
+
+        let kept = queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        let mut short_lived = crate::MmqpMessage::new();
+        short_lived
+            .attributes
+            .insert(TTL_ATTRIBUTE.to_string(), MmqpAttributeValue::Number(0));
+        let expired_id = queue.receive_message(short_lived).unwrap();
+
+        let expired = queue.expire_messages();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].message_id_string(), expired_id);
+        assert_eq!(queue.expired_message_count(), 1);
+        assert_eq!(queue.approximate_message_count(), 1);
+
+        assert!(queue.expire_messages().is_empty(), "nothing else is due");
+
+        let read = queue.read_next().unwrap();
+        assert_eq!(read.message_id_string(), kept);
+        assert!(queue.read_next().is_none());
+
+        // a returned or delayed message that expired while it waited is not handed out either
+        let mut returned = crate::MmqpMessage::new();
+        returned
+            .attributes
+            .insert(TTL_ATTRIBUTE.to_string(), MmqpAttributeValue::Number(0));
+        let returned = returned.normalise();
+        let returned_id = returned.message_id_string();
+        queue
+            .import_message(BrowseState::Delayed, returned)
+            .unwrap();
+        assert!(queue.read_next().is_none());
+        assert!(queue.pending_received().is_empty());
+        let expired = queue.expire_messages();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].message_id_string(), returned_id);
+    }
+
+    #[test]
+    fn purge() {
+        let mut queue = Queue::new(QueueConfiguration::new("queue1".to_string()));
+
+        for _ in 0..3 {
+            queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        }
+        let in_flight = queue.read_next().unwrap().message_id_string();
+
+        assert_eq!(queue.purge(), 3);
+        assert!(queue.read_next().is_none());
+        assert!(!queue.delete_message(&in_flight));
+
+        queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        assert!(queue.read_next().is_some(), "queue is usable after a purge");
+    }
+
+    #[test]
+    fn back_pressure() {
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.buffer_count = 2;
+        config.buffer_size = 1024;
+        config.max_buffered_bytes = Some(1024);
+        let mut queue = Queue::new(config);
+
+        let mut accepted = 0;
+        loop {
+            match queue.receive_message(crate::MmqpMessage::new()) {
+                Ok(_) => accepted += 1,
+                Err(code) => {
+                    assert_eq!(code, MmqpResponseCode::QueueFull);
+                    break;
+                }
+            }
+        }
+        assert!(accepted > 0);
+        assert!(queue.buffered_bytes() <= 1024);
+
+        // reading makes room again
+        queue.read_next().unwrap();
+        assert!(queue.receive_message(crate::MmqpMessage::new()).is_ok());
+
+        // imports are held to the same limits
+        let mut config = QueueConfiguration::new("queue2".to_string());
+        config.max_message_size = Some(4);
+        config.max_buffered_bytes = Some(1024);
+        let mut imported = Queue::new(config);
+        let mut message = crate::MmqpMessage::new();
+        message.set_text("too large");
+        assert_eq!(
+            imported.import_message(BrowseState::Ready, message.normalise()),
+            Err(MmqpResponseCode::MessageTooLarge)
+        );
+        message.set_text("fits");
+        let mut accepted = 0;
+        while imported
+            .import_message(BrowseState::Ready, message.normalise())
+            .is_ok()
+        {
+            accepted += 1;
+        }
+        assert!(accepted > 0);
+        assert!(imported.buffered_bytes() <= 1024);
+
+        // so are dead lettered and transferred messages, which are handed back when they are rejected
+        let normalised = message.normalise();
+        let (code, rejected) = imported.receive_normalised(normalised.clone()).unwrap_err();
+        assert_eq!(code, MmqpResponseCode::QueueFull);
+        assert_eq!(rejected.message_id, normalised.message_id);
+        imported.read_next().unwrap();
+        assert!(imported.receive_normalised(normalised).is_ok());
+    }
+
+    #[test]
+    fn offloaded_bodies() {
+        let dir = std::env::temp_dir().join(format!("mmqp-queue-blobs-{}", std::process::id()));
+        let blobs = BlobStore::new(&dir).unwrap();
+
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.offload_threshold = Some(16);
+        let mut queue = Queue::new(config);
+        queue.set_blob_store(blobs.clone());
+
+        let mut message = crate::MmqpMessage::new();
+        message.set_text("small");
+        queue.receive_message(message.clone()).unwrap();
+        message.set_text("a body over the sixteen byte threshold");
+        queue.receive_message(message).unwrap();
+
+        assert!(!queue.read_next().unwrap().offloaded);
+        let large = queue.read_next().unwrap();
+        assert!(large.offloaded);
+        assert!(blobs.open(&large).is_ok());
+
+        // deleting the message removes its body
+        assert!(queue.delete_message(&large.message_id_string()));
+        assert!(blobs.open(&large).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn priority_order() {
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.queue_type = QueueType::Priority;
+        config.priority_levels = 3;
+        let mut queue = Queue::new(config);
+
+        // 9 is past the top level and shares it with 2
+        for (priority, text) in [(0, "bulk"), (9, "urgent"), (1, "normal"), (2, "urgent2")] {
+            let mut message = crate::MmqpMessage::new();
+            message.priority = priority;
+            message.set_text(text);
+            queue.receive_message(message).unwrap();
+        }
+
+        let order: Vec<Vec<u8>> = (0..4).map(|_| queue.read_next().unwrap().message).collect();
+        assert_eq!(
+            order,
+            vec![
+                b"urgent".to_vec(),
+                b"urgent2".to_vec(),
+                b"normal".to_vec(),
+                b"bulk".to_vec()
+            ]
+        );
+        assert!(queue.read_next().is_none());
+
+        // a returned message goes back into its own level rather than ahead of the others
+        let mut bulk = crate::MmqpMessage::new();
+        bulk.set_text("bulk");
+        let bulk_id = queue.receive_message(bulk).unwrap();
+        queue.read_next().unwrap();
+        assert!(queue.negative_ack(&bulk_id));
+        let mut urgent = crate::MmqpMessage::new();
+        urgent.priority = 2;
+        urgent.set_text("urgent");
+        queue.receive_message(urgent).unwrap();
+
+        assert_eq!(queue.read_next().unwrap().message, b"urgent".to_vec());
+        assert_eq!(queue.read_next().unwrap().message_id_string(), bulk_id);
+        assert!(queue.read_next().is_none());
+    }
+
+    #[test]
+    fn pending_mode() {
+        for (mode, expected) in [
+            (PendingMode::Read, ["returned", "first", "second"]),
+            (PendingMode::Push, ["first", "second", "returned"]),
+        ] {
+            let mut config = QueueConfiguration::new("queue1".to_string());
+            config.pending_mode = mode;
+            let mut queue = Queue::new(config);
+
+            let mut returned = crate::MmqpMessage::new();
+            returned.set_text("returned");
+            let returned_id = queue.receive_message(returned).unwrap();
+            queue.read_next().unwrap();
+            for text in ["first", "second"] {
+                let mut message = crate::MmqpMessage::new();
+                message.set_text(text);
+                queue.receive_message(message).unwrap();
+            }
+            assert!(queue.negative_ack(&returned_id));
+
+            let order: Vec<Vec<u8>> = (0..3).map(|_| queue.read_next().unwrap().message).collect();
+            assert_eq!(
+                order,
+                expected.map(|text| text.as_bytes().to_vec()),
+                "{:?}",
+                mode
+            );
+            assert!(queue.read_next().is_none());
+        }
+    }
+
+    #[test]
+    fn priority_weights() {
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.queue_type = QueueType::Priority;
+        config.priority_levels = 2;
+        config.priority_weights = Some(vec![1, 3]);
+        let mut queue = Queue::new(config);
+
+        for priority in [0, 0, 1, 1, 1, 1, 1, 1] {
+            let mut message = crate::MmqpMessage::new();
+            message.priority = priority;
+            queue.receive_message(message).unwrap();
+        }
+
+        // three urgent messages for every bulk one, then whatever is left
+        let order: Vec<u8> = (0..8)
+            .map(|_| queue.read_next().unwrap().priority)
+            .collect();
+        assert_eq!(order, vec![1, 1, 1, 0, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn max_message_size() {
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.max_message_size = Some(8);
+        let mut queue = Queue::new(config);
+
+        let mut message = crate::MmqpMessage::new();
+        message.set_text("12345678");
+        assert!(queue.receive_message(message.clone()).is_ok());
+
+        message.set_text("123456789");
+        assert_eq!(
+            queue.receive_message(message).unwrap_err(),
+            MmqpResponseCode::MessageTooLarge
+        );
+    }
+
+    #[test]
+    fn log_consumer_groups() {
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.queue_type = QueueType::Log;
+        config.consumer_groups = vec!["audit".to_string(), "billing".to_string()];
+        let mut queue = Queue::new(config);
+
+        for i in 0..3 {
+            let mut message = crate::MmqpMessage::new();
+            message.set_text(&i.to_string());
+            queue.receive_message(message).unwrap();
+        }
+
+        // every group reads every message, at its own pace
+        assert_eq!(queue.read_group("billing").unwrap().message, b"0".to_vec());
+        assert_eq!(queue.read_group("billing").unwrap().message, b"1".to_vec());
+        assert_eq!(queue.read_group("audit").unwrap().message, b"0".to_vec());
+        assert!(queue.pending_sent().is_empty(), "nothing to delete");
+
+        // only what both groups have read is dropped
+        assert_eq!(queue.approximate_message_count(), 2);
+        assert_eq!(queue.read_group("audit").unwrap().message, b"1".to_vec());
+        assert_eq!(queue.approximate_message_count(), 1);
+
+        // a removed group no longer holds messages back
+        assert!(queue.remove_consumer_group("audit"));
+        assert_eq!(queue.read_group("billing").unwrap().message, b"2".to_vec());
+        assert!(queue.read_group("billing").is_none());
+        assert_eq!(queue.approximate_message_count(), 0);
+
+        // a new group starts at the oldest message still kept
+        let mut message = crate::MmqpMessage::new();
+        message.set_text("3");
+        queue.receive_message(message).unwrap();
+        assert_eq!(queue.read_group("late").unwrap().message, b"3".to_vec());
+        assert_eq!(
+            queue.configuration().consumer_groups,
+            vec!["billing", "late"]
+        );
+    }
+
+    #[test]
+    fn log_seek() {
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.queue_type = QueueType::Log;
+        // with a retention messages are kept after every group has read them
+        config.retention = Some(60_000);
+        let mut queue = Queue::new(config);
+
+        for i in 0..5 {
+            let mut message = crate::MmqpMessage::new();
+            message.set_text(&i.to_string());
+            queue.receive_message(message).unwrap();
+        }
+        let received = queue.readers()[0].peek_at(0).unwrap().0.received_time;
+        while queue.read_group("billing").is_some() {}
+        assert_eq!(queue.first_offset(), 0);
+        assert_eq!(queue.end_offset(), 5);
+
+        assert_eq!(queue.seek("billing", SeekTarget::Offset(3)), Some(3));
+        assert_eq!(queue.read_group("billing").unwrap().message, b"3".to_vec());
+
+        // past the end waits for the next message
+        assert_eq!(queue.seek("billing", SeekTarget::Offset(100)), Some(5));
+        assert!(queue.read_group("billing").is_none());
+
+        assert_eq!(queue.seek("billing", SeekTarget::Time(received)), Some(0));
+        assert_eq!(queue.read_group("billing").unwrap().message, b"0".to_vec());
+        assert_eq!(
+            queue.seek("billing", SeekTarget::Time(received + 1_000_000)),
+            Some(5)
+        );
+
+        let mut queue = Queue::new(QueueConfiguration::new("queue2".to_string()));
+        assert_eq!(queue.seek("billing", SeekTarget::Offset(0)), None);
+    }
+
+    #[test]
+    fn log_retention_expiry() {
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.queue_type = QueueType::Log;
+        config.retention = Some(50);
+        config.dead_letter_queue = Some("dlq".to_string());
+        let mut queue = Queue::new(config);
+        let mut dlq = Queue::new(QueueConfiguration::new("dlq".to_string()));
+
+        for _ in 0..3 {
+            queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        }
+        while queue.read_group("billing").is_some() {}
+        std::thread::sleep(std::time::Duration::from_millis(60));
+
+        // as the sweeper does it. billing read everything, so nothing is dead lettered
+        let sweep = |queue: &mut Queue, dlq: &mut Queue| {
+            for message in queue.expire_messages() {
+                dlq.receive_normalised(message).unwrap();
+            }
+        };
+        sweep(&mut queue, &mut dlq);
+        assert_eq!(queue.expired_message_count(), 0);
+        assert_eq!(queue.approximate_message_count(), 0);
+        assert!(dlq.is_empty());
+
+        // a message billing never read still is
+        let unread = queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(60));
+        sweep(&mut queue, &mut dlq);
+        assert_eq!(queue.expired_message_count(), 1);
+        assert_eq!(dlq.read_next().unwrap().message_id_string(), unread);
+        assert!(dlq.read_next().is_none());
+    }
+
+    #[test]
+    fn browse_without_consuming() {
+        let mut config = QueueConfiguration::new("queue1".to_string());
+        config.queue_type = QueueType::Priority;
+        config.priority_levels = 2;
+        let mut queue = Queue::new(config);
+
+        for priority in [0, 1, 0, 1] {
+            let mut message = crate::MmqpMessage::new();
+            message.priority = priority;
+            message.set_text("body");
+            queue.receive_message(message).unwrap();
+        }
+        let polled = queue.read_next().unwrap();
+
+        // pages follow the poll order, the polled message is listed last as in flight
+        let first = queue.browse(BrowsePosition::default(), 2);
+        assert_eq!(first.entries.len(), 2);
+        assert!(first.entries.iter().all(|e| e.state == BrowseState::Ready));
+        assert_eq!(first.entries[0].priority, 1);
+        assert_eq!(first.entries[1].priority, 0);
+        assert_eq!(first.entries[0].size, 4);
+
+        let second = queue.browse(first.next.unwrap(), 2);
+        assert_eq!(second.entries.len(), 2);
+        assert_eq!(second.entries[1].message_id, polled.message_id_string());
+        assert_eq!(second.entries[1].state, BrowseState::InFlight);
+        assert_eq!(second.entries[1].receive_count, 1);
+
+        let end = queue.browse(second.next.unwrap(), 2);
+        assert!(end.entries.is_empty());
+        assert!(end.next.is_none());
+
+        // browsing took nothing
+        let mut read = 0;
+        while let Some(message) = queue.read_next() {
+            assert_eq!(message.receive_count, 1);
+            read += 1;
+        }
+        assert_eq!(read, 3);
+    }
+
+    #[test]
+    fn copy_and_move_selections() {
+        use crate::attributes::attributes::MmqpAttributeValue;
+
+        let mut queue = Queue::new(QueueConfiguration::new("queue1".to_string()));
+        let mut ids = vec![];
+        for region in ["eu", "us", "eu"] {
+            let mut message = crate::MmqpMessage::new();
+            message.attributes.insert(
+                "region".to_string(),
+                MmqpAttributeValue::String(region.to_string()),
+            );
+            ids.push(queue.receive_message(message).unwrap());
+        }
+
+        let eu = MessageSelection::Filter(r#"region = "eu""#.to_string());
+        let copies = queue.copy_messages(eu.matcher().unwrap());
+        assert_eq!(copies.len(), 2);
+        assert_eq!(copies[1].message_id_string(), ids[2]);
+        assert_eq!(queue.approximate_message_count(), 3, "copying leaves them");
+
+        let one = MessageSelection::Ids(vec![ids[1].clone()]);
+        let matcher = one.matcher().unwrap();
+        let moved = queue.retain_messages(|message| !matcher(message));
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].message_id_string(), ids[1]);
+        assert_eq!(queue.approximate_message_count(), 2);
+
+        assert!(MessageSelection::All.matcher().unwrap()(&moved[0]));
+        assert!(MessageSelection::Filter("region =".to_string())
+            .matcher()
+            .is_err());
+    }
+
+    #[test]
+    fn export_and_import() {
+        let mut queue = Queue::new(QueueConfiguration::new("queue1".to_string()));
+        for _ in 0..3 {
+            queue.receive_message(crate::MmqpMessage::new()).unwrap();
+        }
+        let polled = queue.read_next().unwrap();
+        let mut delayed = crate::MmqpMessage::new();
+        delayed.set_text("later");
+        let delayed =
+            crate::normalised_message::normalised_message::Receivable::normalise(&delayed);
+        queue.import_message(BrowseState::Delayed, delayed).unwrap();
+
+        let exported = queue.export_messages();
+        let states: Vec<BrowseState> = exported.iter().map(|(state, _)| *state).collect();
+        assert_eq!(
+            states,
+            vec![
+                BrowseState::Ready,
+                BrowseState::Ready,
+                BrowseState::Delayed,
+                BrowseState::InFlight
+            ]
+        );
+        assert_eq!(queue.copy_messages(|_| true).len(), 3);
+
+        let mut imported = Queue::new(queue.configuration());
+        for (state, message) in exported.into_iter() {
+            imported.import_message(state, message).unwrap();
+        }
+        assert_eq!(imported.approximate_message_count(), 2);
+        assert!(imported
+            .pending_sent()
+            .contains_key(&polled.message_id_string()));
+        assert_eq!(imported.pending_received().len(), 1);
+    }
+
+    #[test]
+    fn reconfigure_pending_mode() {
+        let mut queue = Queue::new(QueueConfiguration::new("queue1".to_string()));
+        let mut returned = crate::MmqpMessage::new();
+        returned.set_text("returned");
+        let returned_id = queue.receive_message(returned).unwrap();
+        queue.read_next().unwrap();
+        let mut first = crate::MmqpMessage::new();
+        first.set_text("first");
+        queue.receive_message(first).unwrap();
+
+        // Read: the returned message is polled ahead of the one waiting
+        assert!(queue.negative_ack(&returned_id));
+        assert_eq!(queue.read_next().unwrap().message_id_string(), returned_id);
+
+        // Push after a reload: it goes behind it
+        let mut config = queue.configuration();
+        config.pending_mode = PendingMode::Push;
+        assert!(queue.reconfigure(config).is_empty());
+        assert_eq!(queue.pending_mode(), PendingMode::Push);
+        assert!(queue.negative_ack(&returned_id));
+        assert_eq!(queue.read_next().unwrap().message, b"first".to_vec());
+        assert_eq!(queue.read_next().unwrap().message_id_string(), returned_id);
+    }
+
+    #[test]
+    fn reconfigure_keeps_messages() {
+        let mut queue = Queue::new(QueueConfiguration::new("queue1".to_string()));
+        queue.receive_message(crate::MmqpMessage::new()).unwrap();
+
+        let mut config = queue.configuration();
+        config.visibility_timeout = 5_000;
+        config.max_message_size = Some(10);
+        assert!(queue.reconfigure(config.clone()).is_empty());
+        assert_eq!(queue.configuration().visibility_timeout, 5_000);
+        assert_eq!(queue.approximate_message_count(), 1);
+
+        config.buffer_size = 1024;
+        config.queue_type = QueueType::Log;
+        assert_eq!(queue.reconfigure(config), vec!["queue_type", "buffer_size"]);
+        assert_eq!(queue.queue_type(), QueueType::Standard);
+
+        queue.set_retired(true);
+        assert_eq!(
+            queue.receive_message(crate::MmqpMessage::new()),
+            Err(MmqpResponseCode::QueueRetired)
+        );
+        assert!(queue.read_next().is_some());
+        assert!(!queue.is_empty());
+        queue.purge();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn config_validation() {
+        let errors = |json: &str| -> Vec<String> {
+            match Queue::deserialise_config_array(json.to_string()) {
+                Ok(_) => vec![],
+                Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+            }
+        };
+
+        assert!(errors("").is_empty());
+        assert!(errors(
+            r#"[{"name":"q","pending_mode":"Read","dead_letter_queue":"dlq"},
+                {"name":"dlq","pending_mode":"Read"}]"#
+        )
+        .is_empty());
+
+        assert_eq!(
+            errors(
+                r#"[{"name":"q","pending_mode":"Read"},{"name":"q2","pending_mode":"Sideways"}]"#
+            )[0]
+            .split(':')
+            .next(),
+            Some("[1].pending_mode")
+        );
+        assert_eq!(
+            errors(r#"[{"name":"q","pending_mode":"Read","buffer_size":"big"}]"#)[0]
+                .split(':')
+                .next(),
+            Some("[0].buffer_size")
+        );
+        assert_eq!(
+            errors(
+                r#"[{"name":"q","pending_mode":"Read","dead_letter_queue":"missing","buffer_count":0},
+                    {"name":"q","pending_mode":"Read","dead_letter_queue":"q"},
+                    {"name":"p","pending_mode":"Read","queue_type":"Priority","priority_levels":2,
+                     "priority_weights":[1,0,1]}]"#
+            ),
+            vec![
+                "[0].buffer_count: must be at least 1",
+                "[1].name: q is already the name of [0]",
+                "[2].priority_weights: has 3 weights for 2 priority levels",
+                "[2].priority_weights[1]: must be at least 1",
+                "[0].dead_letter_queue: there is no queue named missing",
+                "[1].dead_letter_queue: a queue cannot be its own dead letter queue",
+            ]
+        );
+
+        assert!(Queue::deserialise_config(r#"{"name":"q","pending_mode":"Read"}"#).is_ok());
+        assert_eq!(
+            Queue::deserialise_config(r#"{"name":"q","pending_mode":"Read","retention":0}"#)
+                .unwrap_err()
+                .path,
+            "retention"
+        );
+    }
+
     #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
     pub struct QueueConfiguration {
+        // a missing name is reported by validate_configurations, so templates can leave it out
+        #[serde(default)]
         pub name: String,
         pub pending_mode: PendingMode,
+
+        // how long (ms) a polled message stays hidden from other consumers before it is returned to the queue
+        #[serde(default = "default_visibility_timeout")]
+        pub visibility_timeout: u64,
+
+        // how long (ms) a message is kept after it is received. None keeps messages until they are deleted.
+        // messages can ask for a shorter lifetime with the mmqp.ttl attribute
+        #[serde(default)]
+        pub retention: Option<u64>,
+
+        // expired messages are moved to this queue instead of being dropped
+        #[serde(default)]
+        pub dead_letter_queue: Option<String>,
+
+        // read buffer geometry. the queue starts with buffer_count buffers of buffer_size bytes and
+        // adds more when they fill faster than they are read
+        #[serde(default = "default_buffer_count")]
+        pub buffer_count: usize,
+        #[serde(default = "default_buffer_size")]
+        pub buffer_size: usize,
+
+        // hard limit on the bytes waiting to be read. sends are rejected with QUEUE_FULL once it is reached
+        // None lets the buffers grow without limit
+        #[serde(default)]
+        pub max_buffered_bytes: Option<usize>,
+
+        // largest message body in bytes this queue accepts, on top of the server wide limit
+        // sends over it are rejected with MESSAGE_TOO_LARGE
+        #[serde(default)]
+        pub max_message_size: Option<usize>,
+
+        // bodies larger than this many bytes are kept in the blob store under the page dir and only a
+        // reference goes into the read buffers. Ignored when the server has no page dir
+        #[serde(default)]
+        pub offload_threshold: Option<usize>,
+
+        // Priority queues keep one set of read buffers per priority level and read the highest level first.
+        // message priorities above the top level share the top level
+        #[serde(default)]
+        pub queue_type: QueueType,
+        #[serde(default = "default_priority_levels")]
+        pub priority_levels: usize,
+
+        // messages read from each level (index = priority) per round before lower levels get a turn, so
+        // bulk traffic is not starved by a steady stream of urgent messages. None reads strictly by priority
+        #[serde(default)]
+        pub priority_weights: Option<Vec<u32>>,
+
+        // Log queues only. groups that exist from startup, so messages are kept for them before their first
+        // poll. Other groups are created by polling with their name
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub consumer_groups: Vec<String>,
+    }
+
+    fn default_visibility_timeout() -> u64 {
+        30_000
+    }
+
+    fn default_buffer_count() -> usize {
+        16
+    }
+
+    fn default_buffer_size() -> usize {
+        65536
+    }
+
+    fn default_priority_levels() -> usize {
+        4
+    }
+
+    impl QueueConfiguration {
+        pub fn new(name: String) -> QueueConfiguration {
+            QueueConfiguration {
+                name,
+                pending_mode: PendingMode::Read,
+                visibility_timeout: default_visibility_timeout(),
+                retention: None,
+                dead_letter_queue: None,
+                buffer_count: default_buffer_count(),
+                buffer_size: default_buffer_size(),
+                max_buffered_bytes: None,
+                max_message_size: None,
+                offload_threshold: None,
+                queue_type: QueueType::Standard,
+                priority_levels: default_priority_levels(),
+                priority_weights: None,
+                consumer_groups: vec![],
+            }
+        }
+    }
+
+    // a problem with the queue config file. path is where it is, eg [2].dead_letter_queue for the dead letter
+    // queue of the third queue, or empty when the file as a whole is unusable
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ConfigError {
+        pub path: String,
+        pub message: String,
+    }
+
+    impl fmt::Display for ConfigError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if self.path.is_empty() {
+                write!(f, "{}", self.message)
+            } else {
+                write!(f, "{}: {}", self.path, self.message)
+            }
+        }
+    }
+
+    impl std::error::Error for ConfigError {}
+
+    fn config_error(path: String, message: impl Into<String>) -> ConfigError {
+        ConfigError {
+            path,
+            message: message.into(),
+        }
+    }
+
+    // every problem with a set of queue configurations that would stop them working together. Parsing has
+    // already checked the types, this checks names, limits and references between queues
+    pub fn validate_configurations(configurations: &[QueueConfiguration]) -> Vec<ConfigError> {
+        let mut errors = vec![];
+        let mut names: HashMap<&str, usize> = HashMap::new();
+
+        for (index, config) in configurations.iter().enumerate() {
+            let field = |field: &str| format!("[{}].{}", index, field);
+
+            if config.name.is_empty() {
+                errors.push(config_error(field("name"), "a queue needs a name"));
+            } else if let Some(first) = names.insert(&config.name, index) {
+                errors.push(config_error(
+                    field("name"),
+                    format!("{} is already the name of [{}]", config.name, first),
+                ));
+                names.insert(&config.name, first);
+            }
+
+            for (name, value) in [
+                ("buffer_count", Some(config.buffer_count)),
+                ("buffer_size", Some(config.buffer_size)),
+                ("max_buffered_bytes", config.max_buffered_bytes),
+                ("max_message_size", config.max_message_size),
+            ] {
+                if value == Some(0) {
+                    errors.push(config_error(field(name), "must be at least 1"));
+                }
+            }
+            if config.retention == Some(0) {
+                errors.push(config_error(
+                    field("retention"),
+                    "must be at least 1, leave it out to keep messages until they are deleted",
+                ));
+            }
+
+            if config.queue_type == QueueType::Priority {
+                if !(1..=u8::MAX as usize + 1).contains(&config.priority_levels) {
+                    errors.push(config_error(
+                        field("priority_levels"),
+                        "must be between 1 and 256",
+                    ));
+                }
+                if let Some(weights) = config.priority_weights.as_ref() {
+                    if weights.len() > config.priority_levels {
+                        errors.push(config_error(
+                            field("priority_weights"),
+                            format!(
+                                "has {} weights for {} priority levels",
+                                weights.len(),
+                                config.priority_levels
+                            ),
+                        ));
+                    }
+                    if let Some(level) = weights.iter().position(|weight| *weight == 0) {
+                        errors.push(config_error(
+                            format!("[{}].priority_weights[{}]", index, level),
+                            "must be at least 1",
+                        ));
+                    }
+                }
+            }
+            if config.queue_type != QueueType::Log && !config.consumer_groups.is_empty() {
+                errors.push(config_error(
+                    field("consumer_groups"),
+                    "only Log queues have consumer groups",
+                ));
+            }
+        }
+
+        for (index, config) in configurations.iter().enumerate() {
+            if let Some(dlq) = config.dead_letter_queue.as_ref() {
+                let path = format!("[{}].dead_letter_queue", index);
+                if *dlq == config.name {
+                    errors.push(config_error(
+                        path,
+                        "a queue cannot be its own dead letter queue",
+                    ));
+                } else if !names.contains_key(dlq.as_str()) {
+                    errors.push(config_error(
+                        path,
+                        format!("there is no queue named {}", dlq),
+                    ));
+                }
+            }
+        }
+
+        errors
+    }
+
+    // the first problem with a configuration on its own, with a path inside it (eg retention). The other
+    // queues are not known here, so the dead letter queue is only checked against the queue itself
+    pub fn validate_configuration(config: &QueueConfiguration) -> Option<ConfigError> {
+        validate_configurations(std::slice::from_ref(config))
+            .into_iter()
+            .find(|e| !e.message.starts_with("there is no queue"))
+            .map(|mut e| {
+                e.path = e.path.trim_start_matches("[0].").to_string();
+                e
+            })
+    }
+
+    // [1].priority_weights[0] style, as used in ConfigError
+    fn json_path(path: &serde_path_to_error::Path) -> String {
+        let path = path.to_string();
+        match path.as_str() {
+            "." => "".to_string(),
+            _ => path.replace(".[", "["),
+        }
+    }
+
+    // the group plain polls on a Log queue read as
+    pub const DEFAULT_CONSUMER_GROUP: &str = "default";
+
+    fn current_time_ms() -> u128 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
     }
 
     pub struct Queue {
@@ -25,52 +938,169 @@ pub mod queue {
         approximate_message_count: u64,
         pending_message_count: u64,
 
-        // read: messages are taken from the pending received map first if they exist & are available
+        // read: messages are taken from the pending received map first if they exist & are available,
+        // before the rest of their priority level
         // push: messages are sent to the end of the queue when they become available
         pending_mode: PendingMode,
 
-        readers: RotatingReadBuffers<MmqpNormalisedMessage>,
+        visibility_timeout: u64,
+
+        retention: Option<u64>,
+        dead_letter_queue: Option<String>,
+
+        buffer_count: usize,
+        buffer_size: usize,
+        max_buffered_bytes: Option<usize>,
+        max_message_size: Option<usize>,
+        offload_threshold: Option<usize>,
+
+        // where offloaded bodies are kept, set by the server when it has a page dir
+        blobs: Option<BlobStore>,
+
+        // stats
+        expired_message_count: u64,
+
+        // the earliest time any stored message can expire, so sweeps can be skipped when nothing is due
+        earliest_expiry: Option<u128>,
+
+        // expired messages found while reading, handed over on the next sweep so they can be dead lettered
+        expired: Vec<MmqpNormalisedMessage>,
+
+        queue_type: QueueType,
+        priority_levels: usize,
+        priority_weights: Option<Vec<u32>>,
+
+        // one per priority level, lowest first. Standard queues only have one
+        readers: Vec<RotatingReadBuffers<MmqpNormalisedMessage>>,
+
+        // reads left for each level in the current weighted round
+        credits: Vec<u32>,
+
+        // Log queues only. group name -> read buffer position of the next message the group reads
+        consumer_groups: BTreeMap<String, u64>,
+
+        // number of messages ever added to the read buffers. On a Log queue this is the offset the next
+        // message gets, offsets count up from 0 in the order messages join the log
+        end_offset: u64,
 
         //message id -> message. If a message is in this map, it has been sent but a delete command has not been received yet
         pending_sent: HashMap<String, MmqpNormalisedMessage>,
 
+        // visibility timeout -> ids of the messages in pending_sent that become available again then
+        visibility_deadlines: BTreeMap<u128, Vec<String>>,
+
         //message available time -> message. If a message is in this map, it has been received but cannot be added to the queue yet
         pending_received: BTreeMap<u128, Vec<MmqpNormalisedMessage>>,
 
         long_poll_connections: Vec<TcpStream>,
+
+        // removed from the queue config file. New messages are refused and the queue is dropped once the
+        // messages it still has are gone
+        retired: bool,
     }
 
     impl Queue {
         pub fn new(config: QueueConfiguration) -> Queue {
+            let levels = match config.queue_type {
+                QueueType::Standard | QueueType::Log => 1,
+                QueueType::Priority => config.priority_levels.clamp(1, u8::MAX as usize + 1),
+            };
+
             Queue {
                 queue_name: config.name,
                 approximate_message_count: 0,
                 pending_message_count: 0,
                 pending_mode: config.pending_mode,
-                readers: RotatingReadBuffers::new(16, 65536),
+                visibility_timeout: config.visibility_timeout,
+                retention: config.retention,
+                dead_letter_queue: config.dead_letter_queue,
+                expired_message_count: 0,
+                earliest_expiry: None,
+                expired: vec![],
+                buffer_count: config.buffer_count,
+                buffer_size: config.buffer_size,
+                max_buffered_bytes: config.max_buffered_bytes,
+                max_message_size: config.max_message_size,
+                offload_threshold: config.offload_threshold,
+                blobs: None,
+                queue_type: config.queue_type,
+                priority_levels: config.priority_levels,
+                priority_weights: config.priority_weights,
+                readers: (0..levels)
+                    .map(|_| RotatingReadBuffers::new(config.buffer_count, config.buffer_size))
+                    .collect(),
+                credits: vec![0; levels],
+                consumer_groups: config
+                    .consumer_groups
+                    .into_iter()
+                    .map(|group| (group, 0))
+                    .collect(),
+                end_offset: 0,
                 pending_sent: Default::default(),
+                visibility_deadlines: Default::default(),
                 pending_received: Default::default(),
                 long_poll_connections: vec![],
+                retired: false,
             }
         }
 
-        pub fn serialise_settings(self) -> String {
-            let config = QueueConfiguration {
-                name: self.queue_name,
+        pub fn configuration(&self) -> QueueConfiguration {
+            QueueConfiguration {
+                name: self.queue_name.clone(),
                 pending_mode: self.pending_mode,
-            };
+                visibility_timeout: self.visibility_timeout,
+                retention: self.retention,
+                dead_letter_queue: self.dead_letter_queue.clone(),
+                buffer_count: self.buffer_count,
+                buffer_size: self.buffer_size,
+                max_buffered_bytes: self.max_buffered_bytes,
+                max_message_size: self.max_message_size,
+                offload_threshold: self.offload_threshold,
+                queue_type: self.queue_type,
+                priority_levels: self.priority_levels,
+                priority_weights: self.priority_weights.clone(),
+                consumer_groups: self.consumer_groups.keys().cloned().collect(),
+            }
+        }
 
-            serde_json::to_string(&config).unwrap()
+        pub fn serialise_settings(self) -> String {
+            serde_json::to_string(&self.configuration()).unwrap()
+        }
+
+        pub fn deserialise_config(settings: &str) -> Result<QueueConfiguration, ConfigError> {
+            let deserializer = &mut serde_json::Deserializer::from_str(settings);
+            let config: QueueConfiguration = serde_path_to_error::deserialize(deserializer)
+                .map_err(|e| config_error(json_path(e.path()), e.inner().to_string()))?;
+
+            match validate_configuration(&config) {
+                Some(e) => Err(e),
+                None => Ok(config),
+            }
         }
 
-        pub fn deserialise_config(settings: &str) -> QueueConfiguration {
-            serde_json::from_str(settings).unwrap()
+        pub fn serialise_config_array(configurations: &[QueueConfiguration]) -> String {
+            serde_json::to_string_pretty(configurations).unwrap()
         }
 
+        // the queue config file. A blank file has no queues. Every problem found is returned, not just the first
         pub fn deserialise_config_array(
             settings: String,
-        ) -> Result<Box<[QueueConfiguration]>, serde_json::Error> {
-            serde_json::from_str(&*settings)
+        ) -> Result<Box<[QueueConfiguration]>, Vec<ConfigError>> {
+            if settings.trim().is_empty() {
+                return Ok(Box::new([]));
+            }
+
+            let deserializer = &mut serde_json::Deserializer::from_str(&settings);
+            let configurations: Box<[QueueConfiguration]> =
+                serde_path_to_error::deserialize(deserializer)
+                    .map_err(|e| vec![config_error(json_path(e.path()), e.inner().to_string())])?;
+
+            let errors = validate_configurations(&configurations);
+            if errors.is_empty() {
+                Ok(configurations)
+            } else {
+                Err(errors)
+            }
         }
 
         /**    getters  */
@@ -86,10 +1116,78 @@ pub mod queue {
             self.pending_mode
         }
 
-        pub fn readers(&self) -> &RotatingReadBuffers<MmqpNormalisedMessage> {
+        pub fn visibility_timeout(&self) -> u64 {
+            self.visibility_timeout
+        }
+
+        pub fn retention(&self) -> Option<u64> {
+            self.retention
+        }
+
+        pub fn dead_letter_queue(&self) -> Option<String> {
+            self.dead_letter_queue.clone()
+        }
+
+        pub fn expired_message_count(&self) -> u64 {
+            self.expired_message_count
+        }
+
+        pub fn max_buffered_bytes(&self) -> Option<usize> {
+            self.max_buffered_bytes
+        }
+
+        pub fn max_message_size(&self) -> Option<usize> {
+            self.max_message_size
+        }
+
+        pub fn offload_threshold(&self) -> Option<usize> {
+            self.offload_threshold
+        }
+
+        pub fn blob_store(&self) -> Option<&BlobStore> {
+            self.blobs.as_ref()
+        }
+
+        pub fn queue_type(&self) -> QueueType {
+            self.queue_type
+        }
+
+        pub fn buffered_bytes(&self) -> usize {
+            self.readers
+                .iter()
+                .map(|readers| readers.buffered_bytes())
+                .sum()
+        }
+
+        // group name -> read buffer position of the group's next message
+        pub fn consumer_groups(&self) -> &BTreeMap<String, u64> {
+            &self.consumer_groups
+        }
+
+        // Log queues: the offset of the oldest message still kept
+        pub fn first_offset(&self) -> u64 {
+            self.end_offset - self.approximate_message_count
+        }
+
+        pub fn end_offset(&self) -> u64 {
+            self.end_offset
+        }
+
+        pub fn readers(&self) -> &[RotatingReadBuffers<MmqpNormalisedMessage>] {
             &self.readers
         }
 
+        pub fn is_retired(&self) -> bool {
+            self.retired
+        }
+
+        // nothing waiting, delayed or in flight
+        pub fn is_empty(&self) -> bool {
+            self.approximate_message_count == 0
+                && self.pending_received.is_empty()
+                && self.pending_sent.is_empty()
+        }
+
         pub fn pending_sent(&self) -> &HashMap<String, MmqpNormalisedMessage> {
             &self.pending_sent
         }
@@ -102,10 +1200,215 @@ pub mod queue {
             &self.long_poll_connections
         }
 
+        // list up to limit messages from a position without taking them, see BrowsePosition for the order.
+        // nothing about the queue changes, polls see the same messages with the same receive counts
+        pub fn browse(&self, from: BrowsePosition, limit: usize) -> BrowsePage {
+            let current_time_ms = current_time_ms();
+            let levels = self.readers.len();
+            let mut entries = vec![];
+            let mut section = from.section;
+            let mut position = from.position;
+
+            while section < levels && entries.len() < limit {
+                let readers = &self.readers[levels - 1 - section];
+                // positions that have been read since are skipped
+                match readers.peek_at(position.max(readers.read_position())) {
+                    Some((message, next)) => {
+                        entries.push(self.browse_entry(
+                            &message,
+                            BrowseState::Ready,
+                            current_time_ms,
+                        ));
+                        position = next;
+                    }
+                    None => {
+                        section += 1;
+                        position = 0;
+                    }
+                }
+            }
+
+            if section == levels && entries.len() < limit {
+                let mut in_flight: Vec<&MmqpNormalisedMessage> =
+                    self.pending_sent.values().collect();
+                in_flight.sort_by_key(|message| message.available_time);
+
+                let mut rest = self
+                    .pending_received
+                    .values()
+                    .flatten()
+                    .map(|message| (message, BrowseState::Delayed))
+                    .chain(
+                        in_flight
+                            .into_iter()
+                            .map(|message| (message, BrowseState::InFlight)),
+                    )
+                    .skip(position as usize);
+
+                while entries.len() < limit {
+                    match rest.next() {
+                        Some((message, state)) => {
+                            entries.push(self.browse_entry(message, state, current_time_ms));
+                            position += 1;
+                        }
+                        None => {
+                            return BrowsePage {
+                                entries,
+                                next: None,
+                            }
+                        }
+                    }
+                }
+            }
+
+            BrowsePage {
+                entries,
+                next: Some(BrowsePosition { section, position }).filter(|_| section <= levels),
+            }
+        }
+
+        fn browse_entry(
+            &self,
+            message: &MmqpNormalisedMessage,
+            state: BrowseState,
+            current_time_ms: u128,
+        ) -> BrowseEntry {
+            let size = match (message.offloaded, self.blobs.as_ref()) {
+                (true, Some(blobs)) => blobs.open(message).map_or(0, |(_, len)| len),
+                _ => message.message.len(),
+            };
+
+            BrowseEntry {
+                message_id: message.message_id_string(),
+                group: message.message_group_id.clone(),
+                state,
+                age: current_time_ms.saturating_sub(message.received_time) as u64,
+                available_in: message.available_time.saturating_sub(current_time_ms) as u64,
+                receive_count: message.receive_count,
+                priority: message.priority,
+                size: size as u64,
+            }
+        }
+
+        // copies of the waiting messages (not in flight) that match, in the order they are polled. The queue
+        // is left as it is
+        pub fn copy_messages(
+            &self,
+            mut selected: impl FnMut(&MmqpNormalisedMessage) -> bool,
+        ) -> Vec<MmqpNormalisedMessage> {
+            self.export_messages()
+                .into_iter()
+                .filter(|(state, message)| *state != BrowseState::InFlight && selected(message))
+                .map(|(_, message)| message)
+                .collect()
+        }
+
+        // a copy of every message in the queue with the state it is in, in the order browse lists them
+        pub fn export_messages(&self) -> Vec<(BrowseState, MmqpNormalisedMessage)> {
+            let mut messages = vec![];
+
+            for readers in self.readers.iter().rev() {
+                let mut position = readers.read_position();
+                while let Some((message, next)) = readers.peek_at(position) {
+                    messages.push((BrowseState::Ready, message));
+                    position = next;
+                }
+            }
+
+            messages.extend(
+                self.pending_received
+                    .values()
+                    .flatten()
+                    .map(|message| (BrowseState::Delayed, message.clone())),
+            );
+
+            let mut in_flight: Vec<&MmqpNormalisedMessage> = self.pending_sent.values().collect();
+            in_flight.sort_by_key(|message| message.available_time);
+            messages.extend(
+                in_flight
+                    .into_iter()
+                    .map(|message| (BrowseState::InFlight, message.clone())),
+            );
+
+            messages
+        }
+
         /**    mutators */
-        pub fn receive_message(&mut self, message: impl Receivable) {
-            let readers = self.readers.borrow_mut();
-            let norm = message.normalise();
+        pub fn set_blob_store(&mut self, blobs: BlobStore) {
+            self.blobs = Some(blobs);
+        }
+
+        pub fn set_retired(&mut self, retired: bool) {
+            self.retired = retired;
+        }
+
+        // apply a changed configuration to a running queue without touching its messages. The read buffer
+        // layout cannot change while messages are in it, the names of those settings are returned when they
+        // differ and are left as they were. Consumer groups are only added, DELETE_GROUP removes them
+        pub fn reconfigure(&mut self, config: QueueConfiguration) -> Vec<&'static str> {
+            let mut fixed = vec![];
+            if config.queue_type != self.queue_type {
+                fixed.push("queue_type");
+            }
+            if config.priority_levels != self.priority_levels
+                && self.queue_type == QueueType::Priority
+            {
+                fixed.push("priority_levels");
+            }
+            if config.buffer_count != self.buffer_count {
+                fixed.push("buffer_count");
+            }
+            if config.buffer_size != self.buffer_size {
+                fixed.push("buffer_size");
+            }
+
+            // the next sweep looks at every message again and works out the new earliest expiry
+            if config.retention != self.retention {
+                self.earliest_expiry = Some(0);
+            }
+            if config.priority_weights != self.priority_weights {
+                self.credits.iter_mut().for_each(|credits| *credits = 0);
+            }
+
+            self.pending_mode = config.pending_mode;
+            self.visibility_timeout = config.visibility_timeout;
+            self.retention = config.retention;
+            self.dead_letter_queue = config.dead_letter_queue;
+            self.max_buffered_bytes = config.max_buffered_bytes;
+            self.max_message_size = config.max_message_size;
+            self.offload_threshold = config.offload_threshold;
+            self.priority_weights = config.priority_weights;
+            for group in config.consumer_groups.into_iter() {
+                self.consumer_groups.entry(group).or_insert(0);
+            }
+
+            fixed
+        }
+
+        // returns the generated message id, MessageTooLarge if the body is over max_message_size,
+        // or QueueFull if the queue is at its max_buffered_bytes
+        pub fn receive_message(
+            &mut self,
+            message: impl Receivable,
+        ) -> Result<String, MmqpResponseCode> {
+            if self.retired {
+                return Err(MmqpResponseCode::QueueRetired);
+            }
+
+            // checked before the body is copied
+            if let Some(max) = self.max_message_size {
+                if message.body_len() > max {
+                    return Err(MmqpResponseCode::MessageTooLarge);
+                }
+            }
+
+            let mut norm = message.normalise();
+            self.admit(&mut norm)?;
+
+            let message_id = norm.message_id_string();
+            if let Some(expiry) = norm.expiry_time(self.retention) {
+                self.earliest_expiry = Some(self.earliest_expiry.map_or(expiry, |e| e.min(expiry)));
+            }
             let current_time_ms = SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap()
@@ -115,84 +1418,636 @@ pub mod queue {
             if current_time_ms < available_time {
                 //message is not yet available
                 self.pending_message_count += 1;
-                if self.pending_received.contains_key(&available_time) {
-                    self.pending_received
-                        .get_mut(&available_time)
-                        .unwrap()
-                        .push(norm);
-                } else {
-                    self.pending_received.insert(available_time, vec![norm]);
-                }
-                return;
+                self.pending_received
+                    .entry(available_time)
+                    .or_default()
+                    .push(norm);
+                return Ok(message_id);
             }
 
+            let level = self.level(norm.priority);
             let binary = norm.serialise(SerialisationStrategy::Storage);
 
             self.approximate_message_count += 1;
-            readers.push_raw(binary);
+            self.end_offset += 1;
+            self.readers[level].push_raw(binary);
+            Ok(message_id)
+        }
+
+        // the limits every message is held to before it is stored, however it arrives. Bodies over the offload
+        // threshold are offloaded first so only their key counts against max_buffered_bytes
+        fn admit(&mut self, message: &mut MmqpNormalisedMessage) -> Result<(), MmqpResponseCode> {
+            if self.retired {
+                return Err(MmqpResponseCode::QueueRetired);
+            }
+
+            // an offloaded body was checked when it was first sent
+            if let Some(max) = self.max_message_size {
+                if !message.offloaded && message.message.len() > max {
+                    return Err(MmqpResponseCode::MessageTooLarge);
+                }
+            }
+
+            // a body offloaded here is released again if the message is turned away, one that was already
+            // offloaded still belongs to the caller
+            let mut offloaded_here = false;
+            if let (Some(threshold), Some(blobs)) = (self.offload_threshold, self.blobs.as_ref()) {
+                if !message.offloaded && message.message.len() > threshold {
+                    if let Err(e) = blobs.offload(message) {
+                        tracing::error!(
+                            queue = %self.queue_name,
+                            error = %e,
+                            "could not offload message body"
+                        );
+                        return Err(MmqpResponseCode::StorageError);
+                    }
+                    offloaded_here = true;
+                }
+            }
+
+            if let Some(max) = self.max_buffered_bytes {
+                let size = message.get_size() + message.get_size().mmqp_binary_size();
+                if self.buffered_bytes() + size > max {
+                    if offloaded_here {
+                        self.release_blob(message);
+                    }
+                    return Err(MmqpResponseCode::QueueFull);
+                }
+            }
+            Ok(())
+        }
+
+        // place an already normalised message (eg from another queue's dead lettering) at the end of
+        // the queue. The id and timestamps are kept, it becomes available immediately. A message the queue
+        // has no room for is handed back with the reason, so the caller still has it
+        pub fn receive_normalised(
+            &mut self,
+            mut message: MmqpNormalisedMessage,
+        ) -> Result<(), (MmqpResponseCode, Box<MmqpNormalisedMessage>)> {
+            if let Err(code) = self.admit(&mut message) {
+                return Err((code, Box::new(message)));
+            }
+
+            if let Some(expiry) = message.expiry_time(self.retention) {
+                self.earliest_expiry = Some(self.earliest_expiry.map_or(expiry, |e| e.min(expiry)));
+            }
+            message.available_time = current_time_ms();
+
+            self.approximate_message_count += 1;
+            self.end_offset += 1;
+            let level = self.level(message.priority);
+            self.readers[level].push_raw(message.serialise(SerialisationStrategy::Storage));
+            Ok(())
         }
 
+        // the returned message is moved to pending_sent and stays invisible until it is deleted
+        // or the visibility timeout passes, at which point it is returned to the queue
         pub fn read_next(&mut self) -> Option<MmqpNormalisedMessage> {
-            if self.pending_message_count > 0 {
-                let current_time_ms = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis();
-                let map = self.pending_received.borrow_mut();
+            if self.queue_type == QueueType::Log {
+                return self.read_group(DEFAULT_CONSUMER_GROUP);
+            }
+
+            self.release_expired_in_flight();
+
+            let mut message = self.next_available();
+
+            if let Some(message) = message.as_mut() {
+                message.receive_count += 1;
+                message.available_time = current_time_ms() + self.visibility_timeout as u128;
+                self.hide(message.clone());
+            }
+
+            message
+        }
 
-                let mut has_key = false;
-                let mut key = 0u128;
-                for k in map.keys().into_iter() {
-                    if *k <= current_time_ms {
-                        has_key = true;
-                        key = *k;
-                        break;
+        // Log queues: the next message for a consumer group. Reading moves the group past the message, there
+        // is nothing to delete. A group polled for the first time starts at the oldest message still kept.
+        // other queue types have a single shared cursor, the group is ignored
+        pub fn read_group(&mut self, group: &str) -> Option<MmqpNormalisedMessage> {
+            if self.queue_type != QueueType::Log {
+                return self.read_next();
+            }
+
+            // delayed messages join the log once they are due
+            self.flush_pending();
+
+            let current_time_ms = current_time_ms();
+            let readers = &self.readers[0];
+            let cursor = self.consumer_groups.entry(group.to_string()).or_insert(0);
+            // a cursor behind the read position points at messages that were expired or purged
+            let mut position = (*cursor).max(readers.read_position());
+
+            let message = loop {
+                match readers.peek_at(position) {
+                    Some((message, next)) => {
+                        position = next;
+                        match message.expiry_time(self.retention) {
+                            Some(expiry) if expiry <= current_time_ms => continue,
+                            _ => break Some(message),
+                        }
                     }
+                    None => break None,
                 }
+            };
+            *cursor = position;
+
+            self.reclaim();
+            message
+        }
 
-                if has_key {
-                    let mut messages = map.remove(&key).unwrap();
+        // Log queues: move a consumer group to the first message at or after target, to read messages again or
+        // to skip ahead. Targets before the oldest message kept go to the oldest, targets past the last message
+        // wait for the next one. Returns the offset the group reads next, None if this is not a Log queue
+        pub fn seek(&mut self, group: &str, target: SeekTarget) -> Option<u64> {
+            if self.queue_type != QueueType::Log {
+                return None;
+            }
+
+            let readers = &self.readers[0];
+            let mut position = readers.read_position();
+            let mut offset = self.first_offset();
+            while let Some((message, next)) = readers.peek_at(position) {
+                let reached = match target {
+                    SeekTarget::Offset(target) => offset >= target,
+                    SeekTarget::Time(time) => message.received_time >= time,
+                };
+                if reached {
+                    break;
+                }
+                position = next;
+                offset += 1;
+            }
+
+            self.consumer_groups.insert(group.to_string(), position);
+            // skipping ahead can leave messages no group needs
+            self.reclaim();
+            Some(offset)
+        }
+
+        // returns false if there was no such group. Messages only it was holding on to are released
+        pub fn remove_consumer_group(&mut self, group: &str) -> bool {
+            let removed = self.consumer_groups.remove(group).is_some();
+            self.reclaim();
+            removed
+        }
+
+        // Log queues: drop the messages every consumer group has read. Without any groups nothing is
+        // dropped, messages are kept until a group reads them (or they expire).
+        // with a retention, read messages are kept until they expire so groups can seek back to them
+        fn reclaim(&mut self) {
+            if self.retention.is_some() {
+                return;
+            }
+
+            let oldest = match self.consumer_groups.values().min() {
+                Some(oldest) => *oldest,
+                None => return,
+            };
+
+            let current_time_ms = current_time_ms();
+            while self.readers[0].read_position() < oldest {
+                match self.readers[0].next() {
+                    Some(message) => {
+                        self.approximate_message_count -= 1;
+                        match message.expiry_time(self.retention) {
+                            // skipped by the groups, still dead lettered on the next sweep
+                            Some(expiry) if expiry <= current_time_ms => self.expired.push(message),
+                            _ => self.release_blob(&message),
+                        }
+                    }
+                    None => break,
+                }
+            }
+            self.readers[0].compact();
+        }
+
+        fn next_available(&mut self) -> Option<MmqpNormalisedMessage> {
+            if self.pending_mode == PendingMode::Push && self.pending_message_count > 0 {
+                self.flush_pending();
+            }
+
+            // expired messages that have not been swept yet are skipped rather than handed out
+            let current_time_ms = current_time_ms();
+            loop {
+                let message = self.next_buffered(current_time_ms);
+                match message {
+                    Some(message) => match message.expiry_time(self.retention) {
+                        Some(expiry) if expiry <= current_time_ms => {
+                            self.expired.push(message);
+                        }
+                        _ => return Some(message),
+                    },
+                    None => {
+                        // drained, release anything the buffers grew by during a spike
+                        for readers in self.readers.iter_mut() {
+                            readers.compact();
+                        }
+                        return None;
+                    }
+                }
+            }
+        }
+
+        // the read buffers a message with this priority belongs in
+        fn level(&self, priority: u8) -> usize {
+            (priority as usize).min(self.readers.len() - 1)
+        }
+
+        // take the next message from the read buffers, highest priority first.
+        // with weights, a level is skipped once it has used its share of the round. A new round starts
+        // when every level that still has messages is out of reads
+        fn next_buffered(&mut self, current_time_ms: u128) -> Option<MmqpNormalisedMessage> {
+            if self.priority_weights.is_none() {
+                return (0..self.readers.len())
+                    .rev()
+                    .find_map(|level| self.next_in_level(level, current_time_ms));
+            }
+
+            for _ in 0..2 {
+                for level in (0..self.readers.len()).rev() {
+                    if self.credits[level] == 0 {
+                        continue;
+                    }
+                    if let Some(message) = self.next_in_level(level, current_time_ms) {
+                        self.credits[level] -= 1;
+                        return Some(message);
+                    }
+                }
+
+                let weights = self.priority_weights.as_ref().unwrap();
+                for (level, credits) in self.credits.iter_mut().enumerate() {
+                    // a level always gets at least one read per round
+                    *credits = weights.get(level).copied().unwrap_or(1).max(1);
+                }
+            }
+
+            None
+        }
+
+        // the next message of one priority level. In Read mode due pending messages of the level come
+        // first, oldest first
+        fn next_in_level(
+            &mut self,
+            level: usize,
+            current_time_ms: u128,
+        ) -> Option<MmqpNormalisedMessage> {
+            if self.pending_mode == PendingMode::Read && self.pending_message_count > 0 {
+                let levels = self.readers.len();
+                let due =
+                    self.pending_received
+                        .range(..=current_time_ms)
+                        .find_map(|(key, messages)| {
+                            messages
+                                .iter()
+                                .position(|message| {
+                                    (message.priority as usize).min(levels - 1) == level
+                                })
+                                .map(|index| (*key, index))
+                        });
+
+                if let Some((key, index)) = due {
+                    let messages = self.pending_received.get_mut(&key).unwrap();
+                    let message = messages.remove(index);
+                    if messages.is_empty() {
+                        self.pending_received.remove(&key);
+                    }
                     self.pending_message_count -= 1;
-                    return Some(messages.pop().unwrap());
+                    return Some(message);
                 }
             }
 
-            let readers = self.readers.borrow_mut();
+            let message = self.readers[level].next()?;
             self.approximate_message_count -= 1;
-            readers.next()
+            Some(message)
         }
 
-        pub fn flush_pending(&mut self) {
-            let mut map = self.pending_received.borrow_mut();
-            let mut keys: Vec<u128> = vec![];
-            for k in map.keys().into_iter() {
-                if *k
-                    <= SystemTime::now()
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis()
+        // remove every message from the queue, including delayed and in flight messages.
+        // returns the number of messages removed
+        pub fn purge(&mut self) -> u64 {
+            let in_flight = self.pending_sent.len() as u64;
+            let purged = self.approximate_message_count + self.pending_message_count + in_flight;
+
+            // the buffers only have to be read through when there may be offloaded bodies to remove
+            if self.blobs.is_some() {
+                let mut removed = self.retain_messages(|_| false);
+                removed.extend(self.pending_sent.drain().map(|(_, message)| message));
+                removed.append(&mut self.expired);
+                for message in removed.iter() {
+                    self.release_blob(message);
+                }
+            }
+
+            for readers in self.readers.iter_mut() {
+                readers.clear();
+            }
+            self.pending_sent.clear();
+            self.visibility_deadlines.clear();
+            self.pending_received.clear();
+            self.expired.clear();
+            self.approximate_message_count = 0;
+            self.pending_message_count = 0;
+            self.earliest_expiry = None;
+
+            purged
+        }
+
+        // remove every expired message that is waiting in the queue. Messages already in flight are left
+        // alone, they are checked again if they are returned to the queue.
+        // the removed messages are returned so they can be moved to the dead letter queue
+        pub fn expire_messages(&mut self) -> Vec<MmqpNormalisedMessage> {
+            let current_time_ms = current_time_ms();
+            let mut expired: Vec<MmqpNormalisedMessage> = self.expired.drain(..).collect();
+
+            let due = match self.earliest_expiry {
+                Some(earliest) => earliest <= current_time_ms,
+                None => false,
+            };
+
+            // a log keeps its order, so messages are expired from the front. Groups that had not read them
+            // yet move past them. Messages every group has read were delivered, they are dropped rather
+            // than dead lettered
+            if self.queue_type == QueueType::Log {
+                let oldest = self.consumer_groups.values().min().copied().unwrap_or(0);
+                while let Some((message, _)) =
+                    self.readers[0].peek_at(self.readers[0].read_position())
                 {
-                    keys.push(*k);
+                    match message.expiry_time(self.retention) {
+                        Some(expiry) if expiry <= current_time_ms => {}
+                        _ => break,
+                    }
+                    let read = self.readers[0].read_position() < oldest;
+                    self.approximate_message_count -= 1;
+                    match self.readers[0].next() {
+                        Some(message) if read => self.release_blob(&message),
+                        message => expired.extend(message),
+                    }
+                }
+            } else if due {
+                let retention = self.retention;
+                let mut earliest_expiry: Option<u128> = None;
+                expired.extend(self.retain_messages(
+                    |message| match message.expiry_time(retention) {
+                        Some(expiry) if expiry <= current_time_ms => false,
+                        Some(expiry) => {
+                            earliest_expiry =
+                                Some(earliest_expiry.map_or(expiry, |e| e.min(expiry)));
+                            true
+                        }
+                        None => true,
+                    },
+                ));
+
+                // in flight messages can still expire once they are returned
+                for message in self.pending_sent.values() {
+                    if let Some(expiry) = message.expiry_time(retention) {
+                        earliest_expiry = Some(earliest_expiry.map_or(expiry, |e| e.min(expiry)));
+                    }
                 }
+                self.earliest_expiry = earliest_expiry;
             }
 
-            while keys.len() > 0 {
-                let key = keys.pop().unwrap();
-                let messages = map.remove(&key).unwrap();
-                self.pending_message_count -= 1;
-                self.approximate_message_count += 1;
-                let readers = self.readers.borrow_mut();
+            self.expired_message_count += expired.len() as u64;
+            expired
+        }
+
+        // keep only the waiting messages (not in flight) that match, the rest are removed from the
+        // queue and returned. Order of the remaining messages is preserved
+        pub fn retain_messages(
+            &mut self,
+            mut keep: impl FnMut(&MmqpNormalisedMessage) -> bool,
+        ) -> Vec<MmqpNormalisedMessage> {
+            let mut removed = vec![];
 
-                for message in messages.into_iter() {
+            self.approximate_message_count = 0;
+            for readers in self.readers.iter_mut() {
+                let mut kept = vec![];
+                while let Some(message) = readers.next() {
+                    if keep(&message) {
+                        kept.push(message);
+                    } else {
+                        removed.push(message);
+                    }
+                }
+                self.approximate_message_count += kept.len() as u64;
+                for message in kept.into_iter() {
                     readers.push_value(message);
                 }
             }
+
+            let pending: Vec<MmqpNormalisedMessage> = std::mem::take(&mut self.pending_received)
+                .into_values()
+                .flatten()
+                .collect();
+            self.pending_message_count = 0;
+            for message in pending.into_iter() {
+                if keep(&message) {
+                    self.add_pending_received(message);
+                } else {
+                    removed.push(message);
+                }
+            }
+
+            removed
         }
+
+        // add a message from an export (or another server) as it was: ready messages go to the end of the
+        // queue, delayed ones wait for their available time and in flight ones are returned when their
+        // visibility timeout passes. It is held to the same limits as a message that is sent
+        pub fn import_message(
+            &mut self,
+            state: BrowseState,
+            mut message: MmqpNormalisedMessage,
+        ) -> Result<(), MmqpResponseCode> {
+            self.admit(&mut message)?;
+
+            if let Some(expiry) = message.expiry_time(self.retention) {
+                self.earliest_expiry = Some(self.earliest_expiry.map_or(expiry, |e| e.min(expiry)));
+            }
+
+            match state {
+                BrowseState::Ready => {
+                    self.approximate_message_count += 1;
+                    self.end_offset += 1;
+                    let level = self.level(message.priority);
+                    self.readers[level].push_raw(message.serialise(SerialisationStrategy::Storage));
+                }
+                BrowseState::Delayed => self.add_pending_received(message),
+                BrowseState::InFlight => self.hide(message),
+            }
+            Ok(())
+        }
+
+        // in flight messages whose visibility timeout has passed become available again
+        fn release_expired_in_flight(&mut self) {
+            let later = self
+                .visibility_deadlines
+                .split_off(&(current_time_ms() + 1));
+            let expired = std::mem::replace(&mut self.visibility_deadlines, later);
+
+            for id in expired.into_values().flatten() {
+                let message = self.pending_sent.remove(&id).unwrap();
+                self.add_pending_received(message);
+            }
+        }
+
+        // in flight until its available_time
+        fn hide(&mut self, message: MmqpNormalisedMessage) {
+            let id = message.message_id_string();
+            // an import can bring back a message that is still in flight
+            self.unhide(&id);
+            self.visibility_deadlines
+                .entry(message.available_time)
+                .or_default()
+                .push(id.clone());
+            self.pending_sent.insert(id, message);
+        }
+
+        // no longer in flight, whether it was deleted or returned
+        fn unhide(&mut self, message_id: &str) -> Option<MmqpNormalisedMessage> {
+            let message = self.pending_sent.remove(message_id)?;
+            if let Some(ids) = self.visibility_deadlines.get_mut(&message.available_time) {
+                ids.retain(|id| id != message_id);
+                if ids.is_empty() {
+                    self.visibility_deadlines.remove(&message.available_time);
+                }
+            }
+            Some(message)
+        }
+
+        fn add_pending_received(&mut self, message: MmqpNormalisedMessage) {
+            self.pending_message_count += 1;
+            self.pending_received
+                .entry(message.available_time)
+                .or_default()
+                .push(message);
+        }
+
+        // returns false if the message is not in flight, ie it was never polled, already deleted
+        // or its visibility timeout passed and it was returned to the queue
+        pub fn delete_message(&mut self, message_id: &str) -> bool {
+            self.release_expired_in_flight();
+            match self.unhide(message_id) {
+                Some(message) => {
+                    self.release_blob(&message);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        // remove the stored body of a message that is leaving the server, if it was offloaded
+        pub fn release_blob(&self, message: &MmqpNormalisedMessage) {
+            if let Some(blobs) = self.blobs.as_ref() {
+                blobs.release(message);
+            }
+        }
+
+        // hide an in flight message for timeout ms from now. Workers call this periodically as a
+        // heartbeat to keep their lease on long running messages.
+        // a timeout of 0 is a negative ack: the message is returned to the queue straight away
+        pub fn change_visibility(&mut self, message_id: &str, timeout: u64) -> bool {
+            self.release_expired_in_flight();
+
+            if timeout == 0 {
+                return self.negative_ack(message_id);
+            }
+
+            match self.unhide(message_id) {
+                Some(mut message) => {
+                    message.available_time = current_time_ms() + timeout as u128;
+                    self.hide(message);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        // give up on an in flight message so the next poll can receive it again
+        pub fn negative_ack(&mut self, message_id: &str) -> bool {
+            match self.unhide(message_id) {
+                Some(mut message) => {
+                    message.available_time = current_time_ms();
+                    self.add_pending_received(message);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        // move the pending messages that are due to the back of the read buffers of their priority level,
+        // oldest first
+        pub fn flush_pending(&mut self) {
+            let later = self.pending_received.split_off(&(current_time_ms() + 1));
+            let due = std::mem::replace(&mut self.pending_received, later);
+
+            for message in due.into_values().flatten() {
+                self.pending_message_count -= 1;
+                self.approximate_message_count += 1;
+                self.end_offset += 1;
+
+                let level = self.level(message.priority);
+                self.readers[level].push_value(message);
+            }
+        }
+    }
+
+    // which messages a move or copy between queues takes
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum MessageSelection {
+        All,
+
+        // a filter expression, see filter.rs
+        Filter(String),
+
+        // message ids as returned by send and poll
+        Ids(Vec<String>),
     }
 
-    #[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug)]
+    // true for the messages a selection takes
+    pub type MessageMatcher<'a> = Box<dyn Fn(&MmqpNormalisedMessage) -> bool + 'a>;
+
+    impl MessageSelection {
+        // the filter is parsed once, up front
+        pub fn matcher(&self) -> Result<MessageMatcher<'_>, FilterError> {
+            Ok(match self {
+                MessageSelection::All => Box::new(|_| true),
+                MessageSelection::Filter(filter) => {
+                    let filter = parse_filter(filter)?;
+                    Box::new(move |message| {
+                        filter.matches(&message.message_group_id, &message.attributes)
+                    })
+                }
+                MessageSelection::Ids(ids) => {
+                    Box::new(move |message| ids.contains(&message.message_id_string()))
+                }
+            })
+        }
+    }
+
+    // where Queue::seek moves a consumer group to
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum SeekTarget {
+        // the message with this offset
+        Offset(u64),
+
+        // the first message received at or after this time (ms since the unix epoch)
+        Time(u128),
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
     pub enum PendingMode {
         Read,
         Push,
     }
+
+    #[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+    pub enum QueueType {
+        #[default]
+        Standard,
+        Priority,
+
+        // messages are kept after they are read. Every consumer group reads the whole log at its own pace,
+        // a message is only dropped once all of them have read it
+        Log,
+    }
 }
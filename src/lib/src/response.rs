@@ -0,0 +1,185 @@
+pub mod response {
+    use crate::encoding::encoding::MmqpEncoding;
+    use crate::normalised_message::normalised_message::MmqpNormalisedMessage;
+
+    #[test]
+    fn batch_results_round_trip() {
+        let results = vec![
+            MmqpEntryResult::ok("bWVzc2FnZWlk".to_string()),
+            MmqpEntryResult::error(MmqpResponseCode::QueueNotFound, "missing".to_string()),
+            MmqpEntryResult::ok("".to_string()),
+        ];
+
+        let binary = serialise_batch_results(&results);
+
+        assert_eq!(deserialise_batch_results(&binary), results);
+    }
+
+    #[test]
+    fn polled_messages_round_trip() {
+        use crate::normalised_message::normalised_message::Receivable;
+        use crate::{MmqpMessage, MmqpSerialisable, SerialisationStrategy};
+
+        let mut message = MmqpMessage::new();
+        message.set_text("polled");
+        let first = message.normalise();
+        let second = message.normalise();
+
+        let mut binary = MESSAGES_HEADER.to_vec();
+        binary.extend(first.serialise(SerialisationStrategy::Storage).iter());
+        binary.extend(second.serialise(SerialisationStrategy::Storage).iter());
+        binary.push(0x00);
+
+        let messages = deserialise_messages(&binary).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].message_id, first.message_id);
+        assert_eq!(messages[1].message, b"polled".to_vec());
+
+        assert!(deserialise_messages(&binary[..binary.len() - 2]).is_none());
+        assert_eq!(deserialise_messages(b"MMQP|0.1|M|\0").unwrap().len(), 0);
+        assert!(deserialise_messages(b"MMQP|0.1|R|QUEUE_NOT_FOUND|q|0").is_none());
+    }
+
+    // codes sent back to clients in R frames
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MmqpResponseCode {
+        Ok,
+        QueueNotFound,
+        MessageNotFound,
+        TopicNotFound,
+        ExchangeNotFound,
+        ConsumerGroupNotFound,
+        QueueFull,
+        // the queue was removed from the config and only drains the messages it has
+        QueueRetired,
+        MessageTooLarge,
+        MalformedRequest,
+        StorageError,
+        InvalidFilter,
+        UnknownCommand,
+        // a code this version of the library does not know about
+        Unknown,
+    }
+
+    impl MmqpResponseCode {
+        pub fn code(&self) -> &'static str {
+            match self {
+                MmqpResponseCode::Ok => "OK",
+                MmqpResponseCode::QueueNotFound => "QUEUE_NOT_FOUND",
+                MmqpResponseCode::MessageNotFound => "MESSAGE_NOT_FOUND",
+                MmqpResponseCode::TopicNotFound => "TOPIC_NOT_FOUND",
+                MmqpResponseCode::ExchangeNotFound => "EXCHANGE_NOT_FOUND",
+                MmqpResponseCode::ConsumerGroupNotFound => "GROUP_NOT_FOUND",
+                MmqpResponseCode::QueueFull => "QUEUE_FULL",
+                MmqpResponseCode::QueueRetired => "QUEUE_RETIRED",
+                MmqpResponseCode::MessageTooLarge => "MESSAGE_TOO_LARGE",
+                MmqpResponseCode::MalformedRequest => "MALFORMED_REQUEST",
+                MmqpResponseCode::StorageError => "STORAGE_ERROR",
+                MmqpResponseCode::InvalidFilter => "INVALID_FILTER",
+                MmqpResponseCode::UnknownCommand => "UNKNOWN_COMMAND",
+                MmqpResponseCode::Unknown => "UNKNOWN",
+            }
+        }
+
+        pub fn from_code(code: &str) -> MmqpResponseCode {
+            match code {
+                "OK" => MmqpResponseCode::Ok,
+                "QUEUE_NOT_FOUND" => MmqpResponseCode::QueueNotFound,
+                "MESSAGE_NOT_FOUND" => MmqpResponseCode::MessageNotFound,
+                "TOPIC_NOT_FOUND" => MmqpResponseCode::TopicNotFound,
+                "EXCHANGE_NOT_FOUND" => MmqpResponseCode::ExchangeNotFound,
+                "GROUP_NOT_FOUND" => MmqpResponseCode::ConsumerGroupNotFound,
+                "QUEUE_FULL" => MmqpResponseCode::QueueFull,
+                "QUEUE_RETIRED" => MmqpResponseCode::QueueRetired,
+                "MESSAGE_TOO_LARGE" => MmqpResponseCode::MessageTooLarge,
+                "MALFORMED_REQUEST" => MmqpResponseCode::MalformedRequest,
+                "STORAGE_ERROR" => MmqpResponseCode::StorageError,
+                "INVALID_FILTER" => MmqpResponseCode::InvalidFilter,
+                "UNKNOWN_COMMAND" => MmqpResponseCode::UnknownCommand,
+                _ => MmqpResponseCode::Unknown,
+            }
+        }
+    }
+
+    // outcome of a single entry in a batched command
+    // detail is the message id on success, or whatever the code refers to on failure (eg the queue name)
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct MmqpEntryResult {
+        pub code: MmqpResponseCode,
+        pub detail: String,
+    }
+
+    impl MmqpEntryResult {
+        pub fn ok(detail: String) -> MmqpEntryResult {
+            MmqpEntryResult {
+                code: MmqpResponseCode::Ok,
+                detail,
+            }
+        }
+
+        pub fn error(code: MmqpResponseCode, detail: String) -> MmqpEntryResult {
+            MmqpEntryResult { code, detail }
+        }
+
+        pub fn is_ok(&self) -> bool {
+            self.code == MmqpResponseCode::Ok
+        }
+    }
+
+    const BATCH_HEADER: &[u8] = b"MMQP|0.1|R|BATCH|";
+
+    // MMQP|0.1|R|BATCH|&count|%code|%detail|%code|%detail...
+    // results are in the same order as the entries of the request
+    pub fn serialise_batch_results(results: &[MmqpEntryResult]) -> Vec<u8> {
+        let mut binary: Vec<u8> = BATCH_HEADER.to_vec();
+        binary.extend(results.len().to_mmqp_binary().unwrap());
+
+        for result in results.iter() {
+            binary.extend(b"|");
+            binary.extend(result.code.code().to_string().to_mmqp_binary().unwrap());
+            binary.extend(b"|");
+            binary.extend(result.detail.to_mmqp_binary().unwrap());
+        }
+
+        binary.push(0x00);
+        binary
+    }
+
+    pub fn deserialise_batch_results(binary: &[u8]) -> Vec<MmqpEntryResult> {
+        let mut cursor = BATCH_HEADER.len();
+        let count = usize::from_mmqp_binary(binary, &mut cursor);
+
+        let mut results = Vec::with_capacity(count);
+        for _ in 0..count {
+            cursor += 1; //pipe
+            let code = String::from_mmqp_binary(binary, &mut cursor);
+            cursor += 1; //pipe
+            let detail = String::from_mmqp_binary(binary, &mut cursor);
+            results.push(MmqpEntryResult {
+                code: MmqpResponseCode::from_code(&code),
+                detail,
+            });
+        }
+
+        results
+    }
+
+    const MESSAGES_HEADER: &[u8] = b"MMQP|0.1|M|";
+
+    // MMQP|0.1|M|message...0x00 with each message in its Storage form, as sent for a poll
+    // None if the response is not an M frame or a message in it is cut short or corrupt
+    pub fn deserialise_messages(binary: &[u8]) -> Option<Vec<MmqpNormalisedMessage>> {
+        let end = binary.len().checked_sub(1)?;
+        if !binary.starts_with(MESSAGES_HEADER) || binary[end] != 0x00 {
+            return None;
+        }
+
+        let mut cursor = MESSAGES_HEADER.len();
+        let mut messages = Vec::new();
+        while cursor < end {
+            messages
+                .push(MmqpNormalisedMessage::try_deserialise(&binary[..end], &mut cursor).ok()?);
+        }
+        Some(messages)
+    }
+}
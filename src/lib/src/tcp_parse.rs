@@ -3,13 +3,42 @@ use crate::tcp_parse::tcp_parse::MmqpTcpFormat;
 use crate::MmqpMessage;
 
 pub mod tcp_parse {
-    use crate::encoding::encoding::MmqpEncoding;
-    use crate::{MmqpMessage, MmqpSerialisable, SerialisationStrategy};
+    use crate::admin::admin::MmqpAdminCommand;
+    use crate::attributes::attributes::MmqpAttributes;
+    use crate::browse::browse::BrowsePosition;
+    use crate::encoding::encoding::{peek_mmqp_length, DecodeError, MmqpEncoding};
+    use crate::queue::queue::SeekTarget;
+    use crate::response::response::MmqpResponseCode;
+    use crate::{MmqpBodyKind, MmqpMessage, MmqpSerialisable, SerialisationStrategy};
     use std::any::Any;
     use std::net::TcpStream;
 
+    #[test]
+    fn username_without_parsing() {
+        let raw = serialise_seek("alice", "secret", "q", "g", SeekTarget::Offset(1));
+        assert_eq!(request_username(&raw), Some("alice".to_string()));
+
+        let redacted = redact_request(&raw);
+        assert!(!String::from_utf8_lossy(&redacted).contains("secret"));
+        match parse_tcp_request(redacted) {
+            MmqpTcpFormat::Seek(queue, group, SeekTarget::Offset(1)) => {
+                assert_eq!((queue.as_str(), group.as_str()), ("q", "g"))
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+        assert_eq!(redact_request(b"MMQP|0.1|P|"), b"MMQP|0.1|P|".to_vec());
+
+        let mut message = MmqpMessage::new();
+        message.password = "secret".to_string();
+        assert!(!format!("{:?}", message).contains("secret"));
+        assert_eq!(request_username(b"MMQP|0.1|P|\x01\x09ali"), None);
+        assert_eq!(request_username(b"MMQP|0.1|P|"), None);
+    }
+
     #[test]
     fn test_string_parse() {
+        use crate::attributes::attributes::MmqpAttributeValue;
+
         let magic = b"MMQP";
         let version_major = 0u8;
         let delimiter = b".";
@@ -19,6 +48,13 @@ pub mod tcp_parse {
         let username = "myusername".to_string().to_mmqp_binary().unwrap();
         let password = "password".to_string().to_mmqp_binary().unwrap();
         let queuename = "myqueuename".to_string().to_mmqp_binary().unwrap();
+        let group = "mygroup".to_string().to_mmqp_binary().unwrap();
+        let mut attributes = MmqpAttributes::new();
+        attributes.insert(
+            "content-type".to_string(),
+            MmqpAttributeValue::String("text/plain".to_string()),
+        );
+        let attributes = attributes.to_mmqp_binary().unwrap();
         let message = "mymessage".to_string().to_mmqp_binary().unwrap();
 
         let mut bytes = Vec::new();
@@ -34,6 +70,13 @@ pub mod tcp_parse {
         bytes.extend(b"|");
         bytes.extend(queuename);
         bytes.extend(b"|");
+        bytes.extend(group);
+        bytes.extend(b"|");
+        bytes.extend(attributes);
+        bytes.extend(b"|");
+        bytes.push(5);
+        bytes.extend(b"|");
+        bytes.push(MmqpBodyKind::Text.to_byte());
         bytes.extend(message);
 
         dbg!(bytes.clone());
@@ -41,8 +84,15 @@ pub mod tcp_parse {
         let res: MmqpTcpFormat = parse_tcp_request(bytes);
 
         match res {
-            MmqpTcpFormat::Message(_) => {
-                assert!(true);
+            MmqpTcpFormat::Message(message) => {
+                assert_eq!(message.message_group, "mygroup");
+                assert_eq!(message.priority, 5);
+                assert_eq!(message.body_kind, MmqpBodyKind::Text);
+                assert_eq!(message.message, b"mymessage");
+                assert_eq!(
+                    message.attributes.get("content-type"),
+                    Some(&MmqpAttributeValue::String("text/plain".to_string()))
+                );
             }
             _ => {
                 assert!(false);
@@ -57,7 +107,7 @@ pub mod tcp_parse {
         let username = "myusername".to_string();
         let password = "password".to_string();
         let queuename = "myqueuename".to_string();
-        let message = "mymessage".to_string();
+        let message = b"mymessage".to_vec();
 
         let message: MmqpMessage = MmqpMessage {
             version_major,
@@ -65,11 +115,14 @@ pub mod tcp_parse {
             username: username,
             password: password,
             target_queue: queuename,
+            body_kind: MmqpBodyKind::Text,
             message: message,
             message_group: "".to_string(),
+            attributes: MmqpAttributes::new(),
+            priority: 0,
         };
 
-        let bytes = message.serialise(SerialisationStrategy::Wire);
+        let bytes = message.serialise(SerialisationStrategy::Storage);
         let encl = bytes[0];
         //skip the number of bytes listed by encl
         let mut bytes = &bytes[1..];
@@ -90,112 +143,1223 @@ pub mod tcp_parse {
         }
     }
 
+    #[test]
+    fn test_batch_parse() {
+        use crate::MmqpBatch;
+
+        let mut batch = MmqpBatch::new("myusername".to_string(), "password".to_string());
+        for queue in ["queue1", "queue2", "queue1"] {
+            let mut message = MmqpMessage::new();
+            message.target_queue = queue.to_string();
+            message.set_text(queue);
+            batch.messages.push(message);
+        }
+
+        let res: MmqpTcpFormat = parse_tcp_request(batch.serialise().to_vec());
+
+        match res {
+            MmqpTcpFormat::BatchMessage(messages) => {
+                assert_eq!(messages.len(), 3);
+                assert_eq!(messages[1].target_queue, "queue2");
+                assert_eq!(messages[2].message, b"queue1");
+                assert_eq!(messages[2].username, "myusername");
+            }
+            other => panic!("expected a batch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_publish_parse() {
+        let mut message = MmqpMessage::new();
+        message.target_queue = "orders".to_string();
+        message.set_text("order placed");
+
+        match parse_tcp_request(message.serialise_publish().to_vec()) {
+            MmqpTcpFormat::Publish(parsed) => assert_eq!(parsed, message),
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_route_parse() {
+        let mut message = MmqpMessage::new();
+        message.target_queue = "logs".to_string();
+        message.set_text("disk full");
+
+        match parse_tcp_request(message.serialise_route("error.disk").to_vec()) {
+            MmqpTcpFormat::Route(routing_key, parsed) => {
+                assert_eq!(routing_key, "error.disk");
+                assert_eq!(parsed, message);
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_message_size_limit() {
+        use crate::MmqpBatch;
+
+        let mut message = MmqpMessage::new();
+        message.target_queue = "queue1".to_string();
+        message.set_text("0123456789");
+        let binary = message.serialise(SerialisationStrategy::Wire).to_vec();
+
+        match parse_tcp_request_with_limit(binary.clone(), 10) {
+            MmqpTcpFormat::Message(parsed) => assert_eq!(parsed.message, b"0123456789"),
+            res => panic!("unexpected {:?}", res),
+        }
+        match parse_tcp_request_with_limit(binary.clone(), 9) {
+            MmqpTcpFormat::Invalid(code, _) => assert_eq!(code, MmqpResponseCode::MessageTooLarge),
+            res => panic!("unexpected {:?}", res),
+        }
+
+        // a body length pointing past the end of the request
+        match parse_tcp_request_with_limit(binary[..binary.len() - 4].to_vec(), 10) {
+            MmqpTcpFormat::Invalid(code, _) => assert_eq!(code, MmqpResponseCode::MalformedRequest),
+            res => panic!("unexpected {:?}", res),
+        }
+
+        let mut batch = MmqpBatch::new("user".to_string(), "pass".to_string());
+        batch.messages.push(MmqpMessage::new());
+        batch.messages.push(message);
+        match parse_tcp_request_with_limit(batch.serialise().to_vec(), 9) {
+            MmqpTcpFormat::Invalid(code, _) => assert_eq!(code, MmqpResponseCode::MessageTooLarge),
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_malformed_attributes() {
+        use crate::attributes::attributes::MmqpAttributeValue;
+
+        let mut message = MmqpMessage::new();
+        message.target_queue = "queue1".to_string();
+        message
+            .attributes
+            .insert("n".to_string(), MmqpAttributeValue::Number(42));
+        let binary = message.serialise(SerialisationStrategy::Wire).to_vec();
+        assert!(matches!(
+            parse_tcp_request(binary.clone()),
+            MmqpTcpFormat::Message(_)
+        ));
+
+        // the number 42 is written as N, length 1, 2, "42"
+        let number = binary.windows(5).position(|w| w == b"N\x01\x0242").unwrap();
+        let mut not_a_number = binary.clone();
+        not_a_number[number + 3] = b'x';
+        let mut unknown_type = binary.clone();
+        unknown_type[number] = b'Q';
+        for binary in [not_a_number, unknown_type] {
+            match parse_tcp_request(binary) {
+                MmqpTcpFormat::Invalid(code, _) => {
+                    assert_eq!(code, MmqpResponseCode::MalformedRequest)
+                }
+                res => panic!("unexpected {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn test_hostile_frames() {
+        let header = |command: u8| {
+            let mut frame = b"MMQP|".to_vec();
+            frame.extend([0u8, b'.', 1u8, b'|', command, b'|']);
+            frame.extend("user".to_string().to_mmqp_binary().unwrap());
+            frame.extend(b":");
+            frame.extend("pass".to_string().to_mmqp_binary().unwrap());
+            frame.extend(b"|");
+            frame
+        };
+        let assert_invalid =
+            |frame: Vec<u8>, expected: MmqpResponseCode| match parse_tcp_request(frame) {
+                MmqpTcpFormat::Invalid(code, _) => assert_eq!(code, expected),
+                res => panic!("unexpected {:?}", res),
+            };
+
+        // a count or length far larger than the frame is never allocated
+        let huge = usize::MAX.to_mmqp_binary().unwrap();
+        let mut batch = header(b'm');
+        batch.extend(huge.iter());
+        assert_invalid(batch, MmqpResponseCode::MalformedRequest);
+        let mut batch_del = header(b'd');
+        batch_del.extend("queue1".to_string().to_mmqp_binary().unwrap());
+        batch_del.extend(b"|");
+        batch_del.extend(huge.iter());
+        assert_invalid(batch_del, MmqpResponseCode::MalformedRequest);
+        let mut queue_name = header(b'M');
+        queue_name.extend(huge.iter());
+        assert_invalid(queue_name, MmqpResponseCode::MalformedRequest);
+        let mut attributes = header(b'M');
+        attributes.extend("queue1".to_string().to_mmqp_binary().unwrap());
+        attributes.extend(b"|");
+        attributes.extend(String::new().to_mmqp_binary().unwrap());
+        attributes.extend(b"|");
+        attributes.extend(huge.iter());
+        assert_invalid(attributes, MmqpResponseCode::MalformedRequest);
+        assert_invalid(header(0xff), MmqpResponseCode::UnknownCommand);
+
+        // every frame cut short is incomplete, however much of it has arrived
+        let mut message = MmqpMessage::new();
+        message.target_queue = "queue1".to_string();
+        message.set_text("hello");
+        let message = message.serialise(SerialisationStrategy::Wire).to_vec();
+        let queue = "queue1".to_string().to_mmqp_binary().unwrap();
+        let fields = message
+            .windows(queue.len())
+            .position(|w| *w == *queue)
+            .unwrap();
+        let body_end = message.windows(5).position(|w| w == b"hello").unwrap() + 5;
+        let message = message[..body_end].to_vec();
+        let mut batch = header(b'm');
+        batch.extend(2usize.to_mmqp_binary().unwrap());
+        for _ in 0..2 {
+            batch.extend(b"|");
+            batch.extend(&message[fields..]);
+        }
+        for frame in [message.clone(), batch] {
+            for len in 0..frame.len() {
+                let partial = &frame[..len];
+                assert!(parse_partial_request(partial, usize::MAX, &|_| None).is_none());
+                assert_invalid(partial.to_vec(), MmqpResponseCode::MalformedRequest);
+            }
+            assert!(parse_partial_request(&frame, usize::MAX, &|_| None).is_some());
+        }
+
+        // the queue's own limit is checked before the body has arrived
+        let limit = |queue: &str| (queue == "queue1").then_some(4);
+        let body_start = body_end - "hello".len();
+        match parse_partial_request(&message[..body_start], usize::MAX, &limit) {
+            Some(MmqpTcpFormat::Invalid(code, _)) => {
+                assert_eq!(code, MmqpResponseCode::MessageTooLarge)
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+
+        // a body kind that is neither T nor B is not guessed at
+        let mut unknown_kind = message.clone();
+        unknown_kind[body_start - 3] = b'X';
+        assert_invalid(unknown_kind, MmqpResponseCode::MalformedRequest);
+    }
+
+    #[test]
+    fn test_request_scanner() {
+        use crate::attributes::attributes::MmqpAttributeValue;
+        use crate::MmqpBatch;
+
+        let mut message = MmqpMessage::new();
+        message.target_queue = "queue1".to_string();
+        message.set_text("hello");
+        message
+            .attributes
+            .insert("retries".to_string(), MmqpAttributeValue::Number(3));
+        message
+            .attributes
+            .insert("raw".to_string(), MmqpAttributeValue::Binary(vec![0, 1]));
+        let mut batch = MmqpBatch::new("user".to_string(), "pass".to_string());
+        batch.messages = vec![message.clone(), MmqpMessage::new(), message.clone()];
+        let mut del = b"MMQP|".to_vec();
+        del.extend([0u8, b'.', 1u8]);
+        del.extend(b"|d|");
+        for field in ["user", "pass", "queue1"] {
+            del.extend(field.to_string().to_mmqp_binary().unwrap());
+            del.extend(b"|");
+        }
+        del.extend(2usize.to_mmqp_binary().unwrap());
+        for id in ["id1", "id2"] {
+            del.extend(b"|");
+            del.extend(id.to_string().to_mmqp_binary().unwrap());
+        }
+        let subscribe = MmqpAdminCommand::Subscribe(
+            "orders".to_string(),
+            "queue1".to_string(),
+            Some("kind = 'a'".to_string()),
+        );
+
+        // fed a byte at a time, the scanner never holds back a request the parser would answer
+        let frames = [
+            message.serialise(SerialisationStrategy::Wire).to_vec(),
+            message.serialise_route("error.disk").to_vec(),
+            batch.serialise().to_vec(),
+            del,
+            subscribe.serialise("user", "pass").to_vec(),
+            MmqpAdminCommand::Reload.serialise("user", "pass").to_vec(),
+            serialise_poll("user", "pass", "queue1", 5).to_vec(),
+            serialise_seek("user", "pass", "queue1", "g", SeekTarget::Offset(300)).to_vec(),
+        ];
+        for frame in frames {
+            let mut scanner = RequestScanner::new(usize::MAX);
+            let mut parsed = None;
+            for len in 0..=frame.len() {
+                let partial = &frame[..len];
+                match scanner.scan(partial, &|_| None) {
+                    ScanResult::More => {
+                        assert!(parse_partial_request(partial, usize::MAX, &|_| None).is_none())
+                    }
+                    ScanResult::Parse => {
+                        parsed = parse_partial_request(partial, usize::MAX, &|_| None);
+                        // and asks for it once, only admin arguments are counted by the parser
+                        assert!(parsed.is_some() || frame[9] == b'A');
+                        if parsed.is_some() {
+                            break;
+                        }
+                    }
+                    res => panic!("unexpected {:?}", res),
+                }
+            }
+            assert!(parsed.is_some());
+        }
+
+        // an oversized body is turned away as soon as its length has arrived
+        let frame = message.serialise(SerialisationStrategy::Wire);
+        let body_start = frame.windows(5).position(|w| w == b"hello").unwrap();
+        let limit = |queue: &str| (queue == "queue1").then_some(4);
+        let mut scanner = RequestScanner::new(usize::MAX);
+        assert_eq!(
+            scanner.scan(&frame[..body_start - 2], &limit),
+            ScanResult::More
+        );
+        match scanner.scan(&frame[..body_start], &limit) {
+            ScanResult::Reject(code, _) => assert_eq!(code, MmqpResponseCode::MessageTooLarge),
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_batch_del_and_visibility_parse() {
+        let mut del = b"MMQP|".to_vec();
+        del.extend([0u8, b'.', 1u8]);
+        del.extend(b"|d|");
+        del.extend("user".to_string().to_mmqp_binary().unwrap());
+        del.extend(b":");
+        del.extend("pass".to_string().to_mmqp_binary().unwrap());
+        del.extend(b"|");
+        del.extend("queue1".to_string().to_mmqp_binary().unwrap());
+        del.extend(b"|");
+
+        let mut visibility = del.clone();
+        visibility[9] = b'v';
+
+        del.extend(2usize.to_mmqp_binary().unwrap());
+        visibility.extend(2usize.to_mmqp_binary().unwrap());
+        for (id, timeout) in [("id1", 0usize), ("id2", 120000usize)] {
+            del.extend(b"|");
+            del.extend(id.to_string().to_mmqp_binary().unwrap());
+
+            visibility.extend(b"|");
+            visibility.extend(id.to_string().to_mmqp_binary().unwrap());
+            visibility.extend(b"|");
+            visibility.extend(timeout.to_mmqp_binary().unwrap());
+        }
+
+        match parse_tcp_request(del) {
+            MmqpTcpFormat::BatchDel(queue, ids) => {
+                assert_eq!(queue, "queue1");
+                assert_eq!(ids, vec!["id1".to_string(), "id2".to_string()]);
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+
+        match parse_tcp_request(visibility) {
+            MmqpTcpFormat::BatchVisibility(queue, entries) => {
+                assert_eq!(queue, "queue1");
+                assert_eq!(
+                    entries,
+                    vec![("id1".to_string(), 0), ("id2".to_string(), 120000)]
+                );
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_group_poll_parse() {
+        let mut poll = b"MMQP|".to_vec();
+        poll.extend([0u8, b'.', 1u8]);
+        poll.extend(b"|G|");
+        poll.extend("user".to_string().to_mmqp_binary().unwrap());
+        poll.extend(b":");
+        poll.extend("pass".to_string().to_mmqp_binary().unwrap());
+        poll.extend(b"|");
+        poll.extend("queue1".to_string().to_mmqp_binary().unwrap());
+        poll.extend(b"|");
+        poll.extend("billing".to_string().to_mmqp_binary().unwrap());
+        poll.extend(b"|");
+        poll.push(10);
+
+        match parse_tcp_request(poll) {
+            MmqpTcpFormat::GroupPoll(queue, group, count) => {
+                assert_eq!(queue, "queue1");
+                assert_eq!(group, "billing");
+                assert_eq!(count, 10);
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_seek_parse() {
+        for target in [SeekTarget::Offset(42), SeekTarget::Time(1_700_000_000_000)] {
+            let binary = serialise_seek("user", "pass", "queue1", "billing", target);
+            match parse_tcp_request(binary.to_vec()) {
+                MmqpTcpFormat::Seek(queue, group, parsed) => {
+                    assert_eq!(queue, "queue1");
+                    assert_eq!(group, "billing");
+                    assert_eq!(parsed, target);
+                }
+                res => panic!("unexpected {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn test_browse_parse() {
+        let from = BrowsePosition {
+            section: 2,
+            position: 70_000,
+        };
+        let binary = serialise_browse("user", "pass", "queue1", from, 25);
+        match parse_tcp_request(binary.to_vec()) {
+            MmqpTcpFormat::Browse(queue, parsed, count) => {
+                assert_eq!(queue, "queue1");
+                assert_eq!(parsed, from);
+                assert_eq!(count, 25);
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
     #[derive(Debug)]
     pub enum MmqpTcpFormat {
         //place a message in the specified queue
         Message(crate::MmqpMessage),
 
-        //todo figure out how to handle this, placeholder
-        Admin,
+        //place several messages, each entry can target a different queue
+        BatchMessage(Vec<crate::MmqpMessage>),
+
+        //place a copy of the message in every queue subscribed to the topic named by target_queue
+        Publish(crate::MmqpMessage),
+
+        //routing key, message. place a copy of the message in every queue bound to the exchange named by
+        //target_queue with a pattern matching the routing key
+        Route(String, crate::MmqpMessage),
+
+        //operator commands, see admin.rs
+        Admin(crate::admin::admin::MmqpAdminCommand),
 
         // queue name, number of messages to recieve maximum
         Poll(String, u8),
+        // queue name, consumer group, number of messages to recieve maximum. see QueueType::Log
+        GroupPoll(String, String, u8),
+        // queue name, consumer group, where to move the group to. see Queue::seek
+        Seek(String, String, SeekTarget),
+        // queue name, where to start, number of messages to list maximum. see Queue::browse
+        Browse(String, BrowsePosition, u8),
         // keepalive connection, duration, queue
         LongPoll(TcpStream, u32, String),
 
         //queue, message id
         Del(String, String),
 
+        //queue, message id, visibility timeout in ms. 0 returns the message to the queue
+        ChangeVisibility(String, String, u64),
+
+        //queue, message ids
+        BatchDel(String, Vec<String>),
+
+        //queue, (message id, visibility timeout in ms)
+        BatchVisibility(String, Vec<(String, u64)>),
+
         Ping,
+
+        //the request could not be accepted, eg a message over the size limit
+        Invalid(crate::response::response::MmqpResponseCode, String),
+    }
+
+    impl MmqpTcpFormat {
+        // what the request does, for logs
+        pub fn command(&self) -> &'static str {
+            match self {
+                MmqpTcpFormat::Message(_) => "send",
+                MmqpTcpFormat::BatchMessage(_) => "send_batch",
+                MmqpTcpFormat::Publish(_) => "publish",
+                MmqpTcpFormat::Route(_, _) => "route",
+                MmqpTcpFormat::Admin(_) => "admin",
+                MmqpTcpFormat::Poll(_, _) => "poll",
+                MmqpTcpFormat::GroupPoll(_, _, _) => "group_poll",
+                MmqpTcpFormat::Seek(_, _, _) => "seek",
+                MmqpTcpFormat::Browse(_, _, _) => "browse",
+                MmqpTcpFormat::LongPoll(_, _, _) => "long_poll",
+                MmqpTcpFormat::Del(_, _) => "delete",
+                MmqpTcpFormat::ChangeVisibility(_, _, _) => "change_visibility",
+                MmqpTcpFormat::BatchDel(_, _) => "delete_batch",
+                MmqpTcpFormat::BatchVisibility(_, _) => "change_visibility_batch",
+                MmqpTcpFormat::Ping => "ping",
+                MmqpTcpFormat::Invalid(_, _) => "invalid",
+            }
+        }
+
+        // the queue, topic or exchange the request is for. None when there is not exactly one
+        pub fn target(&self) -> Option<&str> {
+            match self {
+                MmqpTcpFormat::Message(message)
+                | MmqpTcpFormat::Publish(message)
+                | MmqpTcpFormat::Route(_, message) => Some(&message.target_queue),
+                MmqpTcpFormat::Poll(queue, _)
+                | MmqpTcpFormat::GroupPoll(queue, _, _)
+                | MmqpTcpFormat::Seek(queue, _, _)
+                | MmqpTcpFormat::Browse(queue, _, _)
+                | MmqpTcpFormat::LongPoll(_, _, queue)
+                | MmqpTcpFormat::Del(queue, _)
+                | MmqpTcpFormat::ChangeVisibility(queue, _, _)
+                | MmqpTcpFormat::BatchDel(queue, _)
+                | MmqpTcpFormat::BatchVisibility(queue, _) => Some(queue),
+                MmqpTcpFormat::BatchMessage(_)
+                | MmqpTcpFormat::Admin(_)
+                | MmqpTcpFormat::Ping
+                | MmqpTcpFormat::Invalid(_, _) => None,
+            }
+        }
+    }
+
+    // a copy of the request with the password replaced, safe to log. Requests too short to have a password
+    // are returned as they are
+    pub fn redact_request(request: &[u8]) -> Vec<u8> {
+        // MMQP|x.x|C|%username:
+        let mut cursor = 11;
+        let start = match peek_mmqp_length(request, cursor) {
+            Some(_) => {
+                String::from_mmqp_binary(request, &mut cursor);
+                cursor + 1
+            }
+            None => return request.to_vec(),
+        };
+        let mut end = start;
+        if peek_mmqp_length(request, end).is_none() {
+            return request[..start.min(request.len())].to_vec();
+        }
+        String::from_mmqp_binary(request, &mut end);
+
+        let mut redacted = request[..start].to_vec();
+        redacted.extend("<redacted>".to_string().to_mmqp_binary().unwrap());
+        redacted.extend(&request[end..]);
+        redacted
     }
 
     pub fn parse_tcp_request(request: Vec<u8>) -> MmqpTcpFormat {
-        //strip first 4 bytes to get rid of the magic MMQP header
-        let request = &request[4..];
+        parse_tcp_request_with_limit(request, usize::MAX)
+    }
+
+    // the user name of any request, read without parsing the rest of it. None if the request ends first
+    pub fn request_username(request: &[u8]) -> Option<String> {
+        // MMQP|x.x|C|
+        let mut cursor = 11;
+        peek_mmqp_length(request, cursor)?;
+        Some(String::from_mmqp_binary(request, &mut cursor))
+    }
+
+    // max_message_size is checked against the declared body length before the body is read, so an
+    // oversized message is rejected without allocating space for it
+    pub fn parse_tcp_request_with_limit(
+        request: Vec<u8>,
+        max_message_size: usize,
+    ) -> MmqpTcpFormat {
+        parse_tcp_request_with_limits(request, max_message_size, &|_| None)
+    }
+
+    // queue_limit is the max_message_size of a queue by name, if it has one. A send to the queue is held to
+    // the lower of it and max_message_size. A request that ends before it is complete is MalformedRequest
+    pub fn parse_tcp_request_with_limits(
+        request: Vec<u8>,
+        max_message_size: usize,
+        queue_limit: &dyn Fn(&str) -> Option<usize>,
+    ) -> MmqpTcpFormat {
+        match parse_frame(&request, max_message_size, queue_limit) {
+            Ok(parsed) => parsed,
+            Err(FrameError::Truncated) => MmqpTcpFormat::Invalid(
+                MmqpResponseCode::MalformedRequest,
+                "the request is incomplete".to_string(),
+            ),
+            Err(FrameError::Invalid(code, detail)) => MmqpTcpFormat::Invalid(code, detail),
+        }
+    }
 
-        //remove   the next byte to strip the delimiter
-        let request = &request[1..];
+    // for a request that is still arriving. None while more bytes are needed to finish it, a request that
+    // is already known to be too large or malformed is returned without waiting for the rest
+    pub fn parse_partial_request(
+        request: &[u8],
+        max_message_size: usize,
+        queue_limit: &dyn Fn(&str) -> Option<usize>,
+    ) -> Option<MmqpTcpFormat> {
+        match parse_frame(request, max_message_size, queue_limit) {
+            Err(FrameError::Truncated)
+            | Ok(MmqpTcpFormat::Admin(MmqpAdminCommand::Truncated(_))) => None,
+            Ok(parsed) => Some(parsed),
+            Err(FrameError::Invalid(code, detail)) => Some(MmqpTcpFormat::Invalid(code, detail)),
+        }
+    }
+
+    // what a request that is still arriving needs before it is worth parsing, see RequestScanner::scan
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum ScanResult {
+        More,
+        Parse,
+        Reject(MmqpResponseCode, String),
+    }
 
-        //next 3 bytes are the x.x version
-        let version = &request[0..3];
-        dbg!(version.clone());
-        //split version at a dot and pare both sides at u8
-        let version_major: u8 = version[0..1][0];
-        let version_minor: u8 = version[2..3][0];
+    // a field in the layout of a request
+    #[derive(Clone, Copy)]
+    enum Step {
+        // a pipe, a colon, a priority or a body kind
+        Byte,
+        // &number
+        Number,
+        // %string
+        String,
+        // a %string naming the queue whose max_message_size applies to the next Body
+        Queue,
+        // the message body, its length is checked as soon as it has arrived
+        Body,
+        // the steps one after the other
+        Sequence(&'static [Step]),
+        // a &count followed by the steps that many times
+        Repeat(&'static [Step]),
+        // the request may be complete, worth parsing before going on
+        Check,
+        // admin arguments: a pipe and a %string, any number of times. Only the parser knows how many
+        Arguments,
+    }
 
-        //move to that po
-        let request = &request[3..];
+    const AUTH: &[Step] = &[Step::String, Step::Byte, Step::String, Step::Byte];
+    // %key, then a type byte and a %string payload
+    const ATTRIBUTE: &[Step] = &[Step::String, Step::Byte, Step::String];
+    // |&attributes|priority|kind%message after the queue and group
+    const MESSAGE_REST: &[Step] = &[
+        Step::Byte,
+        Step::String,
+        Step::Byte,
+        Step::Repeat(ATTRIBUTE),
+        Step::Byte,
+        Step::Byte,
+        Step::Byte,
+        Step::Byte,
+        Step::Body,
+    ];
+    const MESSAGE: &[Step] = &[Step::Queue, Step::Sequence(MESSAGE_REST)];
+    // topics and exchanges are not queues, only the server wide limit applies
+    const PUBLISH: &[Step] = &[Step::String, Step::Sequence(MESSAGE_REST)];
 
-        //skip a byte for the delimiter
-        let request = &request[1..];
+    // the layout after MMQP|x.x|C| for each command, as read by the parse_as_ functions
+    fn layout(command: u8) -> &'static [Step] {
+        use Step::*;
+        match command {
+            b'M' => &[Sequence(AUTH), Sequence(MESSAGE)],
+            b'T' => &[Sequence(AUTH), Sequence(PUBLISH)],
+            b'X' => &[Sequence(AUTH), String, Byte, Sequence(PUBLISH)],
+            b'm' => &[Sequence(AUTH), Repeat(&[Byte, Sequence(MESSAGE)])],
+            b'D' => &[Sequence(AUTH), String, Byte, String],
+            b'd' => &[Sequence(AUTH), String, Byte, Repeat(&[Byte, String])],
+            b'V' => &[Sequence(AUTH), String, Byte, String, Byte, Number],
+            b'v' => &[
+                Sequence(AUTH),
+                String,
+                Byte,
+                Repeat(&[Byte, String, Byte, Number]),
+            ],
+            b'A' => &[Sequence(AUTH), String, Check, Arguments],
+            b'P' => &[Sequence(AUTH), String, Byte, Byte],
+            b'G' => &[Sequence(AUTH), String, Byte, String, Byte, Byte],
+            b'S' => &[
+                Sequence(AUTH),
+                String,
+                Byte,
+                String,
+                Byte,
+                Byte,
+                Byte,
+                Number,
+            ],
+            b'B' => &[
+                Sequence(AUTH),
+                String,
+                Byte,
+                Number,
+                Byte,
+                Number,
+                Byte,
+                Byte,
+            ],
+            // pings, and unknown commands the parser rejects
+            _ => &[],
+        }
+    }
 
-        //next byte is the command
-        let command = String::from_utf8(Vec::from(&request[0..1])).unwrap();
-        //move to that position
-        let request = &request[1..];
+    // finds where a request that is still arriving ends without parsing it, so it is parsed once rather
+    // than again after every read. Lengths are skipped without copying anything, and the scan carries on
+    // from where the last one stopped. Body lengths are checked against the limits as soon as they arrive
+    pub struct RequestScanner {
+        max_message_size: usize,
+        cursor: usize,
+        // the steps being worked through, the next one in each and the repeats left
+        stack: Vec<(&'static [Step], usize, usize)>,
+        // the limit for the next body, from its queue
+        body_limit: Option<usize>,
+        started: bool,
+    }
 
-        //strip a delimiter
-        let request = &request[1..];
+    impl RequestScanner {
+        pub fn new(max_message_size: usize) -> RequestScanner {
+            RequestScanner {
+                max_message_size,
+                cursor: 0,
+                stack: vec![],
+                body_limit: None,
+                started: false,
+            }
+        }
 
-        return match command.as_str() {
-            "M" => {
-                let message = parse_as_message(request.to_vec(), version_major, version_minor);
-                message
+        // request is everything that has arrived so far, it only ever grows between calls
+        pub fn scan(
+            &mut self,
+            request: &[u8],
+            queue_limit: &dyn Fn(&str) -> Option<usize>,
+        ) -> ScanResult {
+            if !self.started {
+                // MMQP|x.x|C|
+                if request.len() < 11 {
+                    return ScanResult::More;
+                }
+                self.started = true;
+                self.cursor = 11;
+                self.stack.push((layout(request[9]), 0, 1));
             }
-            "P" => {
-                let poll = parse_as_poll(request.to_vec());
-                poll
+
+            while let Some((steps, next, repeats)) = self.stack.last_mut() {
+                if *next == steps.len() {
+                    if *repeats > 1 {
+                        *repeats -= 1;
+                        *next = 0;
+                    } else {
+                        self.stack.pop();
+                    }
+                    continue;
+                }
+                let step = steps[*next];
+
+                // each step moves the cursor only once everything it reads has arrived, and may have
+                // steps of its own to work through next
+                let mut cursor = self.cursor;
+                let read = match step {
+                    Step::Byte => skip(request, &mut cursor, 1).map(|_| None),
+                    Step::Number => number(request, &mut cursor).map(|_| None),
+                    Step::String => value(request, &mut cursor).map(|_| None),
+                    Step::Queue => value(request, &mut cursor).map(|value| {
+                        self.body_limit = queue_limit(&String::from_utf8_lossy(value));
+                        None
+                    }),
+                    Step::Body => {
+                        let max_message_size = match self.body_limit {
+                            Some(limit) => limit.min(self.max_message_size),
+                            None => self.max_message_size,
+                        };
+                        if let Ok(length) = number(request, &mut cursor.clone()) {
+                            if length > max_message_size {
+                                return ScanResult::Reject(
+                                    MmqpResponseCode::MessageTooLarge,
+                                    format!("{} > {}", length, max_message_size),
+                                );
+                            }
+                        }
+                        value(request, &mut cursor).map(|_| None)
+                    }
+                    // the count is not trusted, nothing is allocated for it
+                    Step::Repeat(inner) => {
+                        number(request, &mut cursor).map(|count| Some((inner, count)))
+                    }
+                    Step::Sequence(inner) => Ok(Some((inner, 1))),
+                    Step::Check => Ok(None),
+                    Step::Arguments => skip(request, &mut cursor, 1)
+                        .and_then(|_| value(request, &mut cursor))
+                        .map(|_| None),
+                };
+                let steps = match read {
+                    Ok(steps) => steps,
+                    Err(DecodeError::Truncated) => return ScanResult::More,
+                    // malformed requests are left to the parser to report
+                    Err(DecodeError::Malformed) => return ScanResult::Parse,
+                };
+                self.cursor = cursor;
+
+                match step {
+                    Step::Check => {
+                        *next += 1;
+                        return ScanResult::Parse;
+                    }
+                    // only the parser knows how many arguments the command takes, it is asked after each
+                    Step::Arguments => return ScanResult::Parse,
+                    _ => *next += 1,
+                }
+                if let Some((inner, count)) = steps.filter(|(_, count)| *count > 0) {
+                    self.stack.push((inner, 0, count));
+                }
+            }
+            ScanResult::Parse
+        }
+    }
+
+    // skips count bytes
+    fn skip(request: &[u8], cursor: &mut usize, count: usize) -> Result<(), DecodeError> {
+        if request.len().saturating_sub(*cursor) < count {
+            return Err(DecodeError::Truncated);
+        }
+        *cursor += count;
+        Ok(())
+    }
+
+    // a &number, the cursor is left alone unless all of it has arrived
+    fn number(request: &[u8], cursor: &mut usize) -> Result<usize, DecodeError> {
+        let mut end = *cursor;
+        let number = usize::try_from_mmqp_binary(request, &mut end)?;
+        *cursor = end;
+        Ok(number)
+    }
+
+    // a %string or a body without copying it, the cursor is left alone unless all of it has arrived
+    fn value<'a>(request: &'a [u8], cursor: &mut usize) -> Result<&'a [u8], DecodeError> {
+        let mut start = *cursor;
+        let length = number(request, &mut start)?;
+        skip(request, &mut start.clone(), length)?;
+        *cursor = start + length;
+        Ok(&request[start..*cursor])
+    }
+
+    // Truncated requests may still be completed by bytes that have not arrived yet
+    enum FrameError {
+        Truncated,
+        Invalid(MmqpResponseCode, String),
+    }
+
+    type ParseResult<T> = Result<T, FrameError>;
+
+    // every length is checked against what is left of the request before anything is allocated
+    fn read<T: MmqpEncoding>(request: &[u8], cursor: &mut usize, field: &str) -> ParseResult<T> {
+        T::try_from_mmqp_binary(request, cursor).map_err(|e| match e {
+            DecodeError::Truncated => FrameError::Truncated,
+            DecodeError::Malformed => {
+                FrameError::Invalid(MmqpResponseCode::MalformedRequest, field.to_string())
+            }
+        })
+    }
+
+    fn read_byte(request: &[u8], cursor: &mut usize) -> ParseResult<u8> {
+        let byte = *request.get(*cursor).ok_or(FrameError::Truncated)?;
+        *cursor += 1;
+        Ok(byte)
+    }
+
+    // %username:%password| leaving the cursor after the pipe
+    fn read_auth(request: &[u8], cursor: &mut usize) -> ParseResult<(String, String)> {
+        let username = read(request, cursor, "username")?;
+        *cursor += 1;
+        let password = read(request, cursor, "password")?;
+        *cursor += 1;
+        Ok((username, password))
+    }
+
+    // room for count entries of at least min_entry_size bytes, but never more than the rest of the request
+    // could hold. The count is whatever the client sent
+    fn capacity_for<T>(
+        count: usize,
+        request: &[u8],
+        cursor: usize,
+        min_entry_size: usize,
+    ) -> Vec<T> {
+        Vec::with_capacity(count.min(request.len().saturating_sub(cursor) / min_entry_size))
+    }
+
+    fn parse_frame(
+        request: &[u8],
+        max_message_size: usize,
+        queue_limit: &dyn Fn(&str) -> Option<usize>,
+    ) -> ParseResult<MmqpTcpFormat> {
+        // MMQP|x.x|C|
+        if request.len() < 11 {
+            return Err(FrameError::Truncated);
+        }
+
+        //the x.x version is two bytes either side of a dot
+        let version_major: u8 = request[5];
+        let version_minor: u8 = request[7];
+
+        //the command byte is followed by a delimiter, then the rest depends on the command
+        let command = request[9];
+        let request = &request[11..];
+
+        // topics and exchanges are not queues, only the server wide limit applies to them
+        let no_queue_limit = &|_: &str| None;
+
+        let parsed = match command {
+            b'M' => MmqpTcpFormat::Message(parse_as_message(
+                request,
+                version_major,
+                version_minor,
+                max_message_size,
+                queue_limit,
+            )?),
+            b'T' => MmqpTcpFormat::Publish(parse_as_message(
+                request,
+                version_major,
+                version_minor,
+                max_message_size,
+                no_queue_limit,
+            )?),
+            b'X' => parse_as_route(request, version_major, version_minor, max_message_size)?,
+            b'm' => parse_as_batch_message(
+                request,
+                version_major,
+                version_minor,
+                max_message_size,
+                queue_limit,
+            )?,
+            b'D' => parse_as_del(request)?,
+            b'd' => parse_as_batch_del(request)?,
+            b'V' => parse_as_visibility(request)?,
+            b'v' => parse_as_batch_visibility(request)?,
+            b'A' => MmqpTcpFormat::Admin(crate::admin::admin::parse_admin(request)),
+            b'P' => parse_as_poll(request)?,
+            b'G' => parse_as_group_poll(request)?,
+            b'S' => parse_as_seek(request)?,
+            b'B' => parse_as_browse(request)?,
+            // not a command of any version
+            command if !command.is_ascii() => {
+                return Err(FrameError::Invalid(
+                    MmqpResponseCode::UnknownCommand,
+                    format!("{:#04x}", command),
+                ))
             }
             _ => MmqpTcpFormat::Ping,
         };
+        Ok(parsed)
     }
 
-    fn parse_as_poll(request: Vec<u8>) -> MmqpTcpFormat {
+    fn parse_as_poll(request: &[u8]) -> ParseResult<MmqpTcpFormat> {
         let mut cursor = 0usize;
-        let _username: String = String::from_mmqp_binary(&request, &mut cursor);
-        // println!("{}", username);
+        read_auth(request, &mut cursor)?;
+        let target_queue: String = read(request, &mut cursor, "queue")?;
         cursor += 1;
-        let _password: String = String::from_mmqp_binary(&request, &mut cursor);
-        // println!("{}", password);
+        let receive_count: u8 = read_byte(request, &mut cursor)?;
+
+        Ok(MmqpTcpFormat::Poll(target_queue, receive_count))
+    }
+
+    pub fn serialise_poll(username: &str, password: &str, queue: &str, count: u8) -> Box<[u8]> {
+        let mut binary = b"MMQP|".to_vec();
+        binary.extend([0u8, b'.', 1u8]);
+        binary.extend(b"|P|");
+        binary.extend(username.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b":");
+        binary.extend(password.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.extend(queue.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.push(count);
+        binary.into_boxed_slice()
+    }
+
+    // %username:%password|%queue|%group|count
+    fn parse_as_group_poll(request: &[u8]) -> ParseResult<MmqpTcpFormat> {
+        let mut cursor = 0usize;
+        read_auth(request, &mut cursor)?;
+        let target_queue: String = read(request, &mut cursor, "queue")?;
         cursor += 1;
-        let target_queue: String = String::from_mmqp_binary(&request, &mut cursor);
-        // println!("{}", target_queue);
+        let group: String = read(request, &mut cursor, "group")?;
         cursor += 1;
-        let receive_count: u8 = request[cursor];
-        // println!("{}", message);
+        let receive_count: u8 = read_byte(request, &mut cursor)?;
 
-        MmqpTcpFormat::Poll(target_queue, receive_count as u8)
+        Ok(MmqpTcpFormat::GroupPoll(target_queue, group, receive_count))
     }
 
-    fn parse_as_message(request: Vec<u8>, version_major: u8, version_minor: u8) -> MmqpTcpFormat {
-        // dbg!(version);
-        // dbg!(command);
-        // dbg!(request);
-        // dbg!(version_major);
-        // dbg!(version_minor);
+    // %username:%password|%queue|%group|mode|&value
+    // mode is O to seek to the offset in value or T to the receive time (ms since the unix epoch) in value
+    fn parse_as_seek(request: &[u8]) -> ParseResult<MmqpTcpFormat> {
+        let mut cursor = 0usize;
+        read_auth(request, &mut cursor)?;
+        let target_queue: String = read(request, &mut cursor, "queue")?;
+        cursor += 1;
+        let group: String = read(request, &mut cursor, "group")?;
+        cursor += 1;
+        let mode = read_byte(request, &mut cursor)?;
+        cursor += 1;
+        let value: usize = read(request, &mut cursor, "seek value")?;
 
-        // println!("pre req: {:?}", request.clone());
+        let target = match mode {
+            b'O' => SeekTarget::Offset(value as u64),
+            b'T' => SeekTarget::Time(value as u128),
+            _ => {
+                return Err(FrameError::Invalid(
+                    MmqpResponseCode::MalformedRequest,
+                    format!("unknown seek mode {}", mode as char),
+                ))
+            }
+        };
+
+        Ok(MmqpTcpFormat::Seek(target_queue, group, target))
+    }
+
+    // MMQP|0.1|S|%username:%password|%queue|%group|mode|&value
+    pub fn serialise_seek(
+        username: &str,
+        password: &str,
+        queue: &str,
+        group: &str,
+        target: SeekTarget,
+    ) -> Box<[u8]> {
+        let (mode, value) = match target {
+            SeekTarget::Offset(offset) => (b'O', offset as usize),
+            SeekTarget::Time(time) => (b'T', time as usize),
+        };
+
+        let mut binary = b"MMQP|".to_vec();
+        binary.extend([0u8, b'.', 1u8]);
+        binary.extend(b"|S|");
+        binary.extend(username.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b":");
+        binary.extend(password.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.extend(queue.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.extend(group.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.push(mode);
+        binary.extend(b"|");
+        binary.extend(value.to_mmqp_binary().unwrap());
+        binary.into_boxed_slice()
+    }
+
+    // %username:%password|%queue|&section|&position|count
+    fn parse_as_browse(request: &[u8]) -> ParseResult<MmqpTcpFormat> {
         let mut cursor = 0usize;
-        let username: String = String::from_mmqp_binary(&request, &mut cursor);
-        // println!("{}", username);
+        read_auth(request, &mut cursor)?;
+        let target_queue: String = read(request, &mut cursor, "queue")?;
         cursor += 1;
-        let password: String = String::from_mmqp_binary(&request, &mut cursor);
-        // println!("{}", password);
+        let section: usize = read(request, &mut cursor, "section")?;
         cursor += 1;
-        let target_queue: String = String::from_mmqp_binary(&request, &mut cursor);
-        // println!("{}", target_queue);
+        let position: usize = read(request, &mut cursor, "position")?;
         cursor += 1;
-        let message: String = String::from_mmqp_binary(&request, &mut cursor);
-        // println!("{}", message);
+        let count: u8 = read_byte(request, &mut cursor)?;
 
-        MmqpTcpFormat::Message(MmqpMessage {
-            version_major,
-            version_minor,
-            username,
-            password,
+        let from = BrowsePosition {
+            section,
+            position: position as u64,
+        };
+        Ok(MmqpTcpFormat::Browse(target_queue, from, count))
+    }
+
+    // MMQP|0.1|B|%username:%password|%queue|&section|&position|count
+    pub fn serialise_browse(
+        username: &str,
+        password: &str,
+        queue: &str,
+        from: BrowsePosition,
+        count: u8,
+    ) -> Box<[u8]> {
+        let mut binary = b"MMQP|".to_vec();
+        binary.extend([0u8, b'.', 1u8]);
+        binary.extend(b"|B|");
+        binary.extend(username.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b":");
+        binary.extend(password.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.extend(queue.to_string().to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.extend(from.section.to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.extend((from.position as usize).to_mmqp_binary().unwrap());
+        binary.extend(b"|");
+        binary.push(count);
+        binary.into_boxed_slice()
+    }
+
+    // %username:%password|%queue|%messageId
+    fn parse_as_del(request: &[u8]) -> ParseResult<MmqpTcpFormat> {
+        let mut cursor = 0usize;
+        read_auth(request, &mut cursor)?;
+        let target_queue: String = read(request, &mut cursor, "queue")?;
+        cursor += 1;
+        let message_id: String = read(request, &mut cursor, "message id")?;
+
+        Ok(MmqpTcpFormat::Del(target_queue, message_id))
+    }
+
+    // %username:%password|%queue|%messageId|&timeout
+    fn parse_as_visibility(request: &[u8]) -> ParseResult<MmqpTcpFormat> {
+        let mut cursor = 0usize;
+        read_auth(request, &mut cursor)?;
+        let target_queue: String = read(request, &mut cursor, "queue")?;
+        cursor += 1;
+        let message_id: String = read(request, &mut cursor, "message id")?;
+        cursor += 1;
+        let timeout: usize = read(request, &mut cursor, "timeout")?;
+
+        Ok(MmqpTcpFormat::ChangeVisibility(
+            target_queue,
+            message_id,
+            timeout as u64,
+        ))
+    }
+
+    // %username:%password|%queue|&count|%messageId|%messageId...
+    fn parse_as_batch_del(request: &[u8]) -> ParseResult<MmqpTcpFormat> {
+        let mut cursor = 0usize;
+        read_auth(request, &mut cursor)?;
+        let target_queue: String = read(request, &mut cursor, "queue")?;
+        cursor += 1;
+        let count: usize = read(request, &mut cursor, "count")?;
+
+        // pipe and an empty id
+        let mut message_ids = capacity_for(count, request, cursor, 2);
+        for _ in 0..count {
+            cursor += 1; //pipe
+            message_ids.push(read(request, &mut cursor, "message id")?);
+        }
+
+        Ok(MmqpTcpFormat::BatchDel(target_queue, message_ids))
+    }
+
+    // %username:%password|%queue|&count|%messageId|&timeout|%messageId|&timeout...
+    fn parse_as_batch_visibility(request: &[u8]) -> ParseResult<MmqpTcpFormat> {
+        let mut cursor = 0usize;
+        read_auth(request, &mut cursor)?;
+        let target_queue: String = read(request, &mut cursor, "queue")?;
+        cursor += 1;
+        let count: usize = read(request, &mut cursor, "count")?;
+
+        // pipes, an empty id and a zero timeout
+        let mut entries = capacity_for(count, request, cursor, 4);
+        for _ in 0..count {
+            cursor += 1; //pipe
+            let message_id: String = read(request, &mut cursor, "message id")?;
+            cursor += 1; //pipe
+            let timeout: usize = read(request, &mut cursor, "timeout")?;
+            entries.push((message_id, timeout as u64));
+        }
+
+        Ok(MmqpTcpFormat::BatchVisibility(target_queue, entries))
+    }
+
+    fn parse_as_message(
+        request: &[u8],
+        version_major: u8,
+        version_minor: u8,
+        max_message_size: usize,
+        queue_limit: &dyn Fn(&str) -> Option<usize>,
+    ) -> ParseResult<MmqpMessage> {
+        let mut cursor = 0usize;
+        let (username, password) = read_auth(request, &mut cursor)?;
+
+        let mut message =
+            parse_message_fields(request, &mut cursor, max_message_size, queue_limit)?;
+        message.version_major = version_major;
+        message.version_minor = version_minor;
+        message.username = username;
+        message.password = password;
+
+        Ok(message)
+    }
+
+    // %routingKey|%exchange|... the rest is the same as a send
+    fn parse_as_route(
+        request: &[u8],
+        version_major: u8,
+        version_minor: u8,
+        max_message_size: usize,
+    ) -> ParseResult<MmqpTcpFormat> {
+        let mut cursor = 0usize;
+        let (username, password) = read_auth(request, &mut cursor)?;
+        let routing_key: String = read(request, &mut cursor, "routing key")?;
+        cursor += 1;
+
+        let mut message = parse_message_fields(request, &mut cursor, max_message_size, &|_| None)?;
+        message.version_major = version_major;
+        message.version_minor = version_minor;
+        message.username = username;
+        message.password = password;
+
+        Ok(MmqpTcpFormat::Route(routing_key, message))
+    }
+
+    fn parse_as_batch_message(
+        request: &[u8],
+        version_major: u8,
+        version_minor: u8,
+        max_message_size: usize,
+        queue_limit: &dyn Fn(&str) -> Option<usize>,
+    ) -> ParseResult<MmqpTcpFormat> {
+        let mut cursor = 0usize;
+        let (username, password) = read_auth(request, &mut cursor)?;
+
+        let count: usize = read(request, &mut cursor, "count")?;
+
+        // |%queue|%group|&attributes|priority|kind%message with every field empty is 11 bytes
+        let mut messages = capacity_for(count, request, cursor, 11);
+        for _ in 0..count {
+            cursor += 1; //pipe before each entry
+
+            // one oversized entry invalidates the whole frame
+            let mut message =
+                parse_message_fields(request, &mut cursor, max_message_size, queue_limit)?;
+            message.version_major = version_major;
+            message.version_minor = version_minor;
+            message.username = username.clone();
+            message.password = password.clone();
+            messages.push(message);
+        }
+
+        Ok(MmqpTcpFormat::BatchMessage(messages))
+    }
+
+    // %queue|%messageGroupId|&attributes|priority|kind%message
+    // shared by single and batched sends. auth and version are filled in by the caller
+    fn parse_message_fields(
+        request: &[u8],
+        cursor: &mut usize,
+        max_message_size: usize,
+        queue_limit: &dyn Fn(&str) -> Option<usize>,
+    ) -> ParseResult<MmqpMessage> {
+        let target_queue: String = read(request, cursor, "queue")?;
+        *cursor += 1;
+        let message_group: String = read(request, cursor, "message group")?;
+        *cursor += 1;
+        let attributes: MmqpAttributes = read(request, cursor, "attributes")?;
+        *cursor += 1;
+        let priority = read_byte(request, cursor)?;
+        *cursor += 1; //pipe
+        let body_kind = MmqpBodyKind::from_byte(read_byte(request, cursor)?).ok_or_else(|| {
+            FrameError::Invalid(MmqpResponseCode::MalformedRequest, "body kind".to_string())
+        })?;
+
+        // the length is checked as soon as it has arrived, before the body it announces
+        let max_message_size = queue_limit(&target_queue)
+            .map_or(max_message_size, |limit| limit.min(max_message_size));
+        let length: usize = read(request, &mut cursor.clone(), "message body length")?;
+        if length > max_message_size {
+            return Err(FrameError::Invalid(
+                MmqpResponseCode::MessageTooLarge,
+                format!("{} > {}", length, max_message_size),
+            ));
+        }
+        let message: Vec<u8> = read(request, cursor, "message body")?;
+
+        Ok(MmqpMessage {
+            version_major: 0,
+            version_minor: 1,
+            username: String::new(),
+            password: String::new(),
             target_queue,
+            body_kind,
             message,
-            message_group: "main".to_string(), //todo implement message groups
+            message_group,
+            attributes,
+            priority,
         })
     }
 }
@@ -35,9 +35,11 @@ pub mod tests {
 
     #[test]
     fn test_serialise_size() {
+        use crate::SerialisationStrategy;
+
         let message = MmqpMessage::new().normalise();
 
-        let bytes = message.serialise();
+        let bytes = message.serialise(SerialisationStrategy::Storage);
 
         println!("size after serialise {}", bytes.len());
 
@@ -48,9 +50,11 @@ pub mod tests {
 
     #[test]
     fn raw_receive() {
+        use crate::SerialisationStrategy;
+
         let message = MmqpMessage::new().normalise();
 
-        let bytes = message.serialise().to_vec();
+        let bytes = message.serialise(SerialisationStrategy::Storage).to_vec();
 
         let raw = MmqpNormalisedMessage::raw(bytes.to_vec().borrow_mut(), &mut 0);
 
@@ -187,20 +191,187 @@ pub mod tests {
 
     #[test]
     fn encode_message() {
+        use crate::attributes::attributes::MmqpAttributes;
+        use crate::{MmqpBodyKind, SerialisationStrategy};
+
         let message = MmqpMessage {
             username: "testusername".to_string(),
             password: "testpassword".to_string(),
             target_queue: "testqueue".to_string(),
-            message: "this is a message".to_string(),
+            body_kind: MmqpBodyKind::Text,
+            message: b"this is a message".to_vec(),
             version_major: 0,
             version_minor: 1,
             message_group: "mainmessagegroup".to_string(),
+            attributes: MmqpAttributes::new(),
+            priority: 3,
         };
 
-        let binary = message.serialise();
+        let binary = message.serialise(SerialisationStrategy::Storage);
         let mut cursor = 0usize;
         let recreated = MmqpMessage::deserialise(binary.to_vec().borrow_mut(), &mut cursor);
 
         assert_eq!(message, recreated)
     }
+
+    #[test]
+    fn encode_message_with_attributes() {
+        use crate::attributes::attributes::MmqpAttributeValue;
+        use crate::SerialisationStrategy;
+
+        let mut message = MmqpMessage::new();
+        message.attributes.insert(
+            "content-type".to_string(),
+            MmqpAttributeValue::String("application/json".to_string()),
+        );
+        message
+            .attributes
+            .insert("retries".to_string(), MmqpAttributeValue::Number(-3));
+        message.attributes.insert(
+            "checksum".to_string(),
+            MmqpAttributeValue::Binary(vec![0x00, 0xff, 0x7c, 0x00]),
+        );
+
+        let binary = message.serialise(SerialisationStrategy::Storage);
+        let mut cursor = 0usize;
+        let recreated = MmqpMessage::deserialise(binary.to_vec().borrow_mut(), &mut cursor);
+
+        assert_eq!(message, recreated);
+        assert_eq!(
+            cursor,
+            binary.len(),
+            "cursor should be at the end of the message"
+        );
+
+        let normalised = recreated.normalise();
+        let bytes = normalised.serialise(SerialisationStrategy::Storage);
+        assert_eq!(
+            bytes.len(),
+            normalised.get_size() + normalised.get_size().mmqp_binary_size(),
+            "storage size should be the prefix plus the reported size"
+        );
+
+        let mut cursor = 0usize;
+        let stored = MmqpNormalisedMessage::deserialise(bytes.to_vec().borrow_mut(), &mut cursor);
+        assert_eq!(stored.attributes, message.attributes);
+        assert_eq!(stored.message, message.message);
+    }
+
+    #[test]
+    fn binary_body_round_trip() {
+        use crate::tcp_parse::tcp_parse::{parse_tcp_request, MmqpTcpFormat};
+        use crate::{MmqpBodyKind, SerialisationStrategy};
+
+        // not valid utf8, would have panicked when bodies were strings
+        let body: Vec<u8> = vec![0xff, 0xfe, 0x00, 0x80, 0x7c, 0xc3, 0x28];
+
+        let mut message = MmqpMessage::new();
+        message.set_binary(body.clone());
+
+        let parsed = parse_tcp_request(message.serialise(SerialisationStrategy::Wire).to_vec());
+        let parsed = match parsed {
+            MmqpTcpFormat::Message(m) => m,
+            _ => panic!("should parse as a message"),
+        };
+        assert_eq!(parsed.body_kind, MmqpBodyKind::Binary);
+        assert_eq!(parsed.message, body);
+
+        let mut readers: RotatingReadBuffers<MmqpNormalisedMessage> =
+            RotatingReadBuffers::new(4, 65536);
+        readers.push_value(parsed.normalise());
+
+        let stored = readers.next().unwrap();
+        assert_eq!(stored.body_kind, MmqpBodyKind::Binary);
+        assert_eq!(stored.message, body);
+    }
+
+    #[test]
+    fn rotate_grow_and_compact() {
+        let mut readers: RotatingReadBuffers<MmqpNormalisedMessage> =
+            RotatingReadBuffers::new(2, 512);
+
+        // each message is a little over 100 bytes so this spills over many buffers
+        for i in 0..40 {
+            let mut message = MmqpMessage::new();
+            message.set_text(&format!("message {}", i));
+            readers.push_value(message.normalise());
+        }
+        assert!(readers.num_buffers > 2, "buffers should grow under load");
+
+        for i in 0..40 {
+            let message = readers.next();
+            assert!(message.is_some(), "message {} should be readable", i);
+            assert_eq!(
+                message.unwrap().message,
+                format!("message {}", i).into_bytes()
+            );
+        }
+        assert!(readers.next().is_none());
+        assert!(readers.is_empty());
+
+        assert!(readers.compact(), "drained buffers should compact");
+        assert_eq!(readers.num_buffers, 2);
+        assert_eq!(readers.buffers.len(), 2);
+
+        readers.push_value(MmqpMessage::new().normalise());
+        assert!(!readers.compact(), "cannot compact with unread values");
+        assert!(readers.next().is_some());
+    }
+
+    #[test]
+    fn interleaved_push_and_read_keeps_order() {
+        let mut readers: RotatingReadBuffers<MmqpNormalisedMessage> =
+            RotatingReadBuffers::new(3, 512);
+
+        let mut pushed = 0;
+        let mut read = 0;
+        for _ in 0..100 {
+            for _ in 0..3 {
+                let mut message = MmqpMessage::new();
+                message.set_text(&pushed.to_string());
+                readers.push_value(message.normalise());
+                pushed += 1;
+            }
+            for _ in 0..2 {
+                let message = readers.next().unwrap();
+                assert_eq!(message.message, read.to_string().into_bytes());
+                read += 1;
+            }
+        }
+
+        while let Some(message) = readers.next() {
+            assert_eq!(message.message, read.to_string().into_bytes());
+            read += 1;
+        }
+        assert_eq!(read, pushed);
+    }
+
+    #[test]
+    fn peek_at_positions() {
+        let mut readers: RotatingReadBuffers<MmqpNormalisedMessage> =
+            RotatingReadBuffers::new(2, 512);
+
+        for i in 0..20 {
+            let mut message = MmqpMessage::new();
+            message.set_text(&i.to_string());
+            readers.push_value(message.normalise());
+        }
+
+        // peeking walks the values across buffers without reading them
+        let mut position = readers.read_position();
+        for i in 0..20 {
+            let (message, next) = readers.peek_at(position).unwrap();
+            assert_eq!(message.message, i.to_string().into_bytes());
+            position = next;
+        }
+        assert_eq!(position, readers.write_position());
+        assert!(readers.peek_at(position).is_none());
+
+        // positions stay valid as the reader moves past other values
+        let (_, second) = readers.peek_at(readers.read_position()).unwrap();
+        readers.next();
+        assert_eq!(readers.read_position(), second);
+        assert_eq!(readers.peek_at(second).unwrap().0.message, b"1".to_vec());
+        assert!(readers.peek_at(0).is_none(), "already read");
+    }
 }
@@ -0,0 +1,142 @@
+pub mod topic {
+    use crate::attributes::attributes::MmqpAttributes;
+    use crate::filter::filter::{parse_filter, FilterError};
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    fn subscriptions() {
+        let mut topic = TopicConfiguration::new("orders".to_string());
+
+        assert!(topic.subscribe("billing"));
+        assert!(topic.subscribe("shipping"));
+        assert!(!topic.subscribe("billing"), "already subscribed");
+        assert_eq!(topic.subscriptions, vec!["billing", "shipping"]);
+
+        assert!(topic.unsubscribe("billing"));
+        assert!(!topic.unsubscribe("billing"));
+
+        let json = serialise_topics(&[topic.clone()]);
+        assert_eq!(deserialise_topics(&json).unwrap().into_vec(), vec![topic]);
+    }
+
+    #[test]
+    fn filtered_subscribers() {
+        use crate::attributes::attributes::MmqpAttributeValue;
+
+        let mut topic = TopicConfiguration::new("orders".to_string());
+        topic.subscribe("all");
+        topic.subscribe("eu");
+        assert!(topic.set_filter("eu", Some(r#"region = "eu""#)).is_ok());
+        assert!(topic.set_filter("eu", Some("region =")).is_err());
+
+        let mut attributes = MmqpAttributes::new();
+        assert_eq!(topic.subscribers("", &attributes), vec!["all"]);
+
+        attributes.insert(
+            "region".to_string(),
+            MmqpAttributeValue::String("eu".to_string()),
+        );
+        assert_eq!(topic.subscribers("", &attributes), vec!["all", "eu"]);
+
+        // the filter goes with the subscription
+        topic.unsubscribe("eu");
+        assert!(topic.filters.is_empty());
+    }
+
+    // a topic publishes every message it receives to each of its subscribed queues. Each queue gets its
+    // own copy (with its own message id), so subscribers consume independently
+    #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct TopicConfiguration {
+        pub name: String,
+
+        // queue names, in the order they subscribed
+        #[serde(default)]
+        pub subscriptions: Vec<String>,
+
+        // queue name -> filter expression (see filter.rs). Subscriptions without one get every message
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        pub filters: BTreeMap<String, String>,
+    }
+
+    // topic name -> topic
+    pub type TopicMap = HashMap<String, TopicConfiguration>;
+
+    impl TopicConfiguration {
+        pub fn new(name: String) -> TopicConfiguration {
+            TopicConfiguration {
+                name,
+                subscriptions: vec![],
+                filters: BTreeMap::new(),
+            }
+        }
+
+        // returns false if the queue was already subscribed
+        pub fn subscribe(&mut self, queue: &str) -> bool {
+            if self.subscriptions.iter().any(|q| q == queue) {
+                return false;
+            }
+            self.subscriptions.push(queue.to_string());
+            true
+        }
+
+        // returns false if the queue was not subscribed
+        pub fn unsubscribe(&mut self, queue: &str) -> bool {
+            let before = self.subscriptions.len();
+            self.subscriptions.retain(|q| q != queue);
+            self.filters.remove(queue);
+            self.subscriptions.len() != before
+        }
+
+        // replace the filter of a subscription, None removes it. The filter is checked before it is kept
+        pub fn set_filter(&mut self, queue: &str, filter: Option<&str>) -> Result<(), FilterError> {
+            match filter {
+                Some(filter) => {
+                    parse_filter(filter)?;
+                    self.filters.insert(queue.to_string(), filter.to_string());
+                }
+                None => {
+                    self.filters.remove(queue);
+                }
+            }
+            Ok(())
+        }
+
+        // the subscribed queues a message with this group and attributes is published to.
+        // filters are checked when they are set, one that no longer parses (eg the file was edited by
+        // hand) matches nothing
+        pub fn subscribers(&self, group: &str, attributes: &MmqpAttributes) -> Vec<String> {
+            self.subscriptions
+                .iter()
+                .filter(|queue| match self.filters.get(*queue) {
+                    Some(filter) => match parse_filter(filter) {
+                        Ok(filter) => filter.matches(group, attributes),
+                        Err(e) => {
+                            tracing::warn!(
+                                topic = %self.name,
+                                queue = %queue,
+                                error = %e,
+                                "invalid filter"
+                            );
+                            false
+                        }
+                    },
+                    None => true,
+                })
+                .cloned()
+                .collect()
+        }
+    }
+
+    pub fn deserialise_topics(
+        settings: &str,
+    ) -> Result<Box<[TopicConfiguration]>, serde_json::Error> {
+        serde_json::from_str(settings)
+    }
+
+    // sorted by name so the file does not churn between saves
+    pub fn serialise_topics(topics: &[TopicConfiguration]) -> String {
+        let mut topics = topics.to_vec();
+        topics.sort_by(|a, b| a.name.cmp(&b.name));
+        serde_json::to_string_pretty(&topics).unwrap()
+    }
+}
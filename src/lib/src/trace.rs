@@ -0,0 +1,306 @@
+pub mod trace {
+    use crate::attributes::attributes::{
+        MmqpAttributeValue, MmqpAttributes, TRACEPARENT_ATTRIBUTE, TRACESTATE_ATTRIBUTE,
+    };
+    use crate::normalised_message::normalised_message::MmqpNormalisedMessage;
+    use crate::MmqpMessage;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::Context;
+    use rand::RngCore;
+    use std::str::FromStr;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    #[test]
+    fn trace_context_propagation() {
+        use crate::normalised_message::normalised_message::Receivable;
+        use crate::{MmqpSerialisable, SerialisationStrategy};
+
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let context = TraceContext::parse(traceparent, Some("congo=t61rcWkgMzE")).unwrap();
+        assert_eq!(context.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.span_id, "00f067aa0ba902b7");
+        assert!(context.sampled());
+        assert_eq!(context.traceparent(), traceparent);
+
+        let child = context.child();
+        assert_eq!(child.trace_id, context.trace_id);
+        assert_ne!(child.span_id, context.span_id);
+        assert_eq!(child.state, context.state);
+        assert!(TraceContext::parse(&TraceContext::new_root().traceparent(), None).is_some());
+        assert_eq!(
+            TraceContext::from_span_context(&context.span_context()),
+            Some(context.clone())
+        );
+
+        // later versions can add fields after the flags, version 00 cannot
+        assert!(TraceContext::parse(&format!("01{}-extra", &traceparent[2..]), None).is_some());
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-600f067aa0ba902b7-01",
+        ] {
+            assert!(TraceContext::parse(invalid, None).is_none(), "{}", invalid);
+        }
+
+        // the context is kept through storage and comes back with the message on poll
+        let mut message = MmqpMessage::new();
+        message.set_text("hello");
+        context.insert_into(&mut message.attributes);
+        let mut stored = message
+            .normalise()
+            .serialise(SerialisationStrategy::Storage)
+            .to_vec();
+        let polled = MmqpNormalisedMessage::deserialise(&mut stored, &mut 0);
+        assert_eq!(
+            TraceContext::from_attributes(&polled.attributes),
+            Some(context)
+        );
+
+        // a malformed traceparent is dropped with its tracestate instead of failing the send
+        let mut message = MmqpMessage::new();
+        message.attributes.insert(
+            TRACEPARENT_ATTRIBUTE.to_string(),
+            MmqpAttributeValue::String("not a traceparent".to_string()),
+        );
+        message.attributes.insert(
+            TRACESTATE_ATTRIBUTE.to_string(),
+            MmqpAttributeValue::String("congo=t61rcWkgMzE".to_string()),
+        );
+        assert!(message.normalise().attributes.is_empty());
+    }
+
+    // the W3C trace context of a message: the trace it belongs to and the span that sent it
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct TraceContext {
+        // 32 lowercase hex characters
+        pub trace_id: String,
+
+        // 16 lowercase hex characters, the parent id of the traceparent header
+        pub span_id: String,
+
+        // bit 0 is sampled
+        pub flags: u8,
+
+        // vendor specific entries, passed on untouched
+        pub state: Option<String>,
+    }
+
+    // tracestate is capped at 32 entries of up to 256 characters each. Longer values are dropped rather
+    // than stored with every message
+    const MAX_TRACESTATE_LEN: usize = 512;
+
+    fn is_lower_hex(value: &str, len: usize) -> bool {
+        value.len() == len
+            && value
+                .bytes()
+                .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    }
+
+    fn random_hex(len: usize) -> String {
+        let mut bytes = vec![0u8; len / 2];
+        // all zero ids are invalid
+        while bytes.iter().all(|b| *b == 0) {
+            rand::thread_rng().fill_bytes(&mut bytes);
+        }
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    impl TraceContext {
+        // version-traceid-parentid-flags, eg 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01.
+        // None when traceparent is malformed, the state is only kept with a valid traceparent
+        pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<TraceContext> {
+            let mut fields = traceparent.split('-');
+            let version = fields.next()?;
+            let trace_id = fields.next()?;
+            let span_id = fields.next()?;
+            let flags = fields.next()?;
+
+            // ff is not a valid version, and only versions after 00 can have more fields
+            if !is_lower_hex(version, 2)
+                || version == "ff"
+                || (version == "00" && fields.next().is_some())
+            {
+                return None;
+            }
+            if !is_lower_hex(trace_id, 32) || trace_id.bytes().all(|b| b == b'0') {
+                return None;
+            }
+            if !is_lower_hex(span_id, 16) || span_id.bytes().all(|b| b == b'0') {
+                return None;
+            }
+            if !is_lower_hex(flags, 2) {
+                return None;
+            }
+
+            Some(TraceContext {
+                trace_id: trace_id.to_string(),
+                span_id: span_id.to_string(),
+                flags: u8::from_str_radix(flags, 16).ok()?,
+                state: tracestate
+                    .filter(|state| !state.is_empty() && state.len() <= MAX_TRACESTATE_LEN)
+                    .map(|state| state.to_string()),
+            })
+        }
+
+        // a new sampled trace, for a producer that is not already part of one
+        pub fn new_root() -> TraceContext {
+            TraceContext {
+                trace_id: random_hex(32),
+                span_id: random_hex(16),
+                flags: 1,
+                state: None,
+            }
+        }
+
+        // the same trace with a new span, for a span started under this one
+        pub fn child(&self) -> TraceContext {
+            TraceContext {
+                span_id: random_hex(16),
+                ..self.clone()
+            }
+        }
+
+        pub fn sampled(&self) -> bool {
+            self.flags & 1 == 1
+        }
+
+        // always written as version 00
+        pub fn traceparent(&self) -> String {
+            format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, self.flags)
+        }
+
+        pub fn from_attributes(attributes: &MmqpAttributes) -> Option<TraceContext> {
+            let traceparent = match attributes.get(TRACEPARENT_ATTRIBUTE) {
+                Some(MmqpAttributeValue::String(traceparent)) => traceparent,
+                _ => return None,
+            };
+            let tracestate = match attributes.get(TRACESTATE_ATTRIBUTE) {
+                Some(MmqpAttributeValue::String(tracestate)) => Some(tracestate.as_str()),
+                _ => None,
+            };
+            TraceContext::parse(traceparent, tracestate)
+        }
+
+        // the same context as an OpenTelemetry span context of a span in another process
+        pub fn span_context(&self) -> SpanContext {
+            SpanContext::new(
+                TraceId::from_hex(&self.trace_id).unwrap_or(TraceId::INVALID),
+                SpanId::from_hex(&self.span_id).unwrap_or(SpanId::INVALID),
+                TraceFlags::new(self.flags),
+                true,
+                self.state
+                    .as_deref()
+                    .and_then(|state| TraceState::from_str(state).ok())
+                    .unwrap_or_default(),
+            )
+        }
+
+        // the context of an OpenTelemetry span. None if it is invalid, eg there is no OpenTelemetry layer
+        // or the span is disabled
+        pub fn from_span_context(context: &SpanContext) -> Option<TraceContext> {
+            if !context.is_valid() {
+                return None;
+            }
+            let state = context.trace_state().header();
+            Some(TraceContext {
+                trace_id: context.trace_id().to_string(),
+                span_id: context.span_id().to_string(),
+                flags: context.trace_flags().to_u8(),
+                state: Some(state).filter(|state| !state.is_empty()),
+            })
+        }
+
+        // replaces any context already in the attributes
+        pub fn insert_into(&self, attributes: &mut MmqpAttributes) {
+            attributes.insert(
+                TRACEPARENT_ATTRIBUTE.to_string(),
+                MmqpAttributeValue::String(self.traceparent()),
+            );
+            match self.state.as_ref() {
+                Some(state) => attributes.insert(
+                    TRACESTATE_ATTRIBUTE.to_string(),
+                    MmqpAttributeValue::String(state.clone()),
+                ),
+                None => attributes.remove(TRACESTATE_ATTRIBUTE),
+            };
+        }
+    }
+
+    // keep the trace context attributes only when they are valid, so every stored context can be parsed.
+    // A message with a malformed context is still accepted, tracing never stops a message being delivered
+    pub fn sanitise_trace_attributes(mut attributes: MmqpAttributes) -> MmqpAttributes {
+        if !attributes.contains_key(TRACEPARENT_ATTRIBUTE)
+            && !attributes.contains_key(TRACESTATE_ATTRIBUTE)
+        {
+            return attributes;
+        }
+
+        match TraceContext::from_attributes(&attributes) {
+            Some(context) => context.insert_into(&mut attributes),
+            None => {
+                attributes.remove(TRACEPARENT_ATTRIBUTE);
+                attributes.remove(TRACESTATE_ATTRIBUTE);
+            }
+        }
+        attributes
+    }
+
+    // a span for sending a message. The message joins the trace it already carries, or a new one, and
+    // gets this span as its parent so consumers can link back to it. With an OpenTelemetry layer the ids
+    // are the ones of the exported span, otherwise they are generated here
+    pub fn producer_span(message: &mut MmqpMessage) -> tracing::Span {
+        let parent = TraceContext::from_attributes(&message.attributes);
+        let span = tracing::info_span!(
+            "mmqp.send",
+            queue = %message.target_queue,
+            trace_id = tracing::field::Empty,
+            span_id = tracing::field::Empty,
+            parent_span_id = parent.as_ref().map(|parent| parent.span_id.clone()),
+        );
+        if let Some(parent) = parent.as_ref() {
+            // fails without an OpenTelemetry layer, there is nothing to parent then
+            let _ = span.set_parent(Context::new().with_remote_span_context(parent.span_context()));
+        }
+
+        let context = match TraceContext::from_span_context(span.context().span().span_context()) {
+            Some(context) => context,
+            None => match parent.as_ref() {
+                Some(parent) => parent.child(),
+                None => TraceContext::new_root(),
+            },
+        };
+        context.insert_into(&mut message.attributes);
+        span.record("trace_id", context.trace_id.as_str());
+        span.record("span_id", context.span_id.as_str());
+        span
+    }
+
+    // a span for handling a polled message: a child of the span that sent it, in the producer's trace, and
+    // linked to it for backends that follow links between traces. None if the message was sent without a
+    // trace context
+    pub fn consumer_span(message: &MmqpNormalisedMessage) -> Option<tracing::Span> {
+        let producer = TraceContext::from_attributes(&message.attributes)?;
+        let span = tracing::info_span!(
+            "mmqp.receive",
+            message_id = %message.message_id_string(),
+            trace_id = %producer.trace_id,
+            span_id = tracing::field::Empty,
+            link_span_id = %producer.span_id,
+        );
+        let _ = span.set_parent(Context::new().with_remote_span_context(producer.span_context()));
+        span.add_link(producer.span_context());
+
+        let span_id = match TraceContext::from_span_context(span.context().span().span_context()) {
+            Some(context) => context.span_id,
+            None => producer.child().span_id,
+        };
+        span.record("span_id", span_id.as_str());
+        Some(span)
+    }
+}
@@ -1,17 +1,55 @@
-use lib::{MmqpMessage, MmqpSerialisable, SerialisationStrategy};
+use lib::browse::browse::{deserialise_browse_page, BrowsePosition};
+use lib::exchange::exchange::ExchangeType;
+use lib::export::export::{deserialise_export_response, ExportFormat};
+use lib::queue::queue::{MessageSelection, SeekTarget};
+use lib::response::response::deserialise_messages;
+use lib::tcp_parse::tcp_parse::{serialise_browse, serialise_poll, serialise_seek};
+use lib::trace::trace::{consumer_span, producer_span, TraceContext};
+use lib::{
+    MmqpAdminCommand, MmqpAttributeValue, MmqpMessage, MmqpSerialisable, SerialisationStrategy,
+};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
 use std::env;
 use std::io::{Read, Write};
 use std::net::TcpStream;
+use std::str::FromStr;
 use std::time::SystemTime;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
 
 fn main() {
     //parse args: look or --host, --username and --password flags
     let args: Vec<String> = env::args().collect();
 
-    dbg!(args.clone());
+    init_logging();
+    tracing::debug!(args = ?redact_args(&args), "mack");
     //if no args, print help
     if args.len() == 1 {
-        println!("Usage: mack [--host <host>] [--username <username>] [--password <password>]");
+        println!("Usage: mack config [--host <host>] [--port <port>] [--username <username>] [--password <password>]");
+        println!("       mack send <queue> <message>");
+        println!("       mack send <queue> --file <path>");
+        println!("       mack send <queue> <message> --priority <0-255>");
+        println!("       mack send <queue> <message> --trace|--traceparent <traceparent>");
+        println!("       mack poll <queue> [--count <n>]");
+        println!("       mack purge <queue>");
+        println!("       mack publish <topic> <message> [--attr <key>=<value>]...");
+        println!("       mack topic create|delete <topic>");
+        println!("       mack subscribe|unsubscribe <topic> <queue>");
+        println!("       mack subscribe <topic> <queue> --filter <expression>");
+        println!("       mack route <exchange> <routing key> <message> [--attr <key>=<value>]...");
+        println!("       mack exchange create <exchange> direct|topic");
+        println!("       mack exchange delete <exchange>");
+        println!("       mack bind|unbind <exchange> <queue> <pattern>");
+        println!("       mack group delete <queue> <group>");
+        println!("       mack seek <queue> <group> --offset <offset>|--time <ms since epoch>");
+        println!("       mack browse <queue> [--count <n>] [--from <section>:<position>]");
+        println!("       mack move|copy <source> <destination> [--filter <expression>|--ids <id>,<id>...] [--preserve]");
+        println!("       mack reload");
+        println!("       mack export <queue> <file> [--format jsonl|native]");
+        println!("       mack import <file> [--queue <queue>] [--format jsonl|native]");
         return;
     }
 
@@ -26,6 +64,16 @@ fn main() {
             host = &args[index + 1];
         }
 
+        //if --port flag is present, set port to the next argument
+        let mut port = "8787";
+        if args.contains(&String::from("--port")) {
+            let index = args
+                .iter()
+                .position(|x| x == &String::from("--port"))
+                .unwrap();
+            port = &args[index + 1];
+        }
+
         //if --username flag is present, set username to the next argument
         let mut username = "root";
         if args.contains(&String::from("--username")) {
@@ -46,16 +94,20 @@ fn main() {
             password = &args[index + 1];
         }
 
-        dbg!(host);
-        dbg!(username);
-        dbg!(password);
+        tracing::debug!(
+            host,
+            port,
+            username,
+            password = "<redacted>",
+            "writing config"
+        );
 
         let filepath = "./mack.toml";
 
         let mut file = std::fs::File::create(filepath).unwrap();
         let config = format!(
-            "host = \"{}\"\nusername = \"{}\"\npassword = \"{}\"",
-            host, username, password
+            "host = \"{}\"\nport = {}\nusername = \"{}\"\npassword = \"{}\"",
+            host, port, username, password
         );
         file.write_all(config.as_bytes()).unwrap();
         println!("Config file written to {}", filepath);
@@ -71,58 +123,434 @@ fn main() {
         let message = &args[3];
 
         let filepath = "./mack.toml";
-        let filedata = std::fs::read_to_string(filepath);
-        let mut host = "".to_string();
-        let mut username = "".to_string();
-        let mut password = "".to_string();
-
-        dbg!(message.clone());
-        dbg!(queue.clone());
-
-        match filedata {
-            Ok(config) => {
-                for line in config.lines() {
-                    if line.starts_with("host") {
-                        host = line.split("=").collect::<Vec<&str>>()[1].trim().to_string();
-                    }
-                    if line.starts_with("username") {
-                        username = line.split("=").collect::<Vec<&str>>()[1].trim().to_string();
-                    }
-                    if line.starts_with("password") {
-                        password = line.split("=").collect::<Vec<&str>>()[1].trim().to_string();
-                    }
-                }
-
-                //remove the learind and trailing " from the host, username and password
-                let host = host.trim_matches('"').to_string();
-                let username = username.trim_matches('"').to_string();
-                let password = password.trim_matches('"').to_string();
 
-                // dbg!(host.clone());
-                // dbg!(username.clone());
-                // dbg!(password.clone());
+        tracing::debug!(%queue, body = %message, "sending");
 
+        match read_config(filepath) {
+            Some((_host, username, password)) => {
                 let mut message_to_send: MmqpMessage = MmqpMessage::new();
 
-                message_to_send.message = message.to_string();
+                //--file sends the contents of a file as a binary body instead of the message argument
+                if args.contains(&String::from("--file")) {
+                    let index = args
+                        .iter()
+                        .position(|x| x == &String::from("--file"))
+                        .unwrap();
+                    let bytes = std::fs::read(&args[index + 1]);
+                    match bytes {
+                        Ok(bytes) => message_to_send.set_binary(bytes),
+                        Err(_) => {
+                            println!("Could not read file {}", args[index + 1]);
+                            return;
+                        }
+                    }
+                } else {
+                    message_to_send.set_text(message);
+                }
+
+                //--priority only changes the order on priority queues
+                if let Some(index) = args.iter().position(|x| x == "--priority") {
+                    match args.get(index + 1).map(|p| p.parse::<u8>()) {
+                        Some(Ok(priority)) => message_to_send.priority = priority,
+                        _ => {
+                            println!("--priority must be a number from 0 to 255");
+                            return;
+                        }
+                    }
+                }
                 message_to_send.target_queue = queue.to_string();
                 message_to_send.version_major = 0;
                 message_to_send.version_minor = 1;
                 message_to_send.username = username.to_string();
                 message_to_send.password = password.to_string();
+
+                //--traceparent sends the message as part of an existing trace, --trace starts a new one
+                if let Some(index) = args.iter().position(|x| x == "--traceparent") {
+                    match args
+                        .get(index + 1)
+                        .and_then(|tp| TraceContext::parse(tp, None))
+                    {
+                        Some(context) => context.insert_into(&mut message_to_send.attributes),
+                        None => {
+                            println!("--traceparent must be a W3C traceparent");
+                            return;
+                        }
+                    }
+                }
+                let traced = args.iter().any(|x| x == "--trace" || x == "--traceparent");
+                let span = if traced {
+                    producer_span(&mut message_to_send)
+                } else {
+                    tracing::Span::none()
+                };
+                let _entered = span.enter();
                 let raw_message = message_to_send.serialise(SerialisationStrategy::Wire);
 
-                let _stream = TcpStream::connect("127.0.0.1:8787");
+                let response = send_request(&raw_message);
+                println!("{}", String::from_utf8_lossy(&response));
+                if let Some(context) = TraceContext::from_attributes(&message_to_send.attributes) {
+                    println!("traceparent {}", context.traceparent());
+                }
+            }
+            None => {
+                println!(
+                    "Error reading config file {}. Could not send message",
+                    filepath
+                );
+            }
+        }
+    }
+
+    if args[1] == "poll" {
+        let filepath = "./mack.toml";
+
+        let count = match args.iter().position(|x| x == "--count") {
+            Some(index) => match args.get(index + 1).map(|count| count.parse::<u8>()) {
+                Some(Ok(count)) => count,
+                _ => {
+                    println!("--count must be a number from 0 to 255");
+                    return;
+                }
+            },
+            None => 1,
+        };
+
+        match read_config(filepath) {
+            Some((_host, username, password)) => {
+                let raw = serialise_poll(&username, &password, &args[2], count);
+                let response = send_request(&raw);
+                let messages = match deserialise_messages(&response) {
+                    Some(messages) => messages,
+                    None => {
+                        println!("{}", String::from_utf8_lossy(&response));
+                        return;
+                    }
+                };
+
+                //a message sent with a trace context is handled in a span linked to the one that sent it
+                for message in messages.iter() {
+                    let span = consumer_span(message).unwrap_or_else(tracing::Span::none);
+                    let _entered = span.enter();
+                    tracing::debug!(message_id = %message.message_id_string(), "received");
+                    println!(
+                        "{} {}",
+                        message.message_id_string(),
+                        String::from_utf8_lossy(&message.message)
+                    );
+                    if let Some(context) = TraceContext::from_attributes(&message.attributes) {
+                        println!("traceparent {}", context.traceparent());
+                    }
+                }
+            }
+            None => {
+                println!("Error reading config file {}. Could not poll", filepath);
+            }
+        }
+    }
+
+    if args[1] == "purge" {
+        //next arg is the queue to purge
+        send_admin(MmqpAdminCommand::Purge(args[2].to_string()));
+    }
+
+    if args[1] == "topic" {
+        let topic = args[3].to_string();
+        match args[2].as_str() {
+            "create" => send_admin(MmqpAdminCommand::CreateTopic(topic)),
+            "delete" => send_admin(MmqpAdminCommand::DeleteTopic(topic)),
+            other => println!("Unknown topic command {}", other),
+        }
+    }
+
+    if args[1] == "subscribe" || args[1] == "unsubscribe" {
+        let topic = args[2].to_string();
+        let queue = args[3].to_string();
+        if args[1] == "subscribe" {
+            //--filter only delivers messages whose attributes or group match the expression
+            let filter = args
+                .iter()
+                .position(|x| x == "--filter")
+                .and_then(|index| args.get(index + 1))
+                .cloned();
+            send_admin(MmqpAdminCommand::Subscribe(topic, queue, filter));
+        } else {
+            send_admin(MmqpAdminCommand::Unsubscribe(topic, queue));
+        }
+    }
+
+    if args[1] == "exchange" {
+        let exchange = args[3].to_string();
+        match args[2].as_str() {
+            "create" => match ExchangeType::from_name(&args[4]) {
+                Some(exchange_type) => {
+                    send_admin(MmqpAdminCommand::CreateExchange(exchange, exchange_type))
+                }
+                None => println!("Exchange type must be direct or topic"),
+            },
+            "delete" => send_admin(MmqpAdminCommand::DeleteExchange(exchange)),
+            other => println!("Unknown exchange command {}", other),
+        }
+    }
+
+    if args[1] == "bind" || args[1] == "unbind" {
+        let exchange = args[2].to_string();
+        let queue = args[3].to_string();
+        let pattern = args[4].to_string();
+        if args[1] == "bind" {
+            send_admin(MmqpAdminCommand::Bind(exchange, queue, pattern));
+        } else {
+            send_admin(MmqpAdminCommand::Unbind(exchange, queue, pattern));
+        }
+    }
+
+    if args[1] == "reload" {
+        send_admin(MmqpAdminCommand::Reload);
+    }
+
+    if args[1] == "group" {
+        let queue = args[3].to_string();
+        match args[2].as_str() {
+            "delete" => send_admin(MmqpAdminCommand::DeleteConsumerGroup(
+                queue,
+                args[4].to_string(),
+            )),
+            other => println!("Unknown group command {}", other),
+        }
+    }
+
+    if args[1] == "seek" {
+        let filepath = "./mack.toml";
 
-                let mut stream = _stream.unwrap();
+        let value = |flag: &str| {
+            args.iter()
+                .position(|x| x == flag)
+                .and_then(|index| args.get(index + 1))
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+        let target = match (value("--offset"), value("--time")) {
+            (Some(offset), None) => SeekTarget::Offset(offset),
+            (None, Some(time)) => SeekTarget::Time(time as u128),
+            _ => {
+                println!("seek needs either --offset <offset> or --time <ms since epoch>");
+                return;
+            }
+        };
 
-                stream.write_all(&raw_message).unwrap();
+        match read_config(filepath) {
+            Some((_host, username, password)) => {
+                let raw = serialise_seek(&username, &password, &args[2], &args[3], target);
+                let response = send_request(&raw);
+                println!("{}", String::from_utf8_lossy(&response));
+            }
+            None => {
+                println!("Error reading config file {}. Could not seek", filepath);
+            }
+        }
+    }
 
-                let res = &mut String::new();
+    if args[1] == "browse" {
+        let filepath = "./mack.toml";
 
-                stream.read_to_string(res).unwrap();
+        let flag = |flag: &str| {
+            args.iter()
+                .position(|x| x == flag)
+                .and_then(|index| args.get(index + 1))
+        };
+        let count = flag("--count").and_then(|count| count.parse::<u8>().ok());
+        //--from continues from the position printed after the previous page
+        let from = match flag("--from").map(|from| from.split_once(':')) {
+            Some(Some((section, position))) => match (section.parse(), position.parse()) {
+                (Ok(section), Ok(position)) => BrowsePosition { section, position },
+                _ => {
+                    println!("--from must be <section>:<position>");
+                    return;
+                }
+            },
+            Some(None) => {
+                println!("--from must be <section>:<position>");
+                return;
             }
-            Err(_) => {
+            None => BrowsePosition::default(),
+        };
+
+        match read_config(filepath) {
+            Some((_host, username, password)) => {
+                let raw =
+                    serialise_browse(&username, &password, &args[2], from, count.unwrap_or(20));
+                let response = send_request(&raw);
+                if !response.starts_with(b"MMQP|0.1|R|BROWSE|") {
+                    println!("{}", String::from_utf8_lossy(&response));
+                    return;
+                }
+
+                let page = deserialise_browse_page(&response);
+                for entry in page.entries.iter() {
+                    println!(
+                        "{} {:?} group={} priority={} receive_count={} age={}ms available_in={}ms size={}",
+                        entry.message_id,
+                        entry.state,
+                        entry.group,
+                        entry.priority,
+                        entry.receive_count,
+                        entry.age,
+                        entry.available_in,
+                        entry.size
+                    );
+                }
+                match page.next {
+                    Some(next) => println!("next page: --from {}:{}", next.section, next.position),
+                    None => println!("end of queue"),
+                }
+            }
+            None => {
+                println!("Error reading config file {}. Could not browse", filepath);
+            }
+        }
+    }
+
+    if args[1] == "move" || args[1] == "copy" {
+        let source = args[2].to_string();
+        let destination = args[3].to_string();
+
+        let flag = |flag: &str| {
+            args.iter()
+                .position(|x| x == flag)
+                .and_then(|index| args.get(index + 1))
+                .cloned()
+        };
+        //every waiting message unless --filter or --ids picks some of them
+        let selection = match (flag("--filter"), flag("--ids")) {
+            (Some(filter), None) => MessageSelection::Filter(filter),
+            (None, Some(ids)) => {
+                MessageSelection::Ids(ids.split(',').map(|id| id.to_string()).collect())
+            }
+            (None, None) => MessageSelection::All,
+            _ => {
+                println!("Use either --filter or --ids");
+                return;
+            }
+        };
+        //--preserve keeps message ids and timestamps
+        let keep_ids = args.contains(&String::from("--preserve"));
+
+        if args[1] == "move" {
+            send_admin(MmqpAdminCommand::Move(
+                source,
+                destination,
+                selection,
+                keep_ids,
+            ));
+        } else {
+            send_admin(MmqpAdminCommand::Copy(
+                source,
+                destination,
+                selection,
+                keep_ids,
+            ));
+        }
+    }
+
+    if args[1] == "export" || args[1] == "import" {
+        let filepath = "./mack.toml";
+
+        let flag = |flag: &str| {
+            args.iter()
+                .position(|x| x == flag)
+                .and_then(|index| args.get(index + 1))
+                .cloned()
+        };
+        //json lines unless --format native
+        let format = match flag("--format") {
+            Some(name) => match ExportFormat::from_name(&name) {
+                Some(format) => format,
+                None => {
+                    println!("--format must be jsonl or native");
+                    return;
+                }
+            },
+            None => ExportFormat::JsonLines,
+        };
+
+        if args[1] == "import" {
+            let data = match std::fs::read(&args[2]) {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("Could not read {}: {}", args[2], e);
+                    return;
+                }
+            };
+            //--queue imports into another queue than the one that was exported
+            send_admin(MmqpAdminCommand::Import(
+                flag("--queue").unwrap_or_default(),
+                format,
+                data,
+            ));
+            return;
+        }
+
+        match read_config(filepath) {
+            Some((_host, username, password)) => {
+                let command = MmqpAdminCommand::Export(args[2].to_string(), format);
+                let response = send_request(&command.serialise(&username, &password));
+                match deserialise_export_response(&response) {
+                    Some(contents) => match std::fs::write(&args[3], contents) {
+                        Ok(_) => println!("Exported {} to {}", args[2], args[3]),
+                        Err(e) => println!("Could not write {}: {}", args[3], e),
+                    },
+                    None => println!("{}", String::from_utf8_lossy(&response)),
+                }
+            }
+            None => {
+                println!("Error reading config file {}. Could not export", filepath);
+            }
+        }
+    }
+
+    if args[1] == "publish" || args[1] == "route" {
+        let filepath = "./mack.toml";
+
+        // route has the routing key before the message
+        let (text, routing_key) = if args[1] == "route" {
+            (&args[4], Some(&args[3]))
+        } else {
+            (&args[3], None)
+        };
+
+        match read_config(filepath) {
+            Some((_host, username, password)) => {
+                let mut message = MmqpMessage::new();
+                message.target_queue = args[2].to_string();
+                message.set_text(text);
+
+                //--attr adds a string attribute, topic filters can match on it
+                for (index, arg) in args.iter().enumerate() {
+                    if arg != "--attr" {
+                        continue;
+                    }
+                    match args.get(index + 1).and_then(|attr| attr.split_once('=')) {
+                        Some((key, value)) => {
+                            message.attributes.insert(
+                                key.to_string(),
+                                MmqpAttributeValue::String(value.to_string()),
+                            );
+                        }
+                        None => {
+                            println!("--attr must be followed by <key>=<value>");
+                            return;
+                        }
+                    }
+                }
+                message.username = username;
+                message.password = password;
+
+                let raw_message = match routing_key {
+                    Some(routing_key) => message.serialise_route(routing_key),
+                    None => message.serialise_publish(),
+                };
+                let response = send_request(&raw_message);
+                println!("{}", String::from_utf8_lossy(&response));
+            }
+            None => {
                 println!(
                     "Error reading config file {}. Could not send message",
                     filepath
@@ -131,3 +559,121 @@ fn main() {
         }
     }
 }
+
+// send an admin command with the credentials from ./mack.toml and print the response
+fn send_admin(command: MmqpAdminCommand) {
+    let filepath = "./mack.toml";
+
+    match read_config(filepath) {
+        Some((_host, username, password)) => {
+            let response = send_request(&command.serialise(&username, &password));
+            println!("{}", String::from_utf8_lossy(&response));
+        }
+        None => {
+            println!(
+                "Error reading config file {}. Could not send {}",
+                filepath,
+                command.name()
+            );
+        }
+    }
+}
+
+// host, username, password from the file written by `mack config`
+// MACK_LOG=debug|trace shows what is sent and received on stderr, only warnings are shown otherwise
+// spans also go to an OpenTelemetry tracer so send and poll spans carry the trace ids of the messages
+fn init_logging() {
+    let level = env::var("MACK_LOG")
+        .ok()
+        .and_then(|level| tracing::Level::from_str(&level).ok())
+        .unwrap_or(tracing::Level::WARN);
+    let tracer = SdkTracerProvider::builder().build().tracer("mack");
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(LevelFilter::from_level(level)),
+        )
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .init();
+}
+
+// the arguments with the value of --password left out
+fn redact_args(args: &[String]) -> Vec<String> {
+    let mut redacted = args.to_vec();
+    for index in 1..redacted.len() {
+        if args[index - 1] == "--password" {
+            redacted[index] = "<redacted>".to_string();
+        }
+    }
+    redacted
+}
+
+fn read_config(filepath: &str) -> Option<(String, String, String)> {
+    let config = std::fs::read_to_string(filepath).ok()?;
+
+    let mut host = "".to_string();
+    let mut username = "".to_string();
+    let mut password = "".to_string();
+
+    for line in config.lines() {
+        if line.starts_with("host") {
+            host = line.split("=").collect::<Vec<&str>>()[1].trim().to_string();
+        }
+        if line.starts_with("username") {
+            username = line.split("=").collect::<Vec<&str>>()[1].trim().to_string();
+        }
+        if line.starts_with("password") {
+            password = line.split("=").collect::<Vec<&str>>()[1].trim().to_string();
+        }
+    }
+
+    //remove the learind and trailing " from the host, username and password
+    let host = host.trim_matches('"').to_string();
+    let username = username.trim_matches('"').to_string();
+    let password = password.trim_matches('"').to_string();
+
+    Some((host, username, password))
+}
+
+// host:port of the server, from MACK_SERVER or else the host and port written by `mack config`
+fn server_address() -> String {
+    if let Ok(address) = env::var("MACK_SERVER") {
+        return address;
+    }
+
+    let config = std::fs::read_to_string("./mack.toml").unwrap_or_default();
+    let setting = |name: &str| {
+        config
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().trim_matches('"').to_string())
+    };
+    format!(
+        "{}:{}",
+        setting("host").unwrap_or_else(|| "localhost".to_string()),
+        setting("port").unwrap_or_else(|| "8787".to_string())
+    )
+}
+
+// send a single request and return everything the server responds with
+fn send_request(raw: &[u8]) -> Vec<u8> {
+    let address = server_address();
+    tracing::debug!(%address, "connecting");
+    let mut stream = match TcpStream::connect(&address) {
+        Ok(stream) => stream,
+        Err(e) => {
+            println!("Could not connect to {}: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+
+    stream.write_all(raw).unwrap();
+
+    let mut res = Vec::new();
+
+    stream.read_to_end(&mut res).unwrap();
+
+    res
+}
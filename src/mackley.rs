@@ -1,229 +1,1496 @@
 pub mod application {
-    use lib::queue::queue::{PendingMode, QueueConfiguration};
-    use lib::tcp_parse::tcp_parse::MmqpTcpFormat;
-    use lib::{MmqpSerialisable, Queue, SerialisationStrategy};
+    use crate::config::server_config::{AutoCreate, ServerConfig};
+    use lib::blob_store::blob_store::BlobStore;
+    use lib::browse::browse::{serialise_browse_page, BrowseState};
+    use lib::exchange::exchange::{
+        deserialise_exchanges, serialise_exchanges, ExchangeConfiguration, ExchangeMap,
+    };
+    use lib::export::export::{
+        deserialise_export, serialise_export, serialise_export_response, ExportFormat, QueueExport,
+    };
+    use lib::normalised_message::normalised_message::MmqpNormalisedMessage;
+    use lib::queue::queue::{
+        validate_configurations, ConfigError, MessageSelection, PendingMode, QueueConfiguration,
+        QueueType,
+    };
+    use lib::response::response::serialise_batch_results;
+    use lib::tcp_parse::tcp_parse::{
+        redact_request, request_username, MmqpTcpFormat, RequestScanner, ScanResult,
+    };
+    use lib::topic::topic::{deserialise_topics, serialise_topics, TopicConfiguration, TopicMap};
+    use lib::trace::trace::TraceContext;
+    use lib::{
+        MmqpAdminCommand, MmqpEntryResult, MmqpMessage, MmqpResponseCode, MmqpSerialisable, Queue,
+        SerialisationStrategy,
+    };
     use std::borrow::Borrow;
-    use std::collections::HashMap;
+    use std::cell::RefCell;
+    use std::collections::{HashMap, HashSet};
     use std::future::Future;
     use std::hash::Hash;
     use std::io::{Read, Write};
-    use std::net::{TcpListener, TcpStream};
-    use std::time::SystemTime;
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex, RwLock};
+    use std::thread;
+    use std::time::{Duration, Instant, SystemTime};
+
+    pub type QueueMap = HashMap<String, Mutex<Queue>>;
+
+    // how often the background sweeper looks for expired messages
+    const SWEEP_INTERVAL_MS: u64 = 1000;
+
+    // largest message body accepted by the server when no other limit is given. queues can set a lower one
+    pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 262144;
+
+    // largest request read from a client when no other limit is given, IMPORT data included
+    pub const DEFAULT_MAX_REQUEST_SIZE: usize = 64 * 1024 * 1024;
+
+    // a client that stops sending in the middle of a request is answered after this long. Requests are
+    // handled one at a time, so a client that never finishes would otherwise stall every other client
+    const REQUEST_READ_TIMEOUT_MS: u64 = 5000;
 
     pub struct Application {
         listener: TcpListener,
         port: u16,
-        queues: HashMap<String, Queue>,
+        // shared with the background sweeper
+        queues: Arc<RwLock<QueueMap>>,
+
+        topics: TopicMap,
+        exchanges: ExchangeMap,
 
         // optional page dir means there is no disk storage between session
         // messages are lost on crash or restart
         page_dir: Option<String>,
 
-        // optional auth file means there is no authentication required. Desirable for local
-        // undesirable for remote.
-        // if the auth file is missing, user:pass are ignored but must still be present for format reasons
-        // recommended to use 0:0
-        auth_file: Option<String>,
+        // message bodies larger than this are rejected while parsing, before they are copied
+        max_message_size: usize,
+
+        // requests larger than this are refused before the rest of them is read
+        max_request_size: usize,
+
+        // where offloaded bodies are kept, given to queues created after startup as well
+        blobs: Option<BlobStore>,
+
+        // the queues and their settings. read at startup, rewritten when an import creates a queue
+        queue_config: String,
+
+        // topics and their subscriptions, rewritten whenever an admin command changes them
+        topic_config: String,
+
+        // exchanges and their bindings, rewritten whenever an admin command changes them
+        exchange_config: String,
+
+        // unknown queues allowed by this are created when they are sent to or polled
+        auto_create: Option<AutoCreate>,
     }
 
     impl Application {
-        pub fn new(port: u16, page_dir: Option<String>, auth_file: Option<String>) -> Application {
+        /// Load the queues, topics and exchanges. Fails when the queue config file is invalid, the address
+        /// cannot be bound or an auth file is given
+        pub fn new(config: ServerConfig) -> Result<Application, String> {
+            let ServerConfig {
+                bind_address,
+                port,
+                page_dir,
+                auth_file,
+                queue_config,
+                topic_config,
+                exchange_config,
+                max_message_size,
+                max_request_size,
+                auto_create,
+                ..
+            } = config;
+
+            // user:pass in requests are never checked. Starting anyway would look like authentication is on
+            if let Some(auth_file) = auth_file {
+                return Err(format!(
+                    "auth_file {}: authentication is not supported yet, remove the setting to run without it",
+                    auth_file
+                ));
+            }
+
+            // the same goes for auto_create.users, but it only stops clients creating queues by mistake
+            if let Some(auto_create) = auto_create.as_ref().filter(|a| !a.users.is_empty()) {
+                tracing::warn!(
+                    users = ?auto_create.users,
+                    "auto_create.users is advisory: user names are not authenticated, any client can send one of them"
+                );
+            }
+
             //get the file with all the queue configs
-            let filepath = "./queue_config.json";
+            let filepath = queue_config.as_str();
 
             //read the file
             let file = std::fs::File::open(filepath);
-            let mut queues: HashMap<String, Queue> = HashMap::new();
+            let mut queues: QueueMap = HashMap::new();
             if file.is_ok() {
                 //read the file
                 let mut contents = String::new();
-                let r = file.unwrap().read_to_string(&mut contents);
-
-                if r.is_ok() {
-                    let config_maybe = Queue::deserialise_config_array(contents);
+                file.unwrap()
+                    .read_to_string(&mut contents)
+                    .map_err(|e| format!("Could not read {}: {}", filepath, e))?;
 
-                    if config_maybe.is_ok() {
-                        let config_array = config_maybe.unwrap().into_vec();
-                        dbg!(config_array.clone());
+                // starting without the queues of a file that has a mistake in it would lose their messages
+                let config_array = Queue::deserialise_config_array(contents)
+                    .map_err(|errors| Application::describe_config_errors(filepath, &errors))?
+                    .into_vec();
+                tracing::debug!(?config_array, "queue config");
 
-                        //create the queues
-                        for config in config_array.into_iter() {
-                            let queue = Queue::new(config);
-                            queues.insert(queue.name().clone(), queue);
-                        }
-                    }
+                //create the queues
+                for config in config_array.into_iter() {
+                    let queue = Queue::new(config);
+                    queues.insert(queue.name().clone(), Mutex::new(queue));
                 }
             } else {
-                println!("Could not open queue config file");
+                tracing::warn!(
+                    file = filepath,
+                    "could not open the queue config file, creating it"
+                );
                 std::fs::File::create(filepath).unwrap();
             }
 
-            Application {
-                listener: TcpListener::bind(format!("127.0.0.1:{}", port)).unwrap(),
+            if let Some(Some(dlq)) = auto_create
+                .as_ref()
+                .map(|auto_create| auto_create.configuration("").dead_letter_queue)
+            {
+                if !queues.contains_key(&dlq) {
+                    return Err(format!(
+                        "auto_create.template.dead_letter_queue: there is no queue named {}",
+                        dlq
+                    ));
+                }
+            }
+
+            // large bodies are offloaded next to the pages, so without a page dir they stay in memory
+            let blobs = page_dir.as_ref().map(|page_dir| {
+                BlobStore::new(format!("{}/blobs", page_dir))
+                    .expect("could not create the blob directory")
+            });
+            if let Some(blobs) = blobs.as_ref() {
+                for queue in queues.values() {
+                    queue.lock().unwrap().set_blob_store(blobs.clone());
+                }
+            }
+
+            Ok(Application {
+                listener: TcpListener::bind((bind_address.as_str(), port))
+                    .map_err(|e| format!("Could not bind {}:{}: {}", bind_address, port, e))?,
                 port,
-                queues,
+                queues: Arc::new(RwLock::new(queues)),
+                topics: Application::load_topics(&topic_config),
+                exchanges: Application::load_exchanges(&exchange_config),
                 page_dir,
-                auth_file,
-            }
+                max_message_size,
+                max_request_size,
+                blobs,
+                queue_config,
+                topic_config,
+                exchange_config,
+                auto_create,
+            })
+        }
+
+        /// One line per problem, for the log and for RELOAD responses
+        fn describe_config_errors(filepath: &str, errors: &[ConfigError]) -> String {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            format!("{} is not valid: {}", filepath, errors.join("; "))
         }
 
         pub fn listen(&mut self) -> () {
-            println!("Listening on port {}", self.listener.local_addr().unwrap());
-            for stream in self.listener.incoming() {
-                //start a timer for the request
-                let start = SystemTime::now();
+            tracing::info!(address = %self.listener.local_addr().unwrap(), "listening");
+            self.start_sweeper();
+            #[cfg(unix)]
+            self.start_reload_on_hangup();
 
-                let mut s = stream.unwrap();
-                // println!("New connection: {}", s.peer_addr().unwrap());
-                let data = Application::read_stream(&mut s);
+            loop {
+                let (mut s, peer) = match self.listener.accept() {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "could not accept a connection");
+                        continue;
+                    }
+                };
 
-                let (bytes, size) = data;
+                // the other fields are filled in once the request is parsed
+                let span = tracing::info_span!(
+                    "request",
+                    %peer,
+                    user = tracing::field::Empty,
+                    command = tracing::field::Empty,
+                    queue = tracing::field::Empty,
+                    trace_id = tracing::field::Empty,
+                );
+                let _entered = span.enter();
 
-                if size == 0 {
-                    continue;
+                let start = Instant::now();
+                if self.handle_request(&mut s, &span) {
+                    tracing::debug!(latency_us = start.elapsed().as_micros() as u64, "handled");
+                    self.close(&mut s);
                 }
+            }
+        }
 
-                dbg!(bytes.clone());
-                let req = lib::tcp_parse::tcp_parse::parse_tcp_request(bytes);
+        /// Read, parse and answer a single request. False if the connection closed without sending one
+        fn handle_request(&mut self, s: &mut TcpStream, span: &tracing::Span) -> bool {
+            let (bytes, req) = match self.read_request(s) {
+                Some(request) => request,
+                None => return false,
+            };
 
-                match req {
-                    MmqpTcpFormat::Message(message) => {
-                        if self.queues.contains_key(&*message.target_queue) {
-                            let queue = self.queues.get_mut(message.target_queue.as_str()).unwrap();
-                            queue.receive_message(message);
-                        } else {
-                            println!("Queue not found: {}", message.target_queue);
-
-                            // let mut _message:Vec<u8> = (b"MMQP|0.1|R|QUEUE_NOT_FOUND|").to_vec();
-                            //
-                            // _message.extend( message.target_queue.to_string().into_bytes());
-                            // _message.push( 0x00);
-
-                            s.write_all(
-                                format!(
-                                    "MMQP|0.1|R|QUEUE_NOT_FOUND|{}|{}",
-                                    message.target_queue, 0x00
-                                )
-                                .as_bytes(),
+            tracing::trace!(request = ?String::from_utf8_lossy(&redact_request(&bytes)), "read");
+            let username = request_username(&bytes).unwrap_or_default();
+
+            span.record("user", username.as_str());
+            match &req {
+                MmqpTcpFormat::Admin(command) => span.record("command", command.name().as_str()),
+                req => span.record("command", req.command()),
+            };
+            if let Some(target) = req.target() {
+                span.record("queue", target);
+            }
+            // ties the server's logs to the trace of the producer
+            if let MmqpTcpFormat::Message(message)
+            | MmqpTcpFormat::Publish(message)
+            | MmqpTcpFormat::Route(_, message) = &req
+            {
+                if let Some(context) = TraceContext::from_attributes(&message.attributes) {
+                    span.record("trace_id", context.trace_id.as_str());
+                }
+            }
+
+            // queues are auto created before the read lock is taken, creating one needs the write lock
+            match &req {
+                MmqpTcpFormat::Message(message) => {
+                    self.auto_create_queue(&message.target_queue, &username)
+                }
+                MmqpTcpFormat::Poll(queue, _) => self.auto_create_queue(queue, &username),
+                _ => {}
+            }
+
+            // imports and reloads can create queues, so they need the queues for writing
+            let req = match req {
+                MmqpTcpFormat::Admin(MmqpAdminCommand::Import(queue, format, data)) => {
+                    tracing::info!("admin command");
+                    self.import_queue(queue, format, &data, s);
+                    return true;
+                }
+                MmqpTcpFormat::Admin(MmqpAdminCommand::Reload) => {
+                    tracing::info!("admin command");
+                    match Application::reload(&self.queues, &self.queue_config, self.blobs.as_ref())
+                    {
+                        Ok(summary) => {
+                            tracing::info!(%summary, "reloaded {}", self.queue_config);
+                            Application::write_response(s, MmqpResponseCode::Ok, &summary)
+                        }
+                        Err((code, e)) => {
+                            tracing::error!(error = %e, "could not reload");
+                            Application::write_response(s, code, &e);
+                        }
+                    }
+                    return true;
+                }
+                req => req,
+            };
+            let queues = self.queues.read().unwrap();
+
+            match req {
+                MmqpTcpFormat::Message(message) => {
+                    if queues.contains_key(&*message.target_queue) {
+                        let mut queue = queues[message.target_queue.as_str()].lock().unwrap();
+                        match queue.receive_message(message) {
+                            Ok(message_id) => {
+                                Application::write_response(s, MmqpResponseCode::Ok, &message_id)
+                            }
+                            Err(code) => Application::write_response(s, code, &queue.name()),
+                        }
+                    } else {
+                        tracing::warn!("queue not found");
+
+                        // let mut _message:Vec<u8> = (b"MMQP|0.1|R|QUEUE_NOT_FOUND|").to_vec();
+                        //
+                        // _message.extend( message.target_queue.to_string().into_bytes());
+                        // _message.push( 0x00);
+
+                        s.write_all(
+                            format!(
+                                "MMQP|0.1|R|QUEUE_NOT_FOUND|{}|{}",
+                                message.target_queue, 0x00
                             )
-                            .unwrap();
+                            .as_bytes(),
+                        )
+                        .unwrap();
+                        s.flush().unwrap();
+                    }
+                }
+                MmqpTcpFormat::BatchMessage(messages) => {
+                    let results = Application::receive_batch(&queues, messages);
+
+                    s.write_all(&serialise_batch_results(&results)).unwrap();
+                    s.flush().unwrap();
+                }
+                MmqpTcpFormat::Publish(message) => match self.topics.get(&message.target_queue) {
+                    Some(topic) => {
+                        // one copy per subscriber whose filter matches, placed like the entries of a batch
+                        let copies = topic
+                            .subscribers(&message.message_group, &message.attributes)
+                            .iter()
+                            .map(|queue| {
+                                let mut copy = message.clone();
+                                copy.target_queue = queue.clone();
+                                copy
+                            })
+                            .collect();
+                        let results = Application::receive_batch(&queues, copies);
+
+                        s.write_all(&serialise_batch_results(&results)).unwrap();
+                        s.flush().unwrap();
+                    }
+                    None => {
+                        tracing::warn!("topic not found");
+                        Application::write_response(
+                            s,
+                            MmqpResponseCode::TopicNotFound,
+                            &message.target_queue,
+                        );
+                    }
+                },
+                MmqpTcpFormat::Route(routing_key, message) => {
+                    match self.exchanges.get(&message.target_queue) {
+                        Some(exchange) => {
+                            // one copy per matching queue. nothing matching is not an error, the
+                            // response is an empty batch
+                            let copies = exchange
+                                .route(&routing_key)
+                                .into_iter()
+                                .map(|queue| {
+                                    let mut copy = message.clone();
+                                    copy.target_queue = queue;
+                                    copy
+                                })
+                                .collect();
+                            let results = Application::receive_batch(&queues, copies);
+
+                            s.write_all(&serialise_batch_results(&results)).unwrap();
                             s.flush().unwrap();
                         }
+                        None => {
+                            tracing::warn!("exchange not found");
+                            Application::write_response(
+                                s,
+                                MmqpResponseCode::ExchangeNotFound,
+                                &message.target_queue,
+                            );
+                        }
                     }
-                    MmqpTcpFormat::Admin => {
-                        println!("Admin");
+                }
+                MmqpTcpFormat::Admin(command) => {
+                    tracing::info!("admin command");
+                    Application::handle_admin(
+                        &queues,
+                        &mut self.topics,
+                        &self.topic_config,
+                        &mut self.exchanges,
+                        &self.exchange_config,
+                        command,
+                        s,
+                    );
+                }
+                MmqpTcpFormat::LongPoll(_, _, _) => {
+                    tracing::warn!("long polling is not supported");
+                }
+                MmqpTcpFormat::Del(queue, message_id) => match queues.get(&queue) {
+                    Some(q) => {
+                        let deleted = q.lock().unwrap().delete_message(&message_id);
+                        let code = if deleted {
+                            MmqpResponseCode::Ok
+                        } else {
+                            MmqpResponseCode::MessageNotFound
+                        };
+                        Application::write_response(s, code, &message_id);
                     }
-                    MmqpTcpFormat::LongPoll(_, _, _) => {
-                        println!("LongPoll");
+                    None => {
+                        tracing::warn!("queue not found");
+                        Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue);
                     }
-                    MmqpTcpFormat::Del(_, _) => {
-                        println!("Del");
+                },
+                MmqpTcpFormat::ChangeVisibility(queue, message_id, timeout) => {
+                    match queues.get(&queue) {
+                        Some(q) => {
+                            let changed = q.lock().unwrap().change_visibility(&message_id, timeout);
+                            let code = if changed {
+                                MmqpResponseCode::Ok
+                            } else {
+                                MmqpResponseCode::MessageNotFound
+                            };
+                            Application::write_response(s, code, &message_id);
+                        }
+                        None => {
+                            tracing::warn!("queue not found");
+                            Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue);
+                        }
                     }
-                    MmqpTcpFormat::Ping => {
-                        println!("Ping");
+                }
+                MmqpTcpFormat::BatchDel(queue, message_ids) => match queues.get(&queue) {
+                    Some(q) => {
+                        let mut q = q.lock().unwrap();
+                        let results: Vec<MmqpEntryResult> = message_ids
+                            .into_iter()
+                            .map(|id| {
+                                if q.delete_message(&id) {
+                                    MmqpEntryResult::ok(id)
+                                } else {
+                                    MmqpEntryResult::error(MmqpResponseCode::MessageNotFound, id)
+                                }
+                            })
+                            .collect();
+                        drop(q);
 
-                        let success =s.write(b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=UTF-8\r\n\r\npong");
-                        match success {
-                            Ok(_) => {
-                                let r = s.flush();
-                                if r.is_err() {
-                                    println!("Error flushing");
+                        s.write_all(&serialise_batch_results(&results)).unwrap();
+                        s.flush().unwrap();
+                    }
+                    None => {
+                        tracing::warn!("queue not found");
+                        Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue);
+                    }
+                },
+                MmqpTcpFormat::BatchVisibility(queue, entries) => match queues.get(&queue) {
+                    Some(q) => {
+                        let mut q = q.lock().unwrap();
+                        let results: Vec<MmqpEntryResult> = entries
+                            .into_iter()
+                            .map(|(id, timeout)| {
+                                if q.change_visibility(&id, timeout) {
+                                    MmqpEntryResult::ok(id)
+                                } else {
+                                    MmqpEntryResult::error(MmqpResponseCode::MessageNotFound, id)
                                 }
+                            })
+                            .collect();
+                        drop(q);
+
+                        s.write_all(&serialise_batch_results(&results)).unwrap();
+                        s.flush().unwrap();
+                    }
+                    None => {
+                        tracing::warn!("queue not found");
+                        Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue);
+                    }
+                },
+                MmqpTcpFormat::Invalid(code, detail) => {
+                    tracing::warn!(code = code.code(), %detail, "rejected request");
+                    Application::write_response(s, code, &detail);
+                }
+                MmqpTcpFormat::Ping => {
+                    let success = s.write(
+                        b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=UTF-8\r\n\r\npong",
+                    );
+                    match success {
+                        Ok(_) => {
+                            let r = s.flush();
+                            if r.is_err() {
+                                tracing::warn!("could not flush the response");
                             }
-                            Err(_) => {}
-                        }
-                    }
-                    MmqpTcpFormat::Poll(queue, count) => {
-                        if self.queues.contains_key(&*queue) {
-                            let queue = self.queues.get_mut(queue.as_str()).unwrap();
-
-                            let mut c = count;
-                            let mut message = queue.read_next();
-                            let mut _message: Vec<u8> = (b"MMQP|0.1|M|").to_vec();
-                            while message.is_some() && c > 0 {
-                                let message_bytes = message.unwrap();
-                                _message.extend(
-                                    message_bytes
-                                        .serialise(SerialisationStrategy::Storage)
-                                        .into_vec(),
+                        }
+                        Err(_) => {}
+                    }
+                }
+                MmqpTcpFormat::Poll(queue, count) => {
+                    Application::poll(&queues, &queue, None, count, s);
+                }
+                MmqpTcpFormat::GroupPoll(queue, group, count) => {
+                    Application::poll(&queues, &queue, Some(&group), count, s);
+                }
+                MmqpTcpFormat::Browse(queue, from, count) => match queues.get(&queue) {
+                    Some(q) => {
+                        let page = q.lock().unwrap().browse(from, count as usize);
+                        if s.write_all(&serialise_browse_page(&page)).is_err() || s.flush().is_err()
+                        {
+                            tracing::warn!("could not write the browse page");
+                        }
+                    }
+                    None => Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue),
+                },
+                MmqpTcpFormat::Seek(queue, group, target) => match queues.get(&queue) {
+                    Some(q) => match q.lock().unwrap().seek(&group, target) {
+                        Some(offset) => Application::write_response(
+                            s,
+                            MmqpResponseCode::Ok,
+                            &offset.to_string(),
+                        ),
+                        None => Application::write_response(
+                            s,
+                            MmqpResponseCode::MalformedRequest,
+                            &format!("{} is not a log queue", queue),
+                        ),
+                    },
+                    None => Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue),
+                },
+            }
+
+            true
+        }
+
+        /// Topics and exchanges are saved to topic_config and exchange_config after every change
+        fn handle_admin(
+            queues: &QueueMap,
+            topics: &mut TopicMap,
+            topic_config: &str,
+            exchanges: &mut ExchangeMap,
+            exchange_config: &str,
+            command: MmqpAdminCommand,
+            s: &mut TcpStream,
+        ) {
+            match command {
+                MmqpAdminCommand::Purge(queue) => match queues.get(&queue) {
+                    Some(q) => {
+                        let purged = q.lock().unwrap().purge();
+                        Application::write_response(s, MmqpResponseCode::Ok, &purged.to_string());
+                    }
+                    None => {
+                        Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue);
+                    }
+                },
+                MmqpAdminCommand::CreateTopic(topic) => {
+                    topics
+                        .entry(topic.clone())
+                        .or_insert_with(|| TopicConfiguration::new(topic.clone()));
+                    Application::save_topics(topic_config, topics, &topic, s);
+                }
+                MmqpAdminCommand::DeleteTopic(topic) => match topics.remove(&topic) {
+                    Some(_) => Application::save_topics(topic_config, topics, &topic, s),
+                    None => Application::write_response(s, MmqpResponseCode::TopicNotFound, &topic),
+                },
+                MmqpAdminCommand::Subscribe(topic, queue, filter) => {
+                    if !queues.contains_key(&queue) {
+                        Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue);
+                        return;
+                    }
+                    match topics.get_mut(&topic) {
+                        Some(t) => {
+                            if let Err(e) = t.set_filter(&queue, filter.as_deref()) {
+                                Application::write_response(
+                                    s,
+                                    MmqpResponseCode::InvalidFilter,
+                                    &e.to_string(),
                                 );
+                                return;
+                            }
+                            t.subscribe(&queue);
+                            Application::save_topics(topic_config, topics, &queue, s);
+                        }
+                        None => {
+                            Application::write_response(s, MmqpResponseCode::TopicNotFound, &topic)
+                        }
+                    }
+                }
+                MmqpAdminCommand::Unsubscribe(topic, queue) => match topics.get_mut(&topic) {
+                    Some(t) => {
+                        t.unsubscribe(&queue);
+                        Application::save_topics(topic_config, topics, &queue, s);
+                    }
+                    None => Application::write_response(s, MmqpResponseCode::TopicNotFound, &topic),
+                },
+                MmqpAdminCommand::CreateExchange(exchange, exchange_type) => {
+                    exchanges.entry(exchange.clone()).or_insert_with(|| {
+                        ExchangeConfiguration::new(exchange.clone(), exchange_type)
+                    });
+                    Application::save_exchanges(exchange_config, exchanges, &exchange, s);
+                }
+                MmqpAdminCommand::DeleteExchange(exchange) => match exchanges.remove(&exchange) {
+                    Some(_) => {
+                        Application::save_exchanges(exchange_config, exchanges, &exchange, s)
+                    }
+                    None => Application::write_response(
+                        s,
+                        MmqpResponseCode::ExchangeNotFound,
+                        &exchange,
+                    ),
+                },
+                MmqpAdminCommand::Bind(exchange, queue, pattern) => {
+                    if !queues.contains_key(&queue) {
+                        Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue);
+                        return;
+                    }
+                    match exchanges.get_mut(&exchange) {
+                        Some(e) => {
+                            e.bind(&queue, &pattern);
+                            Application::save_exchanges(exchange_config, exchanges, &queue, s);
+                        }
+                        None => Application::write_response(
+                            s,
+                            MmqpResponseCode::ExchangeNotFound,
+                            &exchange,
+                        ),
+                    }
+                }
+                MmqpAdminCommand::Unbind(exchange, queue, pattern) => {
+                    match exchanges.get_mut(&exchange) {
+                        Some(e) => {
+                            e.unbind(&queue, &pattern);
+                            Application::save_exchanges(exchange_config, exchanges, &queue, s);
+                        }
+                        None => Application::write_response(
+                            s,
+                            MmqpResponseCode::ExchangeNotFound,
+                            &exchange,
+                        ),
+                    }
+                }
+                MmqpAdminCommand::DeleteConsumerGroup(queue, group) => match queues.get(&queue) {
+                    Some(q) => {
+                        if q.lock().unwrap().remove_consumer_group(&group) {
+                            Application::write_response(s, MmqpResponseCode::Ok, &group);
+                        } else {
+                            Application::write_response(
+                                s,
+                                MmqpResponseCode::ConsumerGroupNotFound,
+                                &group,
+                            );
+                        }
+                    }
+                    None => Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue),
+                },
+                MmqpAdminCommand::Move(source, destination, selection, keep_ids) => {
+                    Application::transfer_messages(
+                        queues,
+                        &source,
+                        &destination,
+                        &selection,
+                        false,
+                        keep_ids,
+                        s,
+                    );
+                }
+                MmqpAdminCommand::Copy(source, destination, selection, keep_ids) => {
+                    Application::transfer_messages(
+                        queues,
+                        &source,
+                        &destination,
+                        &selection,
+                        true,
+                        keep_ids,
+                        s,
+                    );
+                }
+                MmqpAdminCommand::Export(queue, format) => match queues.get(&queue) {
+                    Some(q) => Application::export_queue(q, format, s),
+                    None => Application::write_response(s, MmqpResponseCode::QueueNotFound, &queue),
+                },
+                MmqpAdminCommand::Import(_, _, _) | MmqpAdminCommand::Reload => {
+                    unreachable!("handled before the queues are locked for reading")
+                }
+                MmqpAdminCommand::UnknownExportFormat(format) => {
+                    Application::write_response(
+                        s,
+                        MmqpResponseCode::MalformedRequest,
+                        &format!("unknown export format {}", format),
+                    );
+                }
+                MmqpAdminCommand::Truncated(name) => {
+                    Application::write_response(
+                        s,
+                        MmqpResponseCode::MalformedRequest,
+                        &format!("{} request is incomplete", name),
+                    );
+                }
+                MmqpAdminCommand::UnknownExchangeType(exchange_type) => {
+                    Application::write_response(
+                        s,
+                        MmqpResponseCode::MalformedRequest,
+                        &format!("unknown exchange type {}", exchange_type),
+                    );
+                }
+                MmqpAdminCommand::Unknown(name) => {
+                    Application::write_response(s, MmqpResponseCode::UnknownCommand, &name);
+                }
+            }
+        }
+
+        /// Move or copy the selected waiting messages of source to the end of destination and respond with
+        /// how many were added, or which were rejected. Without keep_ids they are given new ids and
+        /// timestamps, as if sent again. Moved messages the destination has no room for stay in the source
+        fn transfer_messages(
+            queues: &QueueMap,
+            source: &str,
+            destination: &str,
+            selection: &MessageSelection,
+            copy: bool,
+            keep_ids: bool,
+            s: &mut TcpStream,
+        ) {
+            let selected = match selection.matcher() {
+                Ok(selected) => selected,
+                Err(e) => {
+                    Application::write_response(s, MmqpResponseCode::InvalidFilter, &e.to_string());
+                    return;
+                }
+            };
+            for name in [source, destination] {
+                if !queues.contains_key(name) {
+                    Application::write_response(s, MmqpResponseCode::QueueNotFound, name);
+                    return;
+                }
+            }
 
-                                c -= 1;
+            // the source is unlocked before the destination is locked, as in sweep
+            let (messages, blobs) = {
+                let mut queue = queues[source].lock().unwrap();
+                // taking messages out of the middle of a log would move every consumer group's position
+                if !copy && queue.queue_type() == QueueType::Log {
+                    Application::write_response(
+                        s,
+                        MmqpResponseCode::MalformedRequest,
+                        &format!("messages cannot be moved out of log queue {}", source),
+                    );
+                    return;
+                }
 
-                                if c > 0 {
-                                    message = queue.read_next();
-                                } else {
-                                    message = None;
-                                }
+                let messages = if copy {
+                    queue.copy_messages(|message| selected(message))
+                } else {
+                    queue.retain_messages(|message| !selected(message))
+                };
+                (messages, queue.blob_store().cloned())
+            };
+
+            let current_time_ms = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis();
+            // the id, receive time and receive count a message had in the source, put back if the destination
+            // has no room for it
+            type SourceIdentity = ([u8; 64], u128, u32);
+            let mut prepared: Vec<
+                Result<(SourceIdentity, MmqpNormalisedMessage), MmqpEntryResult>,
+            > = vec![];
+            for mut message in messages.into_iter() {
+                // a copy needs its own body, the source still releases the original
+                if copy {
+                    if let Some(Err(e)) = blobs.as_ref().map(|blobs| blobs.duplicate(&mut message))
+                    {
+                        tracing::error!(
+                            message_id = %message.message_id_string(),
+                            error = %e,
+                            "could not copy the body of a message, skipping it"
+                        );
+                        prepared.push(Err(MmqpEntryResult {
+                            code: MmqpResponseCode::StorageError,
+                            detail: message.message_id_string(),
+                        }));
+                        continue;
+                    }
+                }
+                let original = (
+                    message.message_id,
+                    message.received_time,
+                    message.receive_count,
+                );
+                if !keep_ids {
+                    message.message_id = MmqpNormalisedMessage::generate_message_id();
+                    message.received_time = current_time_ms;
+                    message.receive_count = 0;
+                }
+                prepared.push(Ok((original, message)));
+            }
+
+            let mut rejected: Vec<MmqpNormalisedMessage> = vec![];
+            let results: Vec<MmqpEntryResult> = {
+                let mut destination = queues[destination].lock().unwrap();
+                prepared
+                    .into_iter()
+                    .map(|prepared| {
+                        let (original, message) = match prepared {
+                            Ok(prepared) => prepared,
+                            Err(result) => return result,
+                        };
+                        let message_id = message.message_id_string();
+                        match destination.receive_normalised(message) {
+                            Ok(()) => MmqpEntryResult::ok(message_id),
+                            Err((code, mut message)) => {
+                                (
+                                    message.message_id,
+                                    message.received_time,
+                                    message.receive_count,
+                                ) = original;
+                                let result = MmqpEntryResult {
+                                    code,
+                                    detail: message.message_id_string(),
+                                };
+                                rejected.push(*message);
+                                result
                             }
+                        }
+                    })
+                    .collect()
+            };
 
-                            _message.push(0x00);
-                            s.write_all(&_message).unwrap();
-                            s.flush().unwrap();
-                        } else {
-                            println!("Queue not found: {}", queue);
+            // a rejected copy drops its own body, a rejected move goes back to the source as it was
+            if !rejected.is_empty() {
+                let mut source = queues[source].lock().unwrap();
+                for message in rejected.into_iter() {
+                    if copy {
+                        source.release_blob(&message);
+                        continue;
+                    }
+                    let state = if message.available_time > current_time_ms {
+                        BrowseState::Delayed
+                    } else {
+                        BrowseState::Ready
+                    };
+                    let message_id = message.message_id_string();
+                    if let Err(code) = source.import_message(state, message) {
+                        tracing::error!(
+                            %message_id,
+                            code = code.code(),
+                            "could not return a message to the source queue, it is lost"
+                        );
+                    }
+                }
+            }
+            Application::write_entry_results(s, &results);
+        }
 
-                            // let mut _message:Vec<u8> = (b"MMQP|0.1|R|QUEUE_NOT_FOUND|").to_vec();
-                            //
-                            // _message.extend( queue.to_string().into_bytes());
-                            // _message.push( 0x00);
+        /// Respond with every message of the queue and its configuration in the export format. Offloaded
+        /// bodies are read back so the export does not depend on this server's blob directory
+        fn export_queue(queue: &Mutex<Queue>, format: ExportFormat, s: &mut TcpStream) {
+            let (configuration, messages, blobs) = {
+                let queue = queue.lock().unwrap();
+                (
+                    queue.configuration(),
+                    queue.export_messages(),
+                    queue.blob_store().cloned(),
+                )
+            };
 
-                            s.write_all(
-                                format!("MMQP|0.1|R|QUEUE_NOT_FOUND|{}|{}", queue, 0x00).as_bytes(),
-                            )
-                            .unwrap();
-                            s.flush().unwrap();
+            let mut export = QueueExport {
+                configuration,
+                messages,
+            };
+            if let Some(blobs) = blobs.as_ref() {
+                for (_, message) in export.messages.iter_mut() {
+                    if let Err(e) = blobs.load(message) {
+                        tracing::error!(
+                            message_id = %message.message_id_string(),
+                            error = %e,
+                            "could not read the body of a message"
+                        );
+                        Application::write_response(
+                            s,
+                            MmqpResponseCode::StorageError,
+                            &export.configuration.name,
+                        );
+                        return;
+                    }
+                }
+            }
+
+            let response = serialise_export_response(&serialise_export(&export, format));
+            if s.write_all(&response).is_err() || s.flush().is_err() {
+                tracing::warn!(queue = %export.configuration.name, "could not send the export");
+            }
+        }
+
+        /// Add the messages of an export to a queue, creating it from the exported configuration if it does
+        /// not exist, and respond with how many were added or which were rejected
+        fn import_queue(
+            &self,
+            queue: String,
+            format: ExportFormat,
+            data: &[u8],
+            s: &mut TcpStream,
+        ) {
+            let export = match deserialise_export(data, format) {
+                Ok(export) => export,
+                Err(e) => {
+                    Application::write_response(s, MmqpResponseCode::MalformedRequest, &e);
+                    return;
+                }
+            };
+            let name = if queue.is_empty() {
+                export.configuration.name.clone()
+            } else {
+                queue
+            };
+
+            let mut queues = self.queues.write().unwrap();
+            if !queues.contains_key(&name) {
+                let mut configuration = export.configuration;
+                configuration.name = name.clone();
+
+                // the new queue has to fit in with the others, eg its dead letter queue must exist here too
+                let mut configurations: Vec<QueueConfiguration> = queues
+                    .values()
+                    .map(|queue| queue.lock().unwrap().configuration())
+                    .collect();
+                configurations.push(configuration.clone());
+                let errors: Vec<ConfigError> = validate_configurations(&configurations)
+                    .into_iter()
+                    .filter(|e| {
+                        e.path
+                            .starts_with(&format!("[{}]", configurations.len() - 1))
+                    })
+                    .collect();
+                if !errors.is_empty() {
+                    let errors: Vec<String> = errors.iter().map(|e| e.message.clone()).collect();
+                    Application::write_response(
+                        s,
+                        MmqpResponseCode::MalformedRequest,
+                        &format!("configuration: {}", errors.join("; ")),
+                    );
+                    return;
+                }
+
+                let mut created = Queue::new(configuration);
+                if let Some(blobs) = self.blobs.as_ref() {
+                    created.set_blob_store(blobs.clone());
+                }
+                queues.insert(name.clone(), Mutex::new(created));
+
+                if let Err(e) = self.save_queue_config(&queues) {
+                    tracing::error!(file = %self.queue_config, error = %e, "could not write the queue config");
+                    queues.remove(&name);
+                    Application::write_response(s, MmqpResponseCode::StorageError, &name);
+                    return;
+                }
+            }
+
+            let mut queue = queues[&name].lock().unwrap();
+            let results: Vec<MmqpEntryResult> = export
+                .messages
+                .into_iter()
+                .map(|(state, message)| {
+                    let message_id = message.message_id_string();
+                    match queue.import_message(state, message) {
+                        Ok(()) => MmqpEntryResult::ok(message_id),
+                        Err(code) => MmqpEntryResult {
+                            code,
+                            detail: message_id,
+                        },
+                    }
+                })
+                .collect();
+            Application::write_entry_results(s, &results);
+        }
+
+        /// OK with the number of entries when they all succeeded, otherwise a BATCH frame with the result of
+        /// each entry so the client can tell which were rejected
+        fn write_entry_results(s: &mut TcpStream, results: &[MmqpEntryResult]) {
+            let rejected = results
+                .iter()
+                .filter(|result| result.code != MmqpResponseCode::Ok)
+                .count();
+            if rejected == 0 {
+                Application::write_response(s, MmqpResponseCode::Ok, &results.len().to_string());
+                return;
+            }
+
+            tracing::warn!(rejected, "entries were rejected");
+            if s.write_all(&serialise_batch_results(results)).is_err() || s.flush().is_err() {
+                tracing::warn!("could not write the response");
+            }
+        }
+
+        /// Create queue from the auto create template if it does not exist and username may create it. The
+        /// queue is added to the queue config file so it survives a restart or reload
+        fn auto_create_queue(&self, queue: &str, username: &str) {
+            let auto_create = match self.auto_create.as_ref() {
+                Some(auto_create) => auto_create,
+                None => return,
+            };
+            if self.queues.read().unwrap().contains_key(queue)
+                || !auto_create.allows(queue, username)
+            {
+                return;
+            }
+
+            let mut queues = self.queues.write().unwrap();
+            if queues.contains_key(queue) {
+                return;
+            }
+            let mut created = Queue::new(auto_create.configuration(queue));
+            if let Some(blobs) = self.blobs.as_ref() {
+                created.set_blob_store(blobs.clone());
+            }
+            queues.insert(queue.to_string(), Mutex::new(created));
+            tracing::info!(queue, user = username, "created queue");
+
+            // the queue still works for now, but a reload would retire it
+            if let Err(e) = self.save_queue_config(&queues) {
+                tracing::error!(file = %self.queue_config, error = %e, "could not write the queue config");
+            }
+        }
+
+        /// Write the configuration of every queue to the queue config file, sorted by name
+        fn save_queue_config(&self, queues: &QueueMap) -> std::io::Result<()> {
+            let mut configurations: Vec<QueueConfiguration> = queues
+                .values()
+                .map(|queue| queue.lock().unwrap().configuration())
+                .collect();
+            configurations.sort_by(|a, b| a.name.cmp(&b.name));
+            std::fs::write(
+                &self.queue_config,
+                Queue::serialise_config_array(&configurations),
+            )
+        }
+
+        /// Read the queue config file again and apply it to the running queues: new queues are created,
+        /// existing ones are reconfigured and queues that are no longer listed are retired. Responds with a
+        /// summary, or the code and reason the file could not be used
+        fn reload(
+            queues: &RwLock<QueueMap>,
+            queue_config: &str,
+            blobs: Option<&BlobStore>,
+        ) -> Result<String, (MmqpResponseCode, String)> {
+            let contents = std::fs::read_to_string(queue_config).map_err(|e| {
+                (
+                    MmqpResponseCode::StorageError,
+                    format!("could not read {}: {}", queue_config, e),
+                )
+            })?;
+            let configurations = Queue::deserialise_config_array(contents)
+                .map_err(|errors| {
+                    (
+                        MmqpResponseCode::MalformedRequest,
+                        Application::describe_config_errors(queue_config, &errors),
+                    )
+                })?
+                .into_vec();
+
+            let listed: HashSet<String> = configurations
+                .iter()
+                .map(|config| config.name.clone())
+                .collect();
+            let (mut created, mut updated, mut retired) = (0, 0, 0);
+            let mut queues = queues.write().unwrap();
+
+            for config in configurations.into_iter() {
+                match queues.get(&config.name) {
+                    Some(queue) => {
+                        let mut queue = queue.lock().unwrap();
+                        queue.set_retired(false);
+                        let fixed = queue.reconfigure(config);
+                        if !fixed.is_empty() {
+                            tracing::warn!(
+                                queue = %queue.name(),
+                                settings = %fixed.join(", "),
+                                "settings cannot change while the server is running"
+                            );
                         }
+                        updated += 1;
+                    }
+                    None => {
+                        let mut queue = Queue::new(config);
+                        if let Some(blobs) = blobs {
+                            queue.set_blob_store(blobs.clone());
+                        }
+                        queues.insert(queue.name(), Mutex::new(queue));
+                        created += 1;
                     }
                 }
+            }
 
-                let end = SystemTime::now();
-                let duration = end.duration_since(start).unwrap();
-                println!("request took {}", duration.as_secs());
+            // removed queues keep their messages until they are consumed, see drop_retired
+            for (name, queue) in queues.iter() {
+                let mut queue = queue.lock().unwrap();
+                if !listed.contains(name) && !queue.is_retired() {
+                    tracing::info!(queue = %name, "queue is no longer configured, retiring it");
+                    queue.set_retired(true);
+                    retired += 1;
+                }
             }
+
+            Ok(format!(
+                "{} created, {} updated, {} retired",
+                created, updated, retired
+            ))
         }
 
-        /// Read the stream data and return stream data & its length
-        fn read_stream(stream: &mut TcpStream) -> (Vec<u8>, usize) {
-            let mut request_buffer: Vec<u8> = vec![];
-            // let us loop & try to read the whole request data
-            let mut request_len = 0usize;
+        /// Reload the queue config file whenever the process receives SIGHUP
+        #[cfg(unix)]
+        fn start_reload_on_hangup(&self) {
+            let queues = Arc::clone(&self.queues);
+            let queue_config = self.queue_config.clone();
+            let blobs = self.blobs.clone();
 
-            // fixed size temporary read buffer. its data is replaced each time  after copying it into the vec
-            // if the amount read in is less than the buffer size, then we are done
-            // calculated by current read position (n) mod buffer size (M)
-            //
-            const M: usize = 512usize;
-            loop {
-                let mut buffer = [0; M];
-                match stream.read(&mut buffer) {
-                    Ok(n) => {
-                        dbg!(n);
-                        if n == 0 {
-                            break;
-                        } else {
-                            request_len += n;
-                            request_buffer.extend(&buffer[..n]);
+            let mut signals = match signal_hook::iterator::Signals::new([
+                signal_hook::consts::SIGHUP,
+            ]) {
+                Ok(signals) => signals,
+                Err(e) => {
+                    tracing::warn!(error = %e, "could not listen for SIGHUP, use the RELOAD command");
+                    return;
+                }
+            };
+            thread::spawn(move || {
+                for _ in signals.forever() {
+                    match Application::reload(&queues, &queue_config, blobs.as_ref()) {
+                        Ok(summary) => tracing::info!(%summary, "reloaded {}", queue_config),
+                        Err((_, e)) => tracing::error!(error = %e, "could not reload"),
+                    }
+                }
+            });
+        }
+
+        fn load_topics(filepath: &str) -> TopicMap {
+            let contents = match std::fs::read_to_string(filepath) {
+                Ok(contents) => contents,
+                Err(_) => return TopicMap::new(),
+            };
+
+            match deserialise_topics(&contents) {
+                Ok(topics) => topics
+                    .into_vec()
+                    .into_iter()
+                    .map(|topic| (topic.name.clone(), topic))
+                    .collect(),
+                Err(e) => {
+                    tracing::error!(file = filepath, error = %e, "could not read the topics");
+                    TopicMap::new()
+                }
+            }
+        }
+
+        fn save_topics(filepath: &str, topics: &TopicMap, detail: &str, s: &mut TcpStream) {
+            let topics: Vec<TopicConfiguration> = topics.values().cloned().collect();
+            Application::save_config(filepath, serialise_topics(&topics), detail, s);
+        }
+
+        fn load_exchanges(filepath: &str) -> ExchangeMap {
+            let contents = match std::fs::read_to_string(filepath) {
+                Ok(contents) => contents,
+                Err(_) => return ExchangeMap::new(),
+            };
+
+            match deserialise_exchanges(&contents) {
+                Ok(exchanges) => exchanges
+                    .into_vec()
+                    .into_iter()
+                    .map(|exchange| (exchange.name.clone(), exchange))
+                    .collect(),
+                Err(e) => {
+                    tracing::error!(file = filepath, error = %e, "could not read the exchanges");
+                    ExchangeMap::new()
+                }
+            }
+        }
+
+        fn save_exchanges(
+            filepath: &str,
+            exchanges: &ExchangeMap,
+            detail: &str,
+            s: &mut TcpStream,
+        ) {
+            let exchanges: Vec<ExchangeConfiguration> = exchanges.values().cloned().collect();
+            Application::save_config(filepath, serialise_exchanges(&exchanges), detail, s);
+        }
+
+        /// Persist a config file after an admin change and respond with OK and detail,
+        /// or STORAGE_ERROR if the file could not be written
+        fn save_config(filepath: &str, contents: String, detail: &str, s: &mut TcpStream) {
+            match std::fs::write(filepath, contents) {
+                Ok(_) => Application::write_response(s, MmqpResponseCode::Ok, detail),
+                Err(e) => {
+                    tracing::error!(file = filepath, error = %e, "could not write");
+                    Application::write_response(s, MmqpResponseCode::StorageError, detail);
+                }
+            }
+        }
+
+        /// Periodically remove expired messages from every queue, moving them to the queue's
+        /// dead letter queue when one is configured
+        fn start_sweeper(&self) {
+            let queues = Arc::clone(&self.queues);
 
-                            if n % M != 0 {
-                                println!("should break here?");
-                                break;
+            thread::spawn(move || loop {
+                thread::sleep(Duration::from_millis(SWEEP_INTERVAL_MS));
+                Application::sweep(&queues.read().unwrap());
+                Application::drop_retired(&queues);
+            });
+        }
+
+        /// Remove retired queues that have no messages left
+        fn drop_retired(queues: &RwLock<QueueMap>) {
+            let drained = |queue: &Mutex<Queue>| {
+                let queue = queue.lock().unwrap();
+                queue.is_retired() && queue.is_empty()
+            };
+
+            // most sweeps find nothing, so the write lock is only taken when there is a queue to remove
+            if !queues.read().unwrap().values().any(drained) {
+                return;
+            }
+
+            queues.write().unwrap().retain(|name, queue| {
+                if drained(queue) {
+                    tracing::info!(queue = %name, "retired queue is empty, removing it");
+                    return false;
+                }
+                true
+            });
+        }
+
+        fn sweep(queues: &QueueMap) {
+            for (name, queue) in queues.iter() {
+                // the source queue is unlocked before the dead letter queue is locked, two queues
+                // could be each other's dead letter queue
+                let (expired, dead_letter_queue, blobs) = {
+                    let mut queue = queue.lock().unwrap();
+                    (
+                        queue.expire_messages(),
+                        queue.dead_letter_queue(),
+                        queue.blob_store().cloned(),
+                    )
+                };
+
+                if expired.is_empty() {
+                    continue;
+                }
+                tracing::info!(queue = %name, count = expired.len(), "expired messages");
+
+                match dead_letter_queue.as_ref().and_then(|dlq| queues.get(dlq)) {
+                    Some(dlq) => {
+                        let mut dlq = dlq.lock().unwrap();
+                        for message in expired.into_iter() {
+                            // expired messages cannot go back, one the dead letter queue turns away is lost
+                            if let Err((code, message)) = dlq.receive_normalised(message) {
+                                tracing::warn!(
+                                    queue = %name,
+                                    dead_letter_queue = %dlq.name(),
+                                    message_id = %message.message_id_string(),
+                                    code = code.code(),
+                                    "dead letter queue rejected an expired message, dropping it"
+                                );
+                                if let Some(blobs) = blobs.as_ref() {
+                                    blobs.release(&message);
+                                }
+                            }
+                        }
+                    }
+                    None => {
+                        if let Some(dlq) = dead_letter_queue {
+                            tracing::warn!(queue = %name, dead_letter_queue = %dlq, "dead letter queue not found, dropping messages");
+                        }
+                        if let Some(blobs) = blobs {
+                            for message in expired.iter() {
+                                blobs.release(message);
                             }
+                        }
+                    }
+                }
+            }
+        }
+
+        /// Read up to count messages from a queue and write them back. With a consumer group, a Log queue
+        /// reads from that group's position (see Queue::read_group)
+        fn poll(queues: &QueueMap, queue: &str, group: Option<&str>, count: u8, s: &mut TcpStream) {
+            if queues.contains_key(queue) {
+                let mut queue = queues[queue].lock().unwrap();
+
+                let mut messages: Vec<MmqpNormalisedMessage> = vec![];
+                while messages.len() < count as usize {
+                    let message = match group {
+                        Some(group) => queue.read_group(group),
+                        None => queue.read_next(),
+                    };
+                    match message {
+                        Some(message) => messages.push(message),
+                        None => break,
+                    }
+                }
+                let blobs = queue.blob_store().cloned();
+                // offloaded bodies are copied from disk, the queue does not need to be held for that
+                drop(queue);
 
-                            // we need not read more data in case we have read less data than buffer size
+                if Application::write_messages(s, &messages, blobs.as_ref()).is_err() {
+                    tracing::warn!("could not write the messages");
+                }
+            } else {
+                tracing::warn!("queue not found");
+
+                // let mut _message:Vec<u8> = (b"MMQP|0.1|R|QUEUE_NOT_FOUND|").to_vec();
+                //
+                // _message.extend( queue.to_string().into_bytes());
+                // _message.push( 0x00);
+
+                s.write_all(format!("MMQP|0.1|R|QUEUE_NOT_FOUND|{}|{}", queue, 0x00).as_bytes())
+                    .unwrap();
+                s.flush().unwrap();
+            }
+        }
+
+        /// Write an M frame with the polled messages. Offloaded bodies are streamed from the blob store,
+        /// a message whose body cannot be opened is left out rather than breaking the frame
+        fn write_messages(
+            s: &mut TcpStream,
+            messages: &[MmqpNormalisedMessage],
+            blobs: Option<&BlobStore>,
+        ) -> std::io::Result<()> {
+            s.write_all(b"MMQP|0.1|M|")?;
+            for message in messages.iter() {
+                if !message.offloaded {
+                    s.write_all(&message.serialise(SerialisationStrategy::Storage))?;
+                    continue;
+                }
+
+                match blobs.map(|blobs| blobs.open(message)) {
+                    Some(Ok((mut body, len))) => message.write_with_body(&mut body, len, s)?,
+                    _ => tracing::error!(
+                        message_id = %message.message_id_string(),
+                        "the body of a message is missing, skipping it"
+                    ),
+                }
+            }
+            s.write_all(&[0x00])?;
+            s.flush()
+        }
+
+        /// Write a single R frame with a status code and a detail (queue name, message id etc)
+        fn write_response(s: &mut TcpStream, code: MmqpResponseCode, detail: &str) {
+            let r =
+                s.write_all(format!("MMQP|0.1|R|{}|{}|{}", code.code(), detail, 0x00).as_bytes());
+            if r.is_err() || s.flush().is_err() {
+                tracing::warn!("could not write the response");
+            }
+        }
+
+        /// Place every message of a batch, locking each target queue once.
+        /// Results are returned in the same order as the messages were sent
+        fn receive_batch(queues: &QueueMap, messages: Vec<MmqpMessage>) -> Vec<MmqpEntryResult> {
+            let mut results: Vec<Option<MmqpEntryResult>> = vec![None; messages.len()];
+
+            // queue name -> (position in the batch, message)
+            let mut by_queue: HashMap<String, Vec<(usize, MmqpMessage)>> = HashMap::new();
+            for (index, message) in messages.into_iter().enumerate() {
+                by_queue
+                    .entry(message.target_queue.clone())
+                    .or_default()
+                    .push((index, message));
+            }
+
+            for (queue_name, entries) in by_queue.into_iter() {
+                match queues.get(&queue_name) {
+                    Some(queue) => {
+                        let mut queue = queue.lock().unwrap();
+                        for (index, message) in entries.into_iter() {
+                            results[index] = Some(match queue.receive_message(message) {
+                                Ok(message_id) => MmqpEntryResult::ok(message_id),
+                                Err(code) => MmqpEntryResult::error(code, queue_name.clone()),
+                            });
                         }
                     }
+                    None => {
+                        tracing::warn!(queue = %queue_name, "queue not found");
+                        for (index, _) in entries.into_iter() {
+                            results[index] = Some(MmqpEntryResult::error(
+                                MmqpResponseCode::QueueNotFound,
+                                queue_name.clone(),
+                            ));
+                        }
+                    }
+                }
+            }
+
+            results.into_iter().map(|r| r.unwrap()).collect()
+        }
 
-                    _ => {
-                        println!("error reading stream");
-                        return (Vec::new(), 0);
+        /// Read one request and parse it as it arrives. The length of a body is checked as soon as it has been
+        /// read, so a request that is too large is answered without reading the rest. None if the connection
+        /// closed without sending anything
+        fn read_request(&self, stream: &mut TcpStream) -> Option<(Vec<u8>, MmqpTcpFormat)> {
+            if let Err(e) =
+                stream.set_read_timeout(Some(Duration::from_millis(REQUEST_READ_TIMEOUT_MS)))
+            {
+                tracing::warn!(error = %e, "could not set the read timeout");
+            }
+
+            // sends to a queue are held to its own limit when it is lower. Each queue is only looked up once
+            let limits: RefCell<HashMap<String, Option<usize>>> = RefCell::new(HashMap::new());
+            let queue_limit = |name: &str| {
+                if let Some(max) = limits.borrow().get(name) {
+                    return *max;
+                }
+                let max = self
+                    .queues
+                    .read()
+                    .unwrap()
+                    .get(name)
+                    .and_then(|queue| queue.lock().unwrap().max_message_size());
+                limits.borrow_mut().insert(name.to_string(), max);
+                max
+            };
+            let mut scanner = RequestScanner::new(self.max_message_size);
+
+            let mut request: Vec<u8> = vec![];
+            let mut buffer = [0u8; 8192];
+            loop {
+                let n = match stream.read(&mut buffer) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        if request.is_empty() {
+                            tracing::warn!(error = %e, "could not read the request");
+                            return None;
+                        }
+                        // timed out or reset part way through, the request can never be completed
+                        tracing::warn!(error = %e, bytes = request.len(), "request was not finished");
+                        0
                     }
+                };
+                tracing::trace!(bytes = n, "read");
+
+                if n == 0 {
+                    if request.is_empty() {
+                        return None;
+                    }
+                    // parsing the incomplete request gives MALFORMED_REQUEST
+                    let parsed = lib::tcp_parse::tcp_parse::parse_tcp_request_with_limits(
+                        request.clone(),
+                        self.max_message_size,
+                        &queue_limit,
+                    );
+                    return Some((request, parsed));
+                }
+
+                if request.len() + n > self.max_request_size {
+                    let detail = format!("request > {}", self.max_request_size);
+                    return Some((
+                        request,
+                        MmqpTcpFormat::Invalid(MmqpResponseCode::MessageTooLarge, detail),
+                    ));
                 }
+                request.extend_from_slice(&buffer[..n]);
 
-                println!("{}", request_len);
-                // dbg!(request_buffer.clone());
+                // parsed once the scanner has found the end of it, rather than again after every read
+                match scanner.scan(&request, &queue_limit) {
+                    ScanResult::More => continue,
+                    ScanResult::Reject(code, detail) => {
+                        return Some((request, MmqpTcpFormat::Invalid(code, detail)))
+                    }
+                    ScanResult::Parse => {}
+                }
+                if let Some(parsed) = lib::tcp_parse::tcp_parse::parse_partial_request(
+                    &request,
+                    self.max_message_size,
+                    &queue_limit,
+                ) {
+                    return Some((request, parsed));
+                }
+            }
+        }
+
+        // closing a connection with bytes left unread resets it, and the client can lose the response. That
+        // is the 0x00 after most requests, or the rest of one answered before it was read. It is discarded
+        // until the client closes, up to max_request_size
+        fn close(&self, stream: &mut TcpStream) {
+            let _ = stream.shutdown(Shutdown::Write);
+            let mut buffer = [0u8; 8192];
+            let mut discarded = 0usize;
+            while discarded <= self.max_request_size {
+                match stream.read(&mut buffer) {
+                    Ok(n) if n > 0 => discarded += n,
+                    _ => break,
+                }
             }
-            (request_buffer, request_len)
         }
     }
 }
@@ -1,9 +1,39 @@
+use crate::config::server_config::{LogFormat, ServerConfig, USAGE};
 use crate::mackley::application::Application;
 
+mod config;
 mod mackley;
 
 fn main() {
-    let mut application = Application::new(8787, None, None);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let config = match ServerConfig::load(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            println!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    // the level and format come from the config, so nothing above this can be logged through tracing
+    let subscriber = tracing_subscriber::fmt().with_max_level(config.log_level.level());
+    match config.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+
+    let mut application = match Application::new(config) {
+        Ok(application) => application,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
     application.listen();
 }